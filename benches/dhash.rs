@@ -0,0 +1,523 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use fast_dhash::{hash_many, Dhash, DhashBuilder, DhashHasher, DhashVPTree, ImageRef};
+use image::{DynamicImage, GrayImage, RgbImage};
+use rayon::prelude::*;
+use std::thread;
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+
+fn rgb_image() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        image::Rgb([
+            (x * 255 / WIDTH) as u8,
+            (y * 255 / HEIGHT) as u8,
+            ((x ^ y) & 0xff) as u8,
+        ])
+    }))
+}
+
+fn grayscale_image() -> DynamicImage {
+    DynamicImage::ImageLuma8(GrayImage::from_fn(WIDTH, HEIGHT, |x, y| {
+        image::Luma([((x + y) * 255 / (WIDTH + HEIGHT)) as u8])
+    }))
+}
+
+fn hashes(n: usize) -> Vec<Dhash> {
+    let mut state = 0x9e3779b97f4a7c15u64;
+
+    (0..n)
+        .map(|_| {
+            // NOTE: xorshift, good enough to spread the bits
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            Dhash { hash: state }
+        })
+        .collect()
+}
+
+/// Single-threaded reference of the grid reduction performed by `Dhash::new`
+fn dhash_single_threaded(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Dhash {
+    let width = width as usize;
+    let channel_count = channel_count as usize;
+    let cell_width = width / 9;
+    let cell_height = height as usize / 8;
+
+    let mut grid = [[0f64; 9]; 8];
+
+    for (y, row) in grid.iter_mut().enumerate() {
+        for (x, cell) in row.iter_mut().enumerate() {
+            for image_y in y * cell_height..(y + 1) * cell_height {
+                for image_x in x * cell_width..(x + 1) * cell_width {
+                    let i = (image_y * width + image_x) * channel_count;
+
+                    *cell += if channel_count >= 3 {
+                        bytes[i] as f64 * 0.299
+                            + bytes[i + 1] as f64 * 0.587
+                            + bytes[i + 2] as f64 * 0.114
+                    } else {
+                        bytes[i] as f64
+                    };
+                }
+            }
+        }
+    }
+
+    let mut hash = 0u64;
+
+    for (y, row) in grid.iter().enumerate() {
+        for x in 0..8 {
+            if row[x] > row[x + 1] {
+                hash |= 1 << (y * 8 + x);
+            }
+        }
+    }
+
+    Dhash { hash }
+}
+
+fn pairwise_single_threaded(hashes: &[Dhash]) -> u64 {
+    let mut total = 0u64;
+
+    for (i, a) in hashes.iter().enumerate() {
+        for b in &hashes[i + 1..] {
+            total += a.hamming_distance(b) as u64;
+        }
+    }
+
+    total
+}
+
+fn pairwise_threaded(hashes: &[Dhash]) -> u64 {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = hashes.len().div_ceil(threads);
+
+    thread::scope(|s| {
+        let handles = (0..threads)
+            .map(|t| {
+                s.spawn(move || {
+                    let mut total = 0u64;
+
+                    for i in (t * chunk..(t + 1) * chunk).take_while(|&i| i < hashes.len()) {
+                        for b in &hashes[i + 1..] {
+                            total += hashes[i].hamming_distance(b) as u64;
+                        }
+                    }
+
+                    total
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+fn bench_new(c: &mut Criterion) {
+    for (name, image) in [
+        ("rgb_1080p", rgb_image()),
+        ("grayscale_1080p", grayscale_image()),
+    ] {
+        let bytes = image.as_bytes();
+        let channel_count = image.color().channel_count();
+
+        c.benchmark_group("baseline").bench_with_input(
+            BenchmarkId::new("new", name),
+            bytes,
+            |b, bytes| {
+                b.iter(|| dhash_single_threaded(black_box(bytes), WIDTH, HEIGHT, channel_count))
+            },
+        );
+
+        c.benchmark_group("threaded").bench_with_input(
+            BenchmarkId::new("new", name),
+            bytes,
+            |b, bytes| b.iter(|| Dhash::new(black_box(bytes), WIDTH, HEIGHT, channel_count)),
+        );
+
+        c.benchmark_group("threaded").bench_with_input(
+            BenchmarkId::new("new_assume_grayscale", name),
+            bytes,
+            |b, bytes| {
+                b.iter(|| {
+                    Dhash::new_assume_grayscale(black_box(bytes), WIDTH, HEIGHT, channel_count)
+                })
+            },
+        );
+    }
+}
+
+fn bench_threads(c: &mut Criterion) {
+    let image = rgb_image();
+    let bytes = image.as_bytes();
+
+    for threads in [1, 2, 8] {
+        let builder = DhashBuilder::new().threads(threads);
+
+        c.benchmark_group("threads").bench_with_input(
+            BenchmarkId::new("rgb_1080p", threads),
+            bytes,
+            |b, bytes| b.iter(|| builder.build(black_box(bytes), WIDTH, HEIGHT, 3)),
+        );
+    }
+}
+
+/// 4K frames, where the order the pixels are read in matters the most.
+fn bench_4k(c: &mut Criterion) {
+    let (width, height) = (3840u32, 2160u32);
+
+    let rgb = RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x & 0xff) as u8, (y & 0xff) as u8, ((x ^ y) & 0xff) as u8])
+    })
+    .into_raw();
+    let grayscale =
+        GrayImage::from_fn(width, height, |x, y| image::Luma([((x ^ y) & 0xff) as u8])).into_raw();
+
+    for (name, bytes, channel_count) in [("rgb", &rgb, 3), ("grayscale", &grayscale, 1)] {
+        for threads in [1, 8] {
+            let builder = DhashBuilder::new().threads(threads);
+
+            c.benchmark_group("4k").sample_size(20).bench_with_input(
+                BenchmarkId::new(name, threads),
+                bytes,
+                |b, bytes| b.iter(|| builder.build(black_box(bytes), width, height, channel_count)),
+            );
+        }
+
+        for shift in [1, 3] {
+            c.benchmark_group("4k").sample_size(20).bench_with_input(
+                BenchmarkId::new(format!("{}_downsample_pow2", name), shift),
+                bytes,
+                |b, bytes| {
+                    b.iter(|| {
+                        Dhash::new_downsample_pow2(
+                            black_box(bytes),
+                            width,
+                            height,
+                            channel_count,
+                            shift,
+                        )
+                    })
+                },
+            );
+        }
+    }
+}
+
+/// Rows of the grid per thread, on a thumbnail where spawning dominates.
+fn bench_chunk_size(c: &mut Criterion) {
+    let bytes = RgbImage::from_fn(32, 32, |x, y| {
+        image::Rgb([(x * 8) as u8, (y * 8) as u8, ((x ^ y) * 8) as u8])
+    })
+    .into_raw();
+
+    for rows_per_thread in [1, 2, 4, 8] {
+        let builder = DhashBuilder::new().chunk_size(rows_per_thread);
+
+        c.benchmark_group("chunk_size").bench_with_input(
+            BenchmarkId::new("rgb_32x32", rows_per_thread),
+            &bytes,
+            |b, bytes| b.iter(|| builder.build(black_box(bytes), 32, 32, 3)),
+        );
+    }
+}
+
+/// The calling thread against a thread per row, either side of
+/// `PARALLEL_THRESHOLD` pixels, the crossover is the size from which
+/// spawning wins.
+fn bench_parallel_threshold(c: &mut Criterion) {
+    let inline = DhashBuilder::new().parallel_threshold(usize::MAX);
+    let spawning = DhashBuilder::new().parallel_threshold(0);
+
+    for side in [64u32, 128, 256, 316, 512, 1024] {
+        let bytes = RgbImage::from_fn(side, side, |x, y| {
+            image::Rgb([(x & 0xff) as u8, (y & 0xff) as u8, ((x ^ y) & 0xff) as u8])
+        })
+        .into_raw();
+
+        let name = format!("{}x{}", side, side);
+
+        c.benchmark_group("parallel_threshold").bench_with_input(
+            BenchmarkId::new("inline", &name),
+            &bytes,
+            |b, bytes| b.iter(|| inline.build(black_box(bytes), side, side, 3)),
+        );
+
+        c.benchmark_group("parallel_threshold").bench_with_input(
+            BenchmarkId::new("spawning", &name),
+            &bytes,
+            |b, bytes| b.iter(|| spawning.build(black_box(bytes), side, side, 3)),
+        );
+    }
+}
+
+/// Long-lived workers against threads spawned for each hash.
+fn bench_hasher(c: &mut Criterion) {
+    let hasher = DhashHasher::new(8);
+
+    for side in [512u32, 1024] {
+        let bytes = RgbImage::from_fn(side, side, |x, y| {
+            image::Rgb([(x & 0xff) as u8, (y & 0xff) as u8, ((x ^ y) & 0xff) as u8])
+        })
+        .into_raw();
+
+        let name = format!("{}x{}", side, side);
+
+        c.benchmark_group("hasher").bench_with_input(
+            BenchmarkId::new("spawning", &name),
+            &bytes,
+            |b, bytes| b.iter(|| Dhash::new(black_box(bytes), side, side, 3)),
+        );
+
+        c.benchmark_group("hasher").bench_with_input(
+            BenchmarkId::new("pool", &name),
+            &bytes,
+            |b, bytes| b.iter(|| hasher.hash(black_box(bytes), side, side, 3)),
+        );
+    }
+}
+
+/// Hashing many small images from a rayon thread pool, run with and without
+/// the `rayon` feature to compare the backends.
+fn bench_thumbnails(c: &mut Criterion) {
+    let backend = match cfg!(feature = "rayon") {
+        true => "rayon",
+        false => "threads",
+    };
+
+    let thumbnails = (0..10_000u32)
+        .map(|i| {
+            RgbImage::from_fn(64, 64, |x, y| {
+                image::Rgb([(x * 4) as u8, (y * 4) as u8, ((x ^ y ^ i) & 0xff) as u8])
+            })
+            .into_raw()
+        })
+        .collect::<Vec<_>>();
+
+    c.benchmark_group("thumbnails")
+        .sample_size(10)
+        .bench_with_input(
+            BenchmarkId::new("new_10000_64x64", backend),
+            &thumbnails,
+            |b, thumbnails| {
+                b.iter(|| {
+                    thumbnails
+                        .par_iter()
+                        .map(|bytes| Dhash::new(black_box(bytes), 64, 64, 3))
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+}
+
+/// A batch of small images hashed across threads against one at a time.
+fn bench_hash_many(c: &mut Criterion) {
+    let thumbnails = (0..1000u32)
+        .map(|i| {
+            RgbImage::from_fn(128, 128, |x, y| {
+                image::Rgb([(x * 2) as u8, (y * 2) as u8, ((x ^ y ^ i) & 0xff) as u8])
+            })
+            .into_raw()
+        })
+        .collect::<Vec<_>>();
+
+    let images = thumbnails
+        .iter()
+        .map(|bytes| ImageRef::new(bytes, 128, 128, 3))
+        .collect::<Vec<_>>();
+
+    c.benchmark_group("hash_many")
+        .sample_size(10)
+        .bench_function("sequential_1000_128x128", |b| {
+            b.iter(|| {
+                thumbnails
+                    .iter()
+                    .map(|bytes| Dhash::try_new(black_box(bytes), 128, 128, 3))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .bench_function("hash_many_1000_128x128", |b| {
+            b.iter(|| hash_many(black_box(&images)))
+        });
+}
+
+fn bench_hamming_distance(c: &mut Criterion) {
+    let hashes = hashes(1000);
+
+    c.benchmark_group("baseline")
+        .bench_function("hamming_distance", |b| {
+            b.iter(|| {
+                let mut total = 0u32;
+
+                for pair in black_box(&hashes).windows(2) {
+                    total += pair[0].hamming_distance(&pair[1]);
+                }
+
+                total
+            })
+        })
+        .bench_function("pairwise_1000", |b| {
+            b.iter(|| pairwise_single_threaded(black_box(&hashes)))
+        });
+
+    c.benchmark_group("threaded")
+        .bench_function("hamming_distance", |b| {
+            b.iter(|| {
+                let hashes = black_box(&hashes);
+                let half = hashes.len() / 2;
+
+                thread::scope(|s| {
+                    let sum = |hashes: &[Dhash]| {
+                        hashes
+                            .windows(2)
+                            .map(|pair| pair[0].hamming_distance(&pair[1]))
+                            .sum::<u32>()
+                    };
+
+                    let left = s.spawn(move || sum(&hashes[..=half]));
+                    let right = sum(&hashes[half..]);
+
+                    left.join().unwrap() + right
+                })
+            })
+        })
+        .bench_function("pairwise_1000", |b| {
+            b.iter(|| pairwise_threaded(black_box(&hashes)))
+        });
+}
+
+fn bench_hamming_distance_batch(c: &mut Criterion) {
+    let candidates = hashes(10_000);
+    let query = candidates[0];
+
+    c.benchmark_group("batch")
+        .bench_function("loop_10000", |b| {
+            b.iter(|| {
+                black_box(&candidates)
+                    .iter()
+                    .map(|candidate| query.hamming_distance(candidate))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .bench_function("batch_10000", |b| {
+            b.iter(|| Dhash::hamming_distance_batch(query, black_box(&candidates)))
+        });
+}
+
+/// Reference BK-tree, each child is keyed by its distance to its parent
+struct BkTree {
+    nodes: Vec<(Dhash, Vec<(u32, usize)>)>,
+}
+
+impl BkTree {
+    fn new(hashes: &[Dhash]) -> Self {
+        let mut tree = Self { nodes: Vec::new() };
+
+        for &hash in hashes {
+            tree.insert(hash);
+        }
+
+        tree
+    }
+
+    fn insert(&mut self, hash: Dhash) {
+        let index = self.nodes.len();
+        self.nodes.push((hash, Vec::new()));
+
+        if index == 0 {
+            return;
+        }
+
+        let mut node = 0;
+
+        loop {
+            let distance = self.nodes[node].0.hamming_distance(&hash);
+
+            match self.nodes[node].1.iter().find(|(d, _)| *d == distance) {
+                Some(&(_, child)) => node = child,
+                None => {
+                    self.nodes[node].1.push((distance, index));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn query_range(&self, query: Dhash, radius: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut stack = vec![0];
+
+        while let Some(node) = stack.pop() {
+            let Some((hash, children)) = self.nodes.get(node) else {
+                break;
+            };
+
+            let distance = hash.hamming_distance(&query);
+
+            if distance <= radius {
+                found.push(node);
+            }
+
+            for &(d, child) in children {
+                if d.abs_diff(distance) <= radius {
+                    stack.push(child);
+                }
+            }
+        }
+
+        found
+    }
+}
+
+fn bench_tree(c: &mut Criterion) {
+    let hashes = hashes(100_000);
+    let queries = hashes.iter().step_by(1000).copied().collect::<Vec<_>>();
+
+    let vp_tree = DhashVPTree::new(&hashes);
+    let bk_tree = BkTree::new(&hashes);
+
+    let mut group = c.benchmark_group("tree");
+
+    for radius in [2, 4, 8, 12, 16] {
+        group.bench_with_input(
+            BenchmarkId::new("vp_100000", radius),
+            &radius,
+            |b, &radius| {
+                b.iter(|| {
+                    for &query in &queries {
+                        black_box(vp_tree.query_range(query, radius));
+                    }
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("bk_100000", radius),
+            &radius,
+            |b, &radius| {
+                b.iter(|| {
+                    for &query in &queries {
+                        black_box(bk_tree.query_range(query, radius));
+                    }
+                })
+            },
+        );
+    }
+}
+
+criterion_group!(
+    benches,
+    bench_new,
+    bench_threads,
+    bench_4k,
+    bench_chunk_size,
+    bench_parallel_threshold,
+    bench_hasher,
+    bench_thumbnails,
+    bench_hash_many,
+    bench_hamming_distance,
+    bench_hamming_distance_batch,
+    bench_tree
+);
+criterion_main!(benches);
@@ -0,0 +1,274 @@
+//! Row by row grid accumulation, for images that are not fully in memory.
+use crate::{
+    grid::{channels_luma, check_dimensions, pixel_channels},
+    Dhash, DhashError, Grid,
+};
+
+/// Hashes an image from scanlines as a decoder produces them, without
+/// buffering the whole frame.
+///
+/// ```
+/// use fast_dhash::{Dhash, GridBuilder};
+///
+/// let bytes = vec![0u8; 90 * 80 * 3];
+///
+/// let mut builder = GridBuilder::new(90, 80, 3);
+///
+/// for rows in bytes.chunks(90 * 3 * 16) {
+///     builder.push_rows(rows).expect("invalid rows");
+/// }
+///
+/// assert_eq!(builder.finish(), Ok(Dhash::new(&bytes, 90, 80, 3)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct GridBuilder {
+    accumulator: DhashAccumulator,
+}
+
+impl GridBuilder {
+    pub fn new(width: u32, height: u32, channel_count: u8) -> Self {
+        Self {
+            accumulator: DhashAccumulator::new(width, height, channel_count),
+        }
+    }
+
+    /// Adds any number of complete scanlines, errors without consuming any
+    /// of them if `rows` is not a whole number of scanlines or exceeds the
+    /// image height.
+    pub fn push_rows(&mut self, rows: &[u8]) -> Result<(), DhashError> {
+        self.accumulator.check_channels()?;
+
+        let row_len = self.accumulator.row_len();
+
+        if row_len == 0 {
+            return match rows.len() {
+                0 => Ok(()),
+                actual => Err(DhashError::InvalidDimensions {
+                    expected: 0,
+                    actual,
+                }),
+            };
+        }
+
+        let whole_rows = rows.len().div_ceil(row_len) * row_len;
+
+        if whole_rows != rows.len() {
+            return Err(DhashError::InvalidDimensions {
+                expected: whole_rows,
+                actual: rows.len(),
+            });
+        }
+
+        let pushed = self.accumulator.rows() * row_len;
+        let total = self.accumulator.height * row_len;
+
+        if pushed + rows.len() > total {
+            return Err(DhashError::InvalidDimensions {
+                expected: total,
+                actual: pushed + rows.len(),
+            });
+        }
+
+        for row in rows.chunks_exact(row_len) {
+            self.accumulator.push_row(row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Errors if fewer than `height` rows were pushed.
+    pub fn finish(self) -> Result<Dhash, DhashError> {
+        self.accumulator
+            .finish()
+            .map(|grid| Dhash::from_grid(&grid))
+    }
+}
+
+/// Accumulates the 9x8 grid one pixel row at a time, top to bottom.
+#[derive(Debug, Clone)]
+pub(crate) struct DhashAccumulator {
+    width: usize,
+    height: usize,
+    channel_count: usize,
+    cell_width: usize,
+    cell_height: usize,
+    row: usize,
+    /// Per cell channel sums, luma weights are applied by `finish`
+    sums: [[[f64; 3]; 9]; 8],
+}
+
+impl DhashAccumulator {
+    pub(crate) fn new(width: u32, height: u32, channel_count: u8) -> Self {
+        let width = width as usize;
+        let height = height as usize;
+
+        Self {
+            width,
+            height,
+            channel_count: channel_count as usize,
+            cell_width: width / 9,
+            cell_height: height / 8,
+            row: 0,
+            sums: [[[0f64; 3]; 9]; 8],
+        }
+    }
+
+    pub(crate) fn row_len(&self) -> usize {
+        self.width * self.channel_count
+    }
+
+    pub(crate) fn rows(&self) -> usize {
+        self.row
+    }
+
+    /// Errors as [`check_dimensions`] does for an image without channels,
+    /// whose rows would be empty and all accepted.
+    pub(crate) fn check_channels(&self) -> Result<(), DhashError> {
+        match self.channel_count {
+            0 => check_dimensions(0, self.width, self.height, 0),
+            _ => Ok(()),
+        }
+    }
+
+    /// Adds the next row, `row` must be [`DhashAccumulator::row_len`] long.
+    pub(crate) fn push_row(&mut self, row: &[u8]) -> Result<(), DhashError> {
+        self.check_channels()?;
+
+        if row.len() != self.row_len() {
+            return Err(DhashError::InvalidDimensions {
+                expected: self.row_len(),
+                actual: row.len(),
+            });
+        }
+
+        if self.row >= self.height {
+            return Err(DhashError::InvalidDimensions {
+                expected: self.height * self.row_len(),
+                actual: (self.row + 1) * self.row_len(),
+            });
+        }
+
+        let y = self.row;
+        self.row += 1;
+
+        // NOTE: rows and columns past the last full cell are not sampled
+        if self.cell_height == 0 || y >= self.cell_height * 8 {
+            return Ok(());
+        }
+
+        let cells = &mut self.sums[y / self.cell_height];
+
+        for x in 0..self.cell_width * 9 {
+            let cell = &mut cells[x / self.cell_width];
+
+            for (sum, channel) in cell
+                .iter_mut()
+                .zip(pixel_channels(row, self.channel_count, x))
+            {
+                *sum += channel;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The grid of the rows pushed so far, errors if the image is not
+    /// complete.
+    pub(crate) fn finish(&self) -> Result<Grid, DhashError> {
+        self.check_channels()?;
+
+        if self.row != self.height {
+            return Err(DhashError::InvalidDimensions {
+                expected: self.height * self.row_len(),
+                actual: self.row * self.row_len(),
+            });
+        }
+
+        let cell_size = (self.cell_width * self.cell_height) as f64;
+        let mut cells = [[0f64; 9]; 8];
+
+        for (row, sums) in cells.iter_mut().zip(&self.sums) {
+            for (cell, &channels) in row.iter_mut().zip(sums) {
+                *cell = channels_luma(channels, self.channel_count);
+
+                if cell_size > 0.0 {
+                    *cell /= cell_size;
+                }
+            }
+        }
+
+        Ok(Grid::new(cells))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GridBuilder;
+    use crate::{Dhash, DhashError};
+    use image::ImageReader;
+
+    #[test]
+    fn chunks() {
+        for path in [
+            ".test/grad.ffff.jpg",
+            ".test/grad.0000.jpg",
+            ".test/radial.jpg",
+        ] {
+            let image = ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image");
+
+            let bytes = image.as_bytes();
+            let channel_count = image.color().channel_count();
+            let row_len = image.width() as usize * channel_count as usize;
+
+            let hash = Dhash::new(bytes, image.width(), image.height(), channel_count);
+
+            for rows in [1, 7, image.height() as usize] {
+                let mut builder = GridBuilder::new(image.width(), image.height(), channel_count);
+
+                for chunk in bytes.chunks(rows * row_len) {
+                    builder.push_rows(chunk).expect("invalid rows");
+                }
+
+                assert_eq!(builder.finish(), Ok(hash));
+            }
+        }
+    }
+
+    #[test]
+    fn over_and_underfeed() {
+        let mut builder = GridBuilder::new(9, 8, 1);
+
+        assert!(builder.push_rows(&[0; 10]).is_err());
+        assert!(builder.push_rows(&[0; 9 * 9]).is_err());
+
+        builder.push_rows(&[0; 9 * 7]).expect("invalid rows");
+
+        assert_eq!(
+            builder.finish(),
+            Err(DhashError::InvalidDimensions {
+                expected: 9 * 8,
+                actual: 9 * 7
+            })
+        );
+    }
+
+    #[test]
+    fn no_channels() {
+        // NOTE: the error of check_dimensions, rather than an empty image
+        let error = DhashError::InvalidDimensions {
+            expected: 90 * 80,
+            actual: 0,
+        };
+
+        assert_eq!(Dhash::try_new(&[], 90, 80, 0), Err(error.clone()));
+        assert_eq!(
+            GridBuilder::new(90, 80, 0).push_rows(&[]),
+            Err(error.clone())
+        );
+        assert_eq!(GridBuilder::new(90, 80, 0).finish(), Err(error.clone()));
+        assert_eq!(Dhash::from_reader(std::io::empty(), 90, 80, 0), Err(error));
+    }
+}
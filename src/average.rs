@@ -0,0 +1,115 @@
+//! Average hash (ahash), a companion to dhash computed with the same grid
+//! reduction, e.g. to compare both algorithms on the same pipeline or to
+//! ensemble them.
+//!
+//! The image is reduced to an 8x8 luma grid, as [`Dhash::new`] reduces it to
+//! a 9x8 one, and each bit is set if its cell is brighter than the grid mean.
+//! The bits are the ones of [`Dhash::new_average`], typed apart so that an
+//! average hash cannot be compared with a dhash by mistake.
+//!
+//! [`Dhash::new`]: crate::Dhash::new
+//! [`Dhash::new_average`]: crate::Dhash::new_average
+use crate::{builder::CellAggregation, family, grid::luma_grid, DhashError, SimilarTo};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fmt, num, str};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct AverageHash {
+    /// One bit per cell of the 8x8 grid, row-major, least significant bit
+    /// first, as in [`crate::Dhash`]
+    pub hash: u64,
+}
+
+impl AverageHash {
+    pub fn new(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        match Self::try_new(bytes, width, height, channel_count) {
+            Ok(hash) => hash,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Same as [`AverageHash::new`], returning an error instead of panicking
+    /// on invalid dimensions.
+    pub fn try_new(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        let grid: [[f64; 8]; 8] =
+            luma_grid(bytes, width, height, channel_count, CellAggregation::Sum)?;
+
+        Ok(Self {
+            hash: family::average_bits(&grid),
+        })
+    }
+
+    pub fn hamming_distance(&self, other: &Self) -> u32 {
+        (self.hash ^ other.hash).count_ones()
+    }
+}
+
+impl SimilarTo for AverageHash {
+    fn similar_to(&self, other: &Self, threshold: u32) -> bool {
+        self.hamming_distance(other) < threshold
+    }
+}
+
+impl fmt::Display for AverageHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", &self.hash)
+    }
+}
+
+impl str::FromStr for AverageHash {
+    type Err = num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u64::from_str_radix(s, 16).map(|hash| Self { hash })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::AverageHash;
+    use crate::Dhash;
+    use image::ImageReader;
+
+    #[test]
+    fn average() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+
+        for (bytes, channel_count) in [
+            (image.to_rgb8().into_raw(), 3),
+            (image.to_luma_alpha8().into_raw(), 2),
+            (image.to_luma8().into_raw(), 1),
+        ] {
+            let hash = AverageHash::new(&bytes, width, height, channel_count);
+
+            assert_eq!(
+                hash.hash,
+                Dhash::new_average(&bytes, width, height, channel_count).hash
+            );
+            assert_eq!(hash.hamming_distance(&hash), 0);
+            assert_eq!(hash.to_string().parse::<AverageHash>(), Ok(hash));
+        }
+
+        // NOTE: left half dark, right half bright
+        let bytes = (0..80 * 80)
+            .map(|i| if i % 80 < 40 { 0 } else { 255 })
+            .collect::<Vec<_>>();
+
+        let hash = AverageHash::new(&bytes, 80, 80, 1);
+
+        assert_eq!(hash.hash, 0xf0f0f0f0f0f0f0f0);
+        assert_eq!(hash.hamming_distance(&AverageHash { hash: !hash.hash }), 64);
+        assert!(AverageHash::try_new(&bytes, 80, 81, 1).is_err());
+    }
+}
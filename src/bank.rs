@@ -0,0 +1,125 @@
+//! Contiguous hash storage for large linear scans.
+use crate::Dhash;
+
+/// Number of hashes compared per iteration of [`DhashBank::query`], lets the
+/// compiler unroll and vectorize the popcount.
+const CHUNK: usize = 8;
+
+/// Hashes packed as a contiguous `[u64]`, unlike `Vec<Dhash>` the layout is
+/// guaranteed, so the raw values can be handed to SIMD routines or written
+/// to disk as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhashBank {
+    hashes: Vec<u64>,
+}
+
+impl DhashBank {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            hashes: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn push(&mut self, hash: Dhash) {
+        self.hashes.push(hash.hash);
+    }
+
+    pub fn get(&self, index: usize) -> Option<Dhash> {
+        self.hashes.get(index).map(|&hash| Dhash { hash })
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn as_u64_slice(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    /// Indices of every hash within `max` hamming distance of `hash`, in
+    /// insertion order.
+    pub fn query(&self, hash: &Dhash, max: u32) -> Vec<usize> {
+        let mut indices = Vec::new();
+
+        let chunks = self.hashes.chunks_exact(CHUNK);
+        let remainder = chunks.remainder();
+
+        for (c, chunk) in chunks.enumerate() {
+            let mut distances = [0u32; CHUNK];
+
+            for (distance, other) in distances.iter_mut().zip(chunk) {
+                *distance = (hash.hash ^ other).count_ones();
+            }
+
+            for (i, &distance) in distances.iter().enumerate() {
+                if distance <= max {
+                    indices.push(c * CHUNK + i);
+                }
+            }
+        }
+
+        let offset = self.hashes.len() - remainder.len();
+
+        for (i, other) in remainder.iter().enumerate() {
+            if (hash.hash ^ other).count_ones() <= max {
+                indices.push(offset + i);
+            }
+        }
+
+        indices
+    }
+}
+
+impl FromIterator<Dhash> for DhashBank {
+    fn from_iter<I: IntoIterator<Item = Dhash>>(iter: I) -> Self {
+        Self {
+            hashes: iter.into_iter().map(|hash| hash.hash).collect(),
+        }
+    }
+}
+
+impl Extend<Dhash> for DhashBank {
+    fn extend<I: IntoIterator<Item = Dhash>>(&mut self, iter: I) {
+        self.hashes.extend(iter.into_iter().map(|hash| hash.hash));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhashBank;
+    use crate::Dhash;
+
+    #[test]
+    fn query() {
+        // NOTE: more than a chunk, to cover the remainder
+        let bank = (0..19)
+            .map(|i| Dhash {
+                hash: (1u64 << i) - 1,
+            })
+            .collect::<DhashBank>();
+
+        assert_eq!(bank.len(), 19);
+        assert_eq!(bank.as_u64_slice()[3], 0b111);
+
+        let indices = bank.query(&Dhash { hash: 0xff }, 2);
+
+        assert_eq!(indices, vec![6, 7, 8, 9, 10]);
+
+        let indices = bank.query(
+            &Dhash {
+                hash: (1 << 17) - 1,
+            },
+            1,
+        );
+
+        assert_eq!(indices, vec![16, 17, 18]);
+    }
+}
@@ -0,0 +1,167 @@
+//! One query against many candidates.
+use crate::Dhash;
+
+/// Distances of `query` to each candidate, in order.
+pub(crate) fn hamming_distances(query: u64, candidates: &[Dhash]) -> Vec<u32> {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if is_x86_feature_detected!("avx512f") && is_x86_feature_detected!("avx512vpopcntdq") {
+        // SAFETY: both target features of the function were detected
+        return unsafe { avx512::hamming_distances(query, candidates) };
+    }
+
+    scalar(query, candidates)
+}
+
+/// Branch and bound over the implicit binary trie of the sorted hashes, the
+/// subrange sharing a prefix is found by binary search and skipped when the
+/// prefix alone is farther than the best match so far.
+pub(crate) fn nearest_sorted(query: u64, sorted: &[Dhash]) -> Option<(usize, u32)> {
+    // NOTE: below this size a linear scan is cheaper than splitting
+    const LEAF: usize = 16;
+
+    let mut best: Option<(usize, u32)> = None;
+    // NOTE: (from, to, bits left, distance of the shared prefix)
+    let mut stack = vec![(0, sorted.len(), 64u32, 0u32)];
+
+    while let Some((from, to, bits, prefix_distance)) = stack.pop() {
+        if from == to || best.is_some_and(|(_, distance)| prefix_distance >= distance) {
+            continue;
+        }
+
+        if to - from <= LEAF || bits == 0 {
+            for (i, candidate) in sorted[from..to].iter().enumerate() {
+                let distance = (query ^ candidate.hash).count_ones();
+
+                if best.is_none_or(|(_, best)| distance < best) {
+                    best = Some((from + i, distance));
+                }
+            }
+
+            continue;
+        }
+
+        let bit = 1 << (bits - 1);
+        let split = from + sorted[from..to].partition_point(|hash| hash.hash & bit == 0);
+
+        let (same, other) = if query & bit == 0 {
+            ((from, split), (split, to))
+        } else {
+            ((split, to), (from, split))
+        };
+
+        // NOTE: the branch agreeing with the query is popped first
+        stack.push((other.0, other.1, bits - 1, prefix_distance + 1));
+        stack.push((same.0, same.1, bits - 1, prefix_distance));
+    }
+
+    best
+}
+
+fn scalar(query: u64, candidates: &[Dhash]) -> Vec<u32> {
+    candidates
+        .iter()
+        .map(|candidate| (query ^ candidate.hash).count_ones())
+        .collect()
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx512 {
+    use crate::Dhash;
+    use std::arch::x86_64::*;
+
+    /// Eight candidates at a time with `VPOPCNTQ`.
+    #[target_feature(enable = "avx512f,avx512vpopcntdq")]
+    pub(super) unsafe fn hamming_distances(query: u64, candidates: &[Dhash]) -> Vec<u32> {
+        let mut distances = Vec::with_capacity(candidates.len());
+
+        let query_lanes = _mm512_set1_epi64(query as i64);
+
+        let chunks = candidates.chunks_exact(8);
+        let remainder = chunks.remainder();
+
+        for chunk in chunks {
+            // NOTE: Dhash has no guaranteed layout, the hashes are copied
+            let hashes: [u64; 8] = std::array::from_fn(|i| chunk[i].hash);
+
+            // SAFETY: unaligned load of the 64 bytes of `hashes`
+            let lanes = _mm512_loadu_si512(hashes.as_ptr() as *const _);
+            let counts = _mm512_popcnt_epi64(_mm512_xor_si512(lanes, query_lanes));
+
+            let mut out = [0u32; 8];
+            // SAFETY: unaligned store of the 32 bytes of `out`
+            _mm256_storeu_si256(out.as_mut_ptr() as *mut _, _mm512_cvtepi64_epi32(counts));
+
+            distances.extend_from_slice(&out);
+        }
+
+        distances.extend(super::scalar(query, remainder));
+
+        distances
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hamming_distances, nearest_sorted, scalar};
+    use crate::Dhash;
+
+    #[test]
+    fn matches_scalar() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+
+        // NOTE: not a multiple of the lanes, to cover the remainder
+        let candidates = (0..1003)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                Dhash { hash: state }
+            })
+            .collect::<Vec<_>>();
+
+        let distances = hamming_distances(0xf0f0e8cccce8f0f0, &candidates);
+
+        assert_eq!(distances, scalar(0xf0f0e8cccce8f0f0, &candidates));
+        assert_eq!(
+            distances[0],
+            candidates[0].hamming_distance(&Dhash {
+                hash: 0xf0f0e8cccce8f0f0
+            })
+        );
+    }
+
+    #[test]
+    fn nearest_matches_linear_scan() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut sorted = (0..100_000)
+            .map(|_| Dhash { hash: next() })
+            .collect::<Vec<_>>();
+
+        sorted.sort_unstable_by_key(|hash| hash.hash);
+
+        // NOTE: random queries and near duplicates of stored hashes
+        let queries = (0..200)
+            .map(|i| match i % 2 {
+                0 => next(),
+                _ => sorted[next() as usize % sorted.len()].hash ^ (1 << (next() % 64)),
+            })
+            .collect::<Vec<_>>();
+
+        for query in queries {
+            let linear = scalar(query, &sorted).into_iter().min();
+            let (index, distance) = nearest_sorted(query, &sorted).unwrap();
+
+            assert_eq!(Some(distance), linear);
+            assert_eq!((query ^ sorted[index].hash).count_ones(), distance);
+        }
+
+        assert_eq!(nearest_sorted(0, &[]), None);
+    }
+}
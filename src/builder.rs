@@ -0,0 +1,522 @@
+//! Configurable hash construction.
+use crate::{
+    grid::{subsampled_luma_grid, workers_for, Reduction, Workers, PARALLEL_THRESHOLD},
+    Dhash, Grid,
+};
+
+/// How the luma of the pixels of a grid cell is reduced to a single value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CellAggregation {
+    /// Sum of the luma, divided by the cell size to give the mean
+    #[default]
+    Sum,
+    /// Brightest pixel, more robust to small dark occlusions
+    Max,
+    /// Median luma, the mean of the two middle values for even cell sizes
+    Median,
+}
+
+impl CellAggregation {
+    /// `buffer` is scratch space for [`CellAggregation::Median`], reused
+    /// across cells to avoid an allocation each.
+    pub(crate) fn reduce<I>(self, pixels: I, buffer: &mut Vec<f64>) -> f64
+    where
+        I: Iterator<Item = f64>,
+    {
+        match self {
+            Self::Sum => pixels.sum(),
+            Self::Max => pixels.fold(0f64, f64::max),
+            Self::Median => {
+                buffer.clear();
+                buffer.extend(pixels);
+
+                if buffer.is_empty() {
+                    return 0.0;
+                }
+
+                let len = buffer.len();
+                let mid = len / 2;
+                let (lower, &mut median, _) = buffer.select_nth_unstable_by(mid, f64::total_cmp);
+
+                if mid * 2 == len {
+                    let below = lower.iter().cloned().fold(f64::MIN, f64::max);
+                    (below + median) / 2.0
+                } else {
+                    median
+                }
+            }
+        }
+    }
+}
+
+/// How the image is divided in cells when its size is not a multiple of
+/// the size of the grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RoundingMode {
+    /// Cells of `size / cells` pixels from the top left corner, the
+    /// remaining right columns and bottom rows are ignored, as in
+    /// [`Dhash::new`]
+    #[default]
+    Floor,
+    /// Cell boundaries rounded to the nearest pixel, cells differing by at
+    /// most one pixel cover the whole image. The closest to Python's
+    /// `imagehash.dhash`, which resizes the whole image to the grid, though
+    /// with a Lanczos filter rather than a box one
+    Round,
+    /// Cells of `size / cells` pixels rounded up from the top left corner,
+    /// covering the whole image, the last cells being narrower, or empty
+    Ceil,
+    /// Cells of `size / cells` pixels as with [`RoundingMode::Floor`], the
+    /// grid centered on the nearest pixel, the remaining columns and rows
+    /// ignored on both sides rather than only on the right and bottom
+    Nearest,
+}
+
+impl RoundingMode {
+    /// The `cells + 1` boundaries of the cells along a side of `size`
+    /// pixels, cell `i` spanning `bounds[i]..bounds[i + 1]`, the last one
+    /// is at most `size`.
+    pub(crate) fn bounds(self, cells: usize, size: usize) -> Vec<usize> {
+        (0..=cells)
+            .map(|i| match self {
+                Self::Floor => i * (size / cells),
+                Self::Round => (2 * i * size + cells) / (2 * cells),
+                Self::Ceil => (i * size.div_ceil(cells)).min(size),
+                Self::Nearest => size % cells / 2 + i * (size / cells),
+            })
+            .collect()
+    }
+}
+
+/// Order of the color channels of pixels with 3 or more channels, the
+/// fourth one being alpha, if any.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ChannelOrder {
+    /// RGB or RGBA, as decoded by the *image* crate
+    #[default]
+    Rgb,
+    /// BGR or BGRA, as in OpenCV and Windows bitmaps
+    Bgr,
+}
+
+/// Which neighbor each cell of a row is compared with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    /// Each of the first 8 cells of a 9 cells wide grid with its right
+    /// neighbor, the 9th column only exists to be compared with
+    #[default]
+    RightNeighbor,
+    /// Each cell of an 8 cells wide grid with its right neighbor, the last
+    /// one wrapping around to the first, as in implementations hashing an
+    /// 8x8 grid. Cells are a ninth wider than with
+    /// [`Comparison::RightNeighbor`], so the two hashes are not comparable.
+    Wrap,
+}
+
+/// Builds hashes with non default options.
+///
+/// ```
+/// use fast_dhash::{CellAggregation, DhashBuilder};
+///
+/// let builder = DhashBuilder::new().cell_aggregation(CellAggregation::Max);
+///
+/// let hash = builder.build(&[0; 90 * 80], 90, 80, 1);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DhashBuilder {
+    cell_aggregation: CellAggregation,
+    comparison: Comparison,
+    rounding: RoundingMode,
+    channel_order: ChannelOrder,
+    // NOTE: set by `threads` or `chunk_size`, whichever is called last
+    workers: Option<Workers<'static>>,
+    parallel_threshold: Option<usize>,
+}
+
+impl DhashBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cell_aggregation(mut self, cell_aggregation: CellAggregation) -> Self {
+        self.cell_aggregation = cell_aggregation;
+        self
+    }
+
+    pub fn comparison(mut self, comparison: Comparison) -> Self {
+        self.comparison = comparison;
+        self
+    }
+
+    pub fn rounding(mut self, rounding: RoundingMode) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// Order of the channels of the pixels, so that BGR buffers get the
+    /// same luma, and hash, as the equivalent RGB ones.
+    pub fn channel_order(mut self, channel_order: ChannelOrder) -> Self {
+        self.channel_order = channel_order;
+        self
+    }
+
+    /// Number of threads the rows of the grid are split across, by default
+    /// one per row, or none below the
+    /// [`parallel_threshold`](Self::parallel_threshold). 0 or 1 computes
+    /// the hash on the calling thread, without spawning any, for callers
+    /// already running one of many workers.
+    ///
+    /// With the `rayon` feature the rows are computed on the current rayon
+    /// thread pool, in chunks of at least `rows / threads` rows.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.workers = Some(Workers::Threads(Some(threads)));
+        self
+    }
+
+    /// Number of rows of the grid computed by each thread, the other way
+    /// around from [`threads`](Self::threads), whichever is called last
+    /// applies: with the 8 rows of the hash, 2 spawns 4 threads and 8 or
+    /// more computes the hash on the calling thread. 0 reads as 1.
+    pub fn chunk_size(mut self, rows_per_thread: usize) -> Self {
+        self.workers = Some(Workers::Chunks(rows_per_thread));
+        self
+    }
+
+    /// Number of pixels under which, unless [`threads`](Self::threads) or
+    /// [`chunk_size`](Self::chunk_size) is set, the hash is computed on the
+    /// calling thread, by default [`PARALLEL_THRESHOLD`].
+    pub fn parallel_threshold(mut self, pixels: usize) -> Self {
+        self.parallel_threshold = Some(pixels);
+        self
+    }
+
+    pub fn build(&self, bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Dhash {
+        let threshold = self.parallel_threshold.unwrap_or(PARALLEL_THRESHOLD);
+
+        let reduction = Reduction {
+            aggregation: self.cell_aggregation,
+            rounding: self.rounding,
+            channel_order: self.channel_order,
+            step: 1,
+            workers: self
+                .workers
+                .unwrap_or_else(|| workers_for(width as usize * height as usize, None, threshold)),
+        };
+
+        let hash = match self.comparison {
+            Comparison::RightNeighbor => {
+                subsampled_luma_grid(bytes, width, height, channel_count, reduction)
+                    .map(|cells| Dhash::from_grid(&Grid::new(cells)))
+            }
+            Comparison::Wrap => {
+                subsampled_luma_grid(bytes, width, height, channel_count, reduction).map(|cells| {
+                    Dhash {
+                        hash: wrapped_bits(&cells),
+                    }
+                })
+            }
+        };
+
+        match hash {
+            Ok(hash) => hash,
+            Err(error) => panic!("{}", error),
+        }
+    }
+}
+
+/// Bits of an 8x8 grid, the last cell of each row compared with the first.
+pub(crate) fn wrapped_bits(cells: &[[f64; 8]; 8]) -> u64 {
+    let mut hash = 0u64;
+
+    for (y, row) in cells.iter().enumerate() {
+        for x in 0..8 {
+            if row[x] > row[(x + 1) % 8] {
+                hash |= 1 << (y * 8 + x);
+            }
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CellAggregation, ChannelOrder, Comparison, DhashBuilder, RoundingMode};
+    use crate::Dhash;
+
+    #[test]
+    fn cell_aggregation() {
+        // NOTE: 10x10 cells, even columns are black but for a white pixel,
+        // odd columns are uniformly gray
+        let bytes = (0..90 * 80)
+            .map(|i| match (i % 90 / 10 % 2, i % 10, i / 90 % 10) {
+                (0, 0, 0) => 255,
+                (0, _, _) => 0,
+                _ => 100,
+            })
+            .collect::<Vec<u8>>();
+
+        let hash = |cell_aggregation| {
+            DhashBuilder::new()
+                .cell_aggregation(cell_aggregation)
+                .build(&bytes, 90, 80, 1)
+                .hash
+        };
+
+        assert_eq!(hash(CellAggregation::Sum), 0xaaaaaaaaaaaaaaaa);
+        assert_eq!(hash(CellAggregation::Max), 0x5555555555555555);
+        assert_eq!(hash(CellAggregation::Median), 0xaaaaaaaaaaaaaaaa);
+    }
+
+    #[test]
+    fn median() {
+        let mut buffer = Vec::new();
+
+        let median = |values: &[f64], buffer: &mut Vec<f64>| {
+            CellAggregation::Median.reduce(values.iter().cloned(), buffer)
+        };
+
+        assert_eq!(median(&[3.0, 1.0, 2.0], &mut buffer), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 3.0, 2.0], &mut buffer), 2.5);
+    }
+
+    #[test]
+    fn wrap() {
+        let gradient = |increasing: bool| {
+            (0..72 * 8)
+                .map(|i| match increasing {
+                    true => (i % 72) as u8,
+                    false => (71 - i % 72) as u8,
+                })
+                .collect::<Vec<u8>>()
+        };
+
+        let hash = |bytes: &[u8], comparison| {
+            DhashBuilder::new()
+                .comparison(comparison)
+                .build(bytes, 72, 8, 1)
+                .hash
+        };
+
+        assert_eq!(hash(&gradient(true), Comparison::RightNeighbor), 0);
+        assert_eq!(hash(&gradient(false), Comparison::RightNeighbor), u64::MAX);
+
+        // NOTE: only the last column, compared with the first, differs
+        assert_eq!(hash(&gradient(true), Comparison::Wrap), 0x8080808080808080);
+        assert_eq!(hash(&gradient(false), Comparison::Wrap), 0x7f7f7f7f7f7f7f7f);
+    }
+
+    #[test]
+    fn threads() {
+        let image = image::ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+
+        for (bytes, channel_count) in [
+            (image.to_rgb8().into_raw(), 3),
+            (image.to_luma8().into_raw(), 1),
+        ] {
+            let expected = Dhash::new(&bytes, width, height, channel_count);
+
+            for threads in [0, 1, 2, 3, 8, 16] {
+                for aggregation in [CellAggregation::Sum, CellAggregation::Median] {
+                    let builder = DhashBuilder::new()
+                        .threads(threads)
+                        .cell_aggregation(aggregation);
+
+                    assert_eq!(
+                        builder.build(&bytes, width, height, channel_count),
+                        DhashBuilder::new().cell_aggregation(aggregation).build(
+                            &bytes,
+                            width,
+                            height,
+                            channel_count
+                        )
+                    );
+                }
+
+                assert_eq!(
+                    DhashBuilder::new().threads(threads).build(
+                        &bytes,
+                        width,
+                        height,
+                        channel_count
+                    ),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_size() {
+        let bytes = (0..900 * 800 * 3)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let expected = Dhash::new(&bytes, 900, 800, 3);
+
+        for rows_per_thread in [0, 1, 2, 3, 5, 8, 16] {
+            let builder = DhashBuilder::new().chunk_size(rows_per_thread);
+
+            assert_eq!(builder.build(&bytes, 900, 800, 3), expected);
+            assert_eq!(
+                builder
+                    .cell_aggregation(CellAggregation::Median)
+                    .build(&bytes, 900, 800, 3),
+                DhashBuilder::new()
+                    .cell_aggregation(CellAggregation::Median)
+                    .build(&bytes, 900, 800, 3)
+            );
+        }
+
+        assert_eq!(
+            DhashBuilder::new()
+                .threads(3)
+                .chunk_size(2)
+                .build(&bytes, 900, 800, 3),
+            expected
+        );
+    }
+
+    #[test]
+    fn parallel_threshold() {
+        // NOTE: 99,856 and 100,489 pixels, either side of the threshold
+        for side in [316u32, 317] {
+            let bytes = (0..side * side * 3)
+                .map(|i| (i * 7 % 251) as u8)
+                .collect::<Vec<_>>();
+
+            let expected = DhashBuilder::new().threads(8).build(&bytes, side, side, 3);
+
+            assert_eq!(Dhash::new(&bytes, side, side, 3), expected);
+
+            for threshold in [0, usize::MAX] {
+                assert_eq!(
+                    DhashBuilder::new()
+                        .parallel_threshold(threshold)
+                        .build(&bytes, side, side, 3),
+                    expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(
+            RoundingMode::Floor.bounds(9, 100),
+            [0, 11, 22, 33, 44, 55, 66, 77, 88, 99]
+        );
+        assert_eq!(
+            RoundingMode::Round.bounds(9, 100),
+            [0, 11, 22, 33, 44, 56, 67, 78, 89, 100]
+        );
+        assert_eq!(
+            RoundingMode::Ceil.bounds(9, 100),
+            [0, 12, 24, 36, 48, 60, 72, 84, 96, 100]
+        );
+        assert_eq!(RoundingMode::Ceil.bounds(8, 5), [0, 1, 2, 3, 4, 5, 5, 5, 5]);
+        assert_eq!(
+            RoundingMode::Nearest.bounds(9, 104),
+            [2, 13, 24, 35, 46, 57, 68, 79, 90, 101]
+        );
+        assert_eq!(RoundingMode::Nearest.bounds(8, 5), [2; 9]);
+
+        // NOTE: white but for the last column, ignored by Floor
+        let bytes = (0..100 * 80)
+            .map(|i| if i % 100 == 99 { 0 } else { 255 })
+            .collect::<Vec<u8>>();
+
+        let hash = |rounding| {
+            DhashBuilder::new()
+                .rounding(rounding)
+                .build(&bytes, 100, 80, 1)
+                .hash
+        };
+
+        assert_eq!(hash(RoundingMode::Floor), 0);
+        assert_eq!(hash(RoundingMode::Round), 0x8080808080808080);
+        assert_eq!(hash(RoundingMode::Ceil), 0x8080808080808080);
+        assert_eq!(hash(RoundingMode::Nearest), 0);
+
+        // NOTE: black but for the first column, ignored by Nearest only
+        let bytes = (0..102 * 80)
+            .map(|i| if i % 102 == 0 { 255 } else { 0 })
+            .collect::<Vec<u8>>();
+
+        let hash = |rounding| {
+            DhashBuilder::new()
+                .rounding(rounding)
+                .build(&bytes, 102, 80, 1)
+                .hash
+        };
+
+        assert_eq!(hash(RoundingMode::Floor), 0x0101010101010101);
+        assert_eq!(hash(RoundingMode::Nearest), 0);
+
+        // NOTE: the modes agree when the size is a multiple of the grid's
+        let bytes = (0..90 * 80 * 3)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+        let expected = Dhash::new(&bytes, 90, 80, 3);
+
+        for rounding in [
+            RoundingMode::Floor,
+            RoundingMode::Round,
+            RoundingMode::Ceil,
+            RoundingMode::Nearest,
+        ] {
+            assert_eq!(
+                DhashBuilder::new()
+                    .rounding(rounding)
+                    .build(&bytes, 90, 80, 3),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn channel_order() {
+        let image = image::ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+        let bgr = DhashBuilder::new().channel_order(ChannelOrder::Bgr);
+
+        for (rgb, channel_count) in [
+            (image.to_rgb8().into_raw(), 3),
+            (image.to_rgba8().into_raw(), 4),
+        ] {
+            let mut swapped = rgb.clone();
+
+            for pixel in swapped.chunks_exact_mut(channel_count as usize) {
+                pixel.swap(0, 2);
+            }
+
+            let expected = Dhash::new(&rgb, width, height, channel_count);
+
+            assert_eq!(bgr.build(&swapped, width, height, channel_count), expected);
+            assert_eq!(
+                bgr.cell_aggregation(CellAggregation::Median).build(
+                    &swapped,
+                    width,
+                    height,
+                    channel_count
+                ),
+                DhashBuilder::new()
+                    .cell_aggregation(CellAggregation::Median)
+                    .build(&rgb, width, height, channel_count)
+            );
+            assert_ne!(
+                Dhash::new(&swapped, width, height, channel_count),
+                Dhash::new(&rgb, width, height, channel_count)
+            );
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Compact binary format for large collections of hashes.
+//!
+//! ## Layout
+//!
+//! All integers are little-endian, the hashes start at byte 16 and are 8
+//! bytes aligned, so a memory-mapped file can be read as a `[u64]` as is.
+//!
+//! | offset | size        | content                      |
+//! |--------|-------------|------------------------------|
+//! | 0      | 4           | magic, `b"DHSH"`             |
+//! | 4      | 4           | format version, `u32`, `1`   |
+//! | 8      | 8           | hash count `n`, `u64`        |
+//! | 16     | `8 * n`     | hashes, `u64` each           |
+//!
+//! ```
+//! use fast_dhash::{codec::{DhashDecoder, DhashEncoder}, Dhash};
+//!
+//! let hashes = [Dhash { hash: 1 }, Dhash { hash: 2 }];
+//!
+//! let mut bytes = Vec::new();
+//! DhashEncoder::encode_all(&mut bytes, &hashes).unwrap();
+//!
+//! let decoded = DhashDecoder::new(&bytes[..])
+//!     .unwrap()
+//!     .collect::<Result<Vec<_>, _>>()
+//!     .unwrap();
+//!
+//! assert_eq!(decoded, hashes);
+//! ```
+use crate::Dhash;
+use std::io::{self, Read, Write};
+
+pub const MAGIC: [u8; 4] = *b"DHSH";
+pub const VERSION: u32 = 1;
+/// Size of the header, the offset of the first hash
+pub const HEADER_LEN: usize = 16;
+
+/// Writes the header on creation and the hashes as they are encoded, wrap
+/// the writer in a [`io::BufWriter`] when writing to a file.
+#[derive(Debug)]
+pub struct DhashEncoder<W: Write> {
+    writer: W,
+    count: u64,
+    written: u64,
+}
+
+impl<W: Write> DhashEncoder<W> {
+    /// `count` is the number of hashes that will be encoded.
+    pub fn new(mut writer: W, count: u64) -> io::Result<Self> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&count.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            count,
+            written: 0,
+        })
+    }
+
+    pub fn encode(&mut self, hash: &Dhash) -> io::Result<()> {
+        if self.written == self.count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "more hashes than declared in the header",
+            ));
+        }
+
+        self.writer.write_all(&hash.hash.to_le_bytes())?;
+        self.written += 1;
+
+        Ok(())
+    }
+
+    /// Flushes and returns the writer, errors if fewer hashes than declared
+    /// were encoded.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.written != self.count {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "fewer hashes than declared in the header",
+            ));
+        }
+
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
+    pub fn encode_all(writer: W, hashes: &[Dhash]) -> io::Result<W> {
+        let mut encoder = Self::new(writer, hashes.len() as u64)?;
+
+        for hash in hashes {
+            encoder.encode(hash)?;
+        }
+
+        encoder.finish()
+    }
+}
+
+/// Validates the header on creation, then iterates over the hashes.
+#[derive(Debug)]
+pub struct DhashDecoder<R: Read> {
+    reader: R,
+    count: u64,
+    read: u64,
+}
+
+impl<R: Read> DhashDecoder<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+
+        if header[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid magic"));
+        }
+
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported version {}", version),
+            ));
+        }
+
+        Ok(Self {
+            reader,
+            count: u64::from_le_bytes(header[8..].try_into().unwrap()),
+            read: 0,
+        })
+    }
+
+    /// Number of hashes declared in the header.
+    pub fn hash_count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<R: Read> Iterator for DhashDecoder<R> {
+    type Item = io::Result<Dhash>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.read == self.count {
+            return None;
+        }
+
+        let mut bytes = [0u8; 8];
+
+        if let Err(error) = self.reader.read_exact(&mut bytes) {
+            // NOTE: a truncated stream ends the iteration after the error
+            self.read = self.count;
+            return Some(Err(error));
+        }
+
+        self.read += 1;
+
+        Some(Ok(Dhash {
+            hash: u64::from_le_bytes(bytes),
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count - self.read) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhashDecoder, DhashEncoder, HEADER_LEN};
+    use crate::Dhash;
+
+    #[test]
+    fn round_trip() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+
+        let hashes = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                Dhash { hash: state }
+            })
+            .collect::<Vec<_>>();
+
+        let bytes = DhashEncoder::encode_all(Vec::new(), &hashes).unwrap();
+
+        assert_eq!(bytes.len(), HEADER_LEN + 8 * hashes.len());
+
+        let decoder = DhashDecoder::new(&bytes[..]).unwrap();
+
+        assert_eq!(decoder.hash_count(), 100_000);
+
+        let decoded = decoder.collect::<Result<Vec<_>, _>>().unwrap();
+
+        assert!(decoded.iter().zip(&hashes).all(|(a, b)| a.hash == b.hash));
+        assert_eq!(decoded.len(), hashes.len());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(DhashDecoder::new(&b"DHSX\x01\0\0\0\0\0\0\0\0\0\0\0"[..]).is_err());
+
+        let bytes = DhashEncoder::encode_all(Vec::new(), &[Dhash { hash: 1 }]).unwrap();
+        let mut truncated = DhashDecoder::new(&bytes[..bytes.len() - 1]).unwrap();
+
+        assert!(truncated.next().unwrap().is_err());
+        assert!(truncated.next().is_none());
+
+        let mut encoder = DhashEncoder::new(Vec::new(), 1).unwrap();
+
+        encoder.encode(&Dhash { hash: 1 }).unwrap();
+
+        assert!(encoder.encode(&Dhash { hash: 2 }).is_err());
+        assert!(DhashEncoder::new(Vec::new(), 1).unwrap().finish().is_err());
+    }
+}
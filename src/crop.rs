@@ -0,0 +1,257 @@
+//! Crop resistant hash, a [`Dhash`] per bright or dark segment of the image.
+//!
+//! The luma is reduced to a small grid and thresholded at its mean, each
+//! sizable 4-connected region is then hashed over its bounding box in the
+//! original image. Cropping or adding a banner changes some segments but
+//! leaves the others, and their hashes, intact.
+use crate::grid::check_dimensions;
+use crate::thumbnail::luma_thumbnail;
+use crate::Dhash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fmt, num, str};
+
+/// Side of the segmentation grid
+const GRID: usize = 64;
+/// Segments smaller than this many grid cells are ignored
+const MIN_SEGMENT_CELLS: usize = GRID * GRID / 50;
+
+/// Segment hashes, largest segment first, ties ordered by hash value.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct CropResistantHash(pub Vec<Dhash>);
+
+impl CropResistantHash {
+    pub fn new(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let width = width as usize;
+        let height = height as usize;
+        let channel_count = channel_count as usize;
+
+        // NOTE: Very important, prevents possible segfault
+        if let Err(error) = check_dimensions(bytes.len(), width, height, channel_count) {
+            panic!("{}", error);
+        }
+
+        let luma = luma_thumbnail(bytes, width, height, channel_count, GRID, GRID);
+        let mean = luma.iter().sum::<f64>() / luma.len() as f64;
+        let bright = luma.iter().map(|&l| l > mean).collect::<Vec<_>>();
+
+        let mut segments = Vec::new();
+        let mut visited = vec![false; GRID * GRID];
+
+        for start in 0..GRID * GRID {
+            if visited[start] {
+                continue;
+            }
+
+            visited[start] = true;
+
+            let mut stack = vec![start];
+            let mut cells = 0;
+            let (mut x0, mut y0, mut x1, mut y1) = (GRID, GRID, 0, 0);
+
+            while let Some(i) = stack.pop() {
+                let (x, y) = (i % GRID, i / GRID);
+
+                cells += 1;
+                x0 = x0.min(x);
+                y0 = y0.min(y);
+                x1 = x1.max(x + 1);
+                y1 = y1.max(y + 1);
+
+                let neighbors = [
+                    (x > 0).then(|| i - 1),
+                    (x + 1 < GRID).then(|| i + 1),
+                    (y > 0).then(|| i - GRID),
+                    (y + 1 < GRID).then(|| i + GRID),
+                ];
+
+                for n in neighbors.into_iter().flatten() {
+                    if !visited[n] && bright[n] == bright[start] {
+                        visited[n] = true;
+                        stack.push(n);
+                    }
+                }
+            }
+
+            if cells < MIN_SEGMENT_CELLS {
+                continue;
+            }
+
+            let from_x = x0 * width / GRID;
+            let to_x = x1 * width / GRID;
+            let from_y = y0 * height / GRID;
+            let to_y = y1 * height / GRID;
+
+            // NOTE: the dhash grid needs at least a pixel per cell
+            if to_x - from_x < 9 || to_y - from_y < 8 {
+                continue;
+            }
+
+            let mut crop = Vec::with_capacity((to_x - from_x) * (to_y - from_y) * channel_count);
+
+            for y in from_y..to_y {
+                let row = y * width * channel_count;
+                crop.extend_from_slice(
+                    &bytes[row + from_x * channel_count..row + to_x * channel_count],
+                );
+            }
+
+            let hash = Dhash::new(
+                &crop,
+                (to_x - from_x) as u32,
+                (to_y - from_y) as u32,
+                channel_count as u8,
+            );
+
+            segments.push((cells, hash));
+        }
+
+        segments.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.hash.cmp(&b.1.hash)));
+
+        Self(segments.into_iter().map(|(_, hash)| hash).collect())
+    }
+
+    /// Whether at least `min_segments` segments of `self` have a segment of
+    /// `other` within `max_distance`.
+    pub fn matches(&self, other: &Self, min_segments: usize, max_distance: u32) -> bool {
+        self.0
+            .iter()
+            .filter(|a| {
+                other
+                    .0
+                    .iter()
+                    .any(|b| a.hamming_distance(b) <= max_distance)
+            })
+            .count()
+            >= min_segments
+    }
+}
+
+impl fmt::Display for CropResistantHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, hash) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+
+            write!(f, "{}", hash)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl str::FromStr for CropResistantHash {
+    type Err = num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(Self(Vec::new()));
+        }
+
+        s.split(',')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CropResistantHash;
+    use image::{imageops, DynamicImage, ImageReader, Rgb, RgbImage};
+
+    fn hash(image: &DynamicImage) -> CropResistantHash {
+        CropResistantHash::new(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().channel_count(),
+        )
+    }
+
+    fn radial() -> DynamicImage {
+        ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+    }
+
+    #[test]
+    fn cropped() {
+        let image = radial();
+        let cropped = image.crop_imm(0, 0, image.width() * 85 / 100, image.height());
+
+        assert!(hash(&image).matches(&hash(&cropped), 1, 8));
+    }
+
+    #[test]
+    fn banner() {
+        let image = radial().to_rgb8();
+        let banner_height = image.height() / 5;
+
+        let mut bannered = RgbImage::from_pixel(
+            image.width(),
+            image.height() + banner_height,
+            Rgb([255, 255, 255]),
+        );
+
+        imageops::replace(&mut bannered, &image, 0, banner_height as i64);
+
+        let image = DynamicImage::ImageRgb8(image);
+        let bannered = DynamicImage::ImageRgb8(bannered);
+
+        assert!(hash(&image).matches(&hash(&bannered), 1, 8));
+    }
+
+    #[test]
+    fn unrelated() {
+        let open = |path| {
+            ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image")
+        };
+
+        let image = radial();
+        let cropped = image.crop_imm(0, 0, image.width() * 85 / 100, image.height());
+
+        // NOTE: blocks of pseudo random noise, neither gradients nor rings
+        let (width, height) = (image.width(), image.height());
+        let noise = RgbImage::from_fn(width, height, |x, y| {
+            let mut state = (x / 16 * 7919 + y / 16 * 104_729) | 1;
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            Rgb([state as u8, (state >> 8) as u8, (state >> 16) as u8])
+        });
+
+        let others = [
+            open(".test/grad.0000.jpg"),
+            open(".test/grad.ffff.jpg"),
+            DynamicImage::ImageRgb8(noise),
+        ];
+
+        for other in &others {
+            // NOTE: an empty hash would match nothing, trivially
+            assert!(!hash(other).0.is_empty());
+            assert!(!hash(&image).matches(&hash(other), 1, 8));
+            assert!(!hash(&cropped).matches(&hash(other), 1, 8));
+        }
+    }
+
+    #[test]
+    fn display_from_str() {
+        let hash = hash(&radial());
+
+        assert!(!hash.0.is_empty());
+        assert_eq!(hash.to_string().parse::<CropResistantHash>(), Ok(hash));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid image dimensions, expected 7200 got 0")]
+    fn no_channels() {
+        CropResistantHash::new(&[], 90, 80, 0);
+    }
+}
@@ -0,0 +1,234 @@
+//! Persistent hash storage in an SQLite database.
+use crate::Dhash;
+use rusqlite::{params, params_from_iter, Connection};
+use std::path::Path;
+
+/// The hash is split in this many 16 bits chunks, each one indexed
+const CHUNKS: u32 = 4;
+
+/// Largest per-chunk radius searched through the chunk indexes, past it
+/// the candidates are a large part of the table and a full scan is faster
+const MAX_INDEXED_RADIUS: u32 = 3;
+
+/// Hashes and their metadata stored in the `hashes` table of an SQLite
+/// database, kept between runs.
+///
+/// SQLite has no popcount, so each hash is also split in four indexed 16
+/// bits chunks: two hashes within a distance `t` have at least one chunk
+/// within `t / 4`, and only the rows with such a chunk are compared.
+#[derive(Debug)]
+pub struct DhashDatabase {
+    connection: Connection,
+}
+
+impl DhashDatabase {
+    /// Opens or creates the database at `path`.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Database living in memory, gone once dropped.
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(connection: Connection) -> rusqlite::Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS hashes (
+                hash INTEGER PRIMARY KEY,
+                metadata TEXT,
+                chunk0 INTEGER NOT NULL,
+                chunk1 INTEGER NOT NULL,
+                chunk2 INTEGER NOT NULL,
+                chunk3 INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS hashes_chunk0 ON hashes (chunk0);
+            CREATE INDEX IF NOT EXISTS hashes_chunk1 ON hashes (chunk1);
+            CREATE INDEX IF NOT EXISTS hashes_chunk2 ON hashes (chunk2);
+            CREATE INDEX IF NOT EXISTS hashes_chunk3 ON hashes (chunk3);",
+        )?;
+
+        Ok(Self { connection })
+    }
+
+    /// Stores `hash` with `metadata`, replacing the metadata if the hash is
+    /// already stored.
+    pub fn insert(&self, hash: Dhash, metadata: &str) -> rusqlite::Result<()> {
+        let [c0, c1, c2, c3] = chunks(hash.hash);
+
+        self.connection
+            .prepare_cached(
+                "INSERT OR REPLACE INTO hashes (hash, metadata, chunk0, chunk1, chunk2, chunk3)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )?
+            .execute(params![hash.hash as i64, metadata, c0, c1, c2, c3])?;
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> rusqlite::Result<usize> {
+        self.connection
+            .query_row("SELECT COUNT(*) FROM hashes", [], |row| row.get(0))
+    }
+
+    pub fn is_empty(&self) -> rusqlite::Result<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Stored hashes at a hamming distance of at most `threshold` from
+    /// `query`, inclusive unlike [`SimilarTo`](crate::SimilarTo), and their
+    /// metadata, in no particular order.
+    pub fn find_similar(
+        &self,
+        query: Dhash,
+        threshold: u32,
+    ) -> rusqlite::Result<Vec<(Dhash, String)>> {
+        let radius = threshold / CHUNKS;
+
+        let mut statement;
+        let rows;
+
+        if radius <= MAX_INDEXED_RADIUS {
+            let neighbors = chunks(query.hash).map(|chunk| chunk_neighbors(chunk, radius));
+
+            let filter = neighbors
+                .iter()
+                .enumerate()
+                .map(|(i, values)| format!("chunk{} IN ({})", i, vec!["?"; values.len()].join(",")))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+
+            statement = self.connection.prepare(&format!(
+                "SELECT hash, metadata FROM hashes WHERE {}",
+                filter
+            ))?;
+
+            rows = statement.query(params_from_iter(neighbors.iter().flatten()))?;
+        } else {
+            statement = self
+                .connection
+                .prepare("SELECT hash, metadata FROM hashes")?;
+            rows = statement.query([])?;
+        }
+
+        rows.mapped(|row| {
+            Ok((
+                Dhash {
+                    hash: row.get::<_, i64>(0)? as u64,
+                },
+                row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            ))
+        })
+        .filter(|result| {
+            result
+                .as_ref()
+                .map_or(true, |(hash, _)| hash.hamming_distance(&query) <= threshold)
+        })
+        .collect()
+    }
+}
+
+/// The four 16 bits chunks of a hash, from the least significant.
+fn chunks(hash: u64) -> [u16; 4] {
+    [
+        hash as u16,
+        (hash >> 16) as u16,
+        (hash >> 32) as u16,
+        (hash >> 48) as u16,
+    ]
+}
+
+/// Every 16 bits value within `radius` hamming distance of `chunk`.
+fn chunk_neighbors(chunk: u16, radius: u32) -> Vec<u16> {
+    (0..=u16::MAX)
+        .filter(|value| (value ^ chunk).count_ones() <= radius)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhashDatabase;
+    use crate::Dhash;
+
+    #[test]
+    fn find_similar() {
+        let database = DhashDatabase::open_in_memory().unwrap();
+
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut hashes = Vec::new();
+
+        for i in 0..500 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            // NOTE: every other hash is a near duplicate of the previous one
+            let hash = match i % 2 {
+                0 => state,
+                _ => hashes.last().copied().unwrap_or(0) ^ (state & 0x0101_0101),
+            };
+
+            hashes.push(hash);
+            database.insert(Dhash { hash }, &i.to_string()).unwrap();
+        }
+
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        assert_eq!(database.len().unwrap(), hashes.len());
+
+        let query = Dhash { hash: hashes[42] };
+
+        for threshold in [0, 3, 10, 15, 20, 40, 64] {
+            let mut found = database
+                .find_similar(query, threshold)
+                .unwrap()
+                .into_iter()
+                .map(|(hash, _)| hash.hash)
+                .collect::<Vec<_>>();
+
+            found.sort_unstable();
+
+            let expected = hashes
+                .iter()
+                .copied()
+                .filter(|&hash| Dhash { hash }.hamming_distance(&query) <= threshold)
+                .collect::<Vec<_>>();
+
+            assert_eq!(found, expected, "threshold {}", threshold);
+        }
+
+        database.insert(query, "replaced").unwrap();
+
+        assert_eq!(
+            database.find_similar(query, 0).unwrap(),
+            vec![(query, "replaced".to_string())]
+        );
+        assert_eq!(database.len().unwrap(), hashes.len());
+    }
+
+    #[test]
+    fn reopen() {
+        let path = std::env::temp_dir().join("fast-dhash-database-reopen.sqlite");
+        let _ = std::fs::remove_file(&path);
+
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        DhashDatabase::open(&path)
+            .unwrap()
+            .insert(hash, "radial.jpg")
+            .unwrap();
+
+        let database = DhashDatabase::open(&path).unwrap();
+
+        assert_eq!(
+            database.find_similar(hash, 4).unwrap(),
+            vec![(hash, "radial.jpg".to_string())]
+        );
+
+        drop(database);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+//! Dhash and average hash of the same image, compared together.
+use crate::{
+    family::{HashFamily, HashKinds},
+    AverageHash, Dhash, DhashError,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Weight of the dhash in [`EnsembleHash::distance`].
+pub const DEFAULT_DHASH_WEIGHT: f64 = 0.5;
+
+/// See [`Dhash::ensemble`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct EnsembleHash {
+    pub dhash: Dhash,
+    pub ahash: AverageHash,
+}
+
+impl EnsembleHash {
+    /// Same as [`Dhash::ensemble`], returning an error instead of panicking
+    /// on invalid dimensions.
+    pub fn try_new(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        let family = HashFamily::compute(
+            bytes,
+            width,
+            height,
+            channel_count,
+            HashKinds::DHASH | HashKinds::AHASH,
+        )?;
+
+        Ok(Self {
+            dhash: family.dhash.unwrap_or_default(),
+            ahash: AverageHash {
+                hash: family.ahash.unwrap_or_default().hash,
+            },
+        })
+    }
+
+    /// [`EnsembleHash::weighted_distance`] with [`DEFAULT_DHASH_WEIGHT`].
+    pub fn distance(&self, other: &Self) -> f64 {
+        self.weighted_distance(other, DEFAULT_DHASH_WEIGHT)
+    }
+
+    /// Hamming distance of the dhashes weighted by `dhash_weight`, plus the
+    /// one of the average hashes weighted by `1 - dhash_weight`, in
+    /// `[0, 64]` for a weight in `[0, 1]`.
+    ///
+    /// A pair must be close in both hashes to score low, which rejects the
+    /// pairs only one of the algorithms confuses.
+    pub fn weighted_distance(&self, other: &Self, dhash_weight: f64) -> f64 {
+        self.dhash.hamming_distance(&other.dhash) as f64 * dhash_weight
+            + self.ahash.hamming_distance(&other.ahash) as f64 * (1.0 - dhash_weight)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EnsembleHash;
+    use crate::{AverageHash, Dhash};
+    use image::ImageReader;
+
+    #[test]
+    fn ensemble() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+
+        for (bytes, channel_count) in [
+            (image.to_rgb8().into_raw(), 3),
+            (image.to_luma_alpha8().into_raw(), 2),
+            (image.to_luma8().into_raw(), 1),
+        ] {
+            let hash = Dhash::ensemble(&bytes, width, height, channel_count);
+
+            assert_eq!(hash.dhash, Dhash::new(&bytes, width, height, channel_count));
+            assert_eq!(
+                hash.ahash,
+                AverageHash::new(&bytes, width, height, channel_count)
+            );
+            assert_eq!(hash.distance(&hash), 0.0);
+        }
+
+        let a = EnsembleHash {
+            dhash: Dhash { hash: 0 },
+            ahash: AverageHash { hash: 0 },
+        };
+        let b = EnsembleHash {
+            dhash: Dhash { hash: 0xff },
+            ahash: AverageHash { hash: 0xf },
+        };
+
+        assert_eq!(a.distance(&b), 6.0);
+        assert_eq!(a.weighted_distance(&b, 1.0), 8.0);
+        assert_eq!(a.weighted_distance(&b, 0.0), 4.0);
+        assert!(EnsembleHash::try_new(&[0; 10], 90, 80, 1).is_err());
+    }
+}
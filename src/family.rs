@@ -0,0 +1,369 @@
+//! Several hash variants from a single scan of the image.
+use crate::{
+    grid::{
+        channels_luma, check_dimensions, map_rows, pixel_channels, scan_cells, workers_for,
+        ScanOrder, PARALLEL_THRESHOLD,
+    },
+    Dhash, DhashError, Grid,
+};
+use std::ops;
+
+/// Set of hash variants, combine them with `|`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HashKinds(u8);
+
+impl HashKinds {
+    /// [`Dhash::new`]
+    pub const DHASH: Self = Self(1);
+    /// [`Dhash::new_vertical`]
+    pub const VERTICAL: Self = Self(1 << 1);
+    /// [`Dhash::new_average`]
+    pub const AHASH: Self = Self(1 << 2);
+    /// [`Dhash::new_median`]
+    pub const MEDIAN: Self = Self(1 << 3);
+    pub const ALL: Self = Self(0b1111);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl ops::BitOr for HashKinds {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+/// The requested hash variants, each equal to its standalone computation,
+/// except for LumaA input: composited on white, its pixels are not
+/// integers, and cells summed from parts round differently than cells
+/// summed whole, so a near tie between two cells can flip a bit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HashFamily {
+    pub dhash: Option<Dhash>,
+    pub vertical: Option<Dhash>,
+    pub ahash: Option<Dhash>,
+    pub median: Option<Dhash>,
+}
+
+impl HashFamily {
+    /// Scans the image once, whatever the number of requested variants.
+    ///
+    /// The pixels are summed over the cells delimited by the boundaries of
+    /// all the requested grids, each grid is then an exact union of them.
+    /// The bands of rows between boundaries are spread over threads as with
+    /// [`Dhash::new`], and not for images under
+    /// [`PARALLEL_THRESHOLD`] pixels.
+    pub fn compute(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+        which: HashKinds,
+    ) -> Result<Self, DhashError> {
+        let width = width as usize;
+        let height = height as usize;
+        let channel_count = channel_count as usize;
+
+        // NOTE: Very important, prevents possible segfault
+        check_dimensions(bytes.len(), width, height, channel_count)?;
+
+        // NOTE: (columns, rows) of each variant's grid
+        let grids = [
+            (HashKinds::DHASH, (9, 8)),
+            (HashKinds::VERTICAL, (8, 9)),
+            (HashKinds::AHASH, (8, 8)),
+            (HashKinds::MEDIAN, (8, 8)),
+        ]
+        .into_iter()
+        .filter(|&(kind, _)| which.contains(kind))
+        .map(|(_, size)| size)
+        .collect::<Vec<_>>();
+
+        let cuts = |size: usize, parts: &mut dyn Iterator<Item = usize>| {
+            let mut cuts = parts
+                .flat_map(|n| (0..=n).map(move |k| k * (size / n)))
+                .collect::<Vec<_>>();
+
+            cuts.sort_unstable();
+            cuts.dedup();
+            cuts
+        };
+
+        let col_cuts = cuts(width, &mut grids.iter().map(|&(w, _)| w));
+        let row_cuts = cuts(height, &mut grids.iter().map(|&(_, h)| h));
+
+        // NOTE: the pixels of each band between row cuts are summed over
+        // the cells between column cuts, in the order of Dhash::new
+        let order = match channel_count {
+            2 => ScanOrder::Columns,
+            _ => ScanOrder::Rows,
+        };
+        let workers = workers_for(width * height, None, PARALLEL_THRESHOLD);
+
+        let sums = map_rows(row_cuts.len().saturating_sub(1), workers, |band| {
+            let mut sums = vec![[0f64; 3]; col_cuts.len().saturating_sub(1)];
+
+            scan_cells(
+                width,
+                &col_cuts,
+                &row_cuts[band..band + 2],
+                1,
+                order,
+                |c, run| {
+                    let [mut rs, mut gs, mut bs] = sums[c];
+
+                    for i in run {
+                        let [r, g, b] = pixel_channels(bytes, channel_count, i);
+
+                        rs += r;
+                        gs += g;
+                        bs += b;
+                    }
+
+                    sums[c] = [rs, gs, bs];
+                },
+            );
+
+            sums
+        });
+
+        let derive = |columns: usize, rows: usize, x: usize, y: usize| {
+            let (cell_width, cell_height) = (width / columns, height / rows);
+
+            // NOTE: the bounds of every requested grid are cuts, see `cuts`
+            let index = |cuts: &[usize], cut: usize| {
+                cuts.binary_search(&cut)
+                    .expect("cell bounds are always cuts")
+            };
+            let span = |cuts: &[usize], from: usize, to: usize| index(cuts, from)..index(cuts, to);
+
+            let col_span = span(&col_cuts, x * cell_width, (x + 1) * cell_width);
+            let row_span = span(&row_cuts, y * cell_height, (y + 1) * cell_height);
+
+            let mut rgb = [0f64; 3];
+
+            for r in row_span {
+                for c in col_span.clone() {
+                    for (channel, sum) in rgb.iter_mut().zip(sums[r][c]) {
+                        *channel += sum;
+                    }
+                }
+            }
+
+            let mut cell = channels_luma(rgb, channel_count);
+
+            let cell_size = (cell_width * cell_height) as f64;
+
+            if cell_size > 0.0 {
+                cell /= cell_size;
+            }
+
+            cell
+        };
+
+        let mut family = Self::default();
+
+        if which.contains(HashKinds::DHASH) {
+            let mut grid = [[0f64; 9]; 8];
+
+            for (y, row) in grid.iter_mut().enumerate() {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    *cell = derive(9, 8, x, y);
+                }
+            }
+
+            family.dhash = Some(Dhash::from_grid(&Grid::new(grid)));
+        }
+
+        if which.contains(HashKinds::VERTICAL) {
+            let mut grid = [[0f64; 8]; 9];
+
+            for (y, row) in grid.iter_mut().enumerate() {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    *cell = derive(8, 9, x, y);
+                }
+            }
+
+            family.vertical = Some(Dhash {
+                hash: vertical_bits(&grid),
+            });
+        }
+
+        if which.contains(HashKinds::AHASH) || which.contains(HashKinds::MEDIAN) {
+            let mut grid = [[0f64; 8]; 8];
+
+            for (y, row) in grid.iter_mut().enumerate() {
+                for (x, cell) in row.iter_mut().enumerate() {
+                    *cell = derive(8, 8, x, y);
+                }
+            }
+
+            if which.contains(HashKinds::AHASH) {
+                family.ahash = Some(Dhash {
+                    hash: average_bits(&grid),
+                });
+            }
+
+            if which.contains(HashKinds::MEDIAN) {
+                family.median = Some(Dhash {
+                    hash: median_bits(&grid),
+                });
+            }
+        }
+
+        Ok(family)
+    }
+}
+
+/// Sets each bit if the cell is brighter than the one below it.
+pub(crate) fn vertical_bits(grid: &[[f64; 8]; 9]) -> u64 {
+    let mut hash = 0u64;
+
+    for (y, rows) in grid.windows(2).enumerate() {
+        for (x, (above, below)) in rows[0].iter().zip(&rows[1]).enumerate() {
+            if above > below {
+                hash |= 1 << (y * 8 + x);
+            }
+        }
+    }
+
+    hash
+}
+
+/// Sets each bit if the cell is brighter than the grid mean.
+pub(crate) fn average_bits(grid: &[[f64; 8]; 8]) -> u64 {
+    let mean = grid.iter().flatten().sum::<f64>() / 64.0;
+
+    threshold_bits(grid, mean)
+}
+
+/// Sets each bit if the cell is brighter than the grid median.
+pub(crate) fn median_bits(grid: &[[f64; 8]; 8]) -> u64 {
+    let mut sorted = grid.concat();
+    sorted.sort_by(f64::total_cmp);
+
+    threshold_bits(grid, (sorted[31] + sorted[32]) / 2.0)
+}
+
+fn threshold_bits(grid: &[[f64; 8]; 8], threshold: f64) -> u64 {
+    let mut hash = 0u64;
+
+    for (i, &cell) in grid.iter().flatten().enumerate() {
+        if cell > threshold {
+            hash |= 1 << i;
+        }
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HashFamily, HashKinds};
+    use crate::Dhash;
+    use image::ImageReader;
+
+    #[test]
+    fn standalone() {
+        for path in [
+            ".test/grad.ffff.jpg",
+            ".test/grad.0000.jpg",
+            ".test/radial.jpg",
+        ] {
+            let image = ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image");
+
+            let bytes = image.as_bytes();
+            let (width, height) = (image.width(), image.height());
+            let channel_count = image.color().channel_count();
+
+            let family = HashFamily::compute(bytes, width, height, channel_count, HashKinds::ALL)
+                .expect("invalid dimensions");
+
+            assert_eq!(
+                family.dhash,
+                Some(Dhash::new(bytes, width, height, channel_count))
+            );
+            assert_eq!(
+                family.vertical,
+                Some(Dhash::new_vertical(bytes, width, height, channel_count))
+            );
+            assert_eq!(
+                family.ahash,
+                Some(Dhash::new_average(bytes, width, height, channel_count))
+            );
+            assert_eq!(
+                family.median,
+                Some(Dhash::new_median(bytes, width, height, channel_count))
+            );
+
+            let family = HashFamily::compute(
+                bytes,
+                width,
+                height,
+                channel_count,
+                HashKinds::VERTICAL | HashKinds::AHASH,
+            )
+            .expect("invalid dimensions");
+
+            assert!(family.dhash.is_none() && family.median.is_none());
+            assert!(family.vertical.is_some() && family.ahash.is_some());
+        }
+    }
+
+    #[test]
+    fn standalone_transparent() {
+        let mut image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+            .to_luma_alpha8();
+
+        // NOTE: alpha varying within every cell, composited on white
+        for (x, y, pixel) in image.enumerate_pixels_mut() {
+            pixel[1] = ((x * 7 + y * 13) % 256) as u8;
+        }
+
+        let (width, height) = image.dimensions();
+        let bytes = image.as_raw();
+
+        let family = HashFamily::compute(bytes, width, height, 2, HashKinds::ALL)
+            .expect("invalid dimensions");
+
+        // NOTE: the sums are grouped differently, only near ties can differ
+        for (derived, standalone) in [
+            (family.dhash, Dhash::new(bytes, width, height, 2)),
+            (
+                family.vertical,
+                Dhash::new_vertical(bytes, width, height, 2),
+            ),
+            (family.ahash, Dhash::new_average(bytes, width, height, 2)),
+            (family.median, Dhash::new_median(bytes, width, height, 2)),
+        ] {
+            assert!(derived.unwrap().hamming_distance(&standalone) <= 1);
+        }
+
+        // NOTE: fully transparent black reads as the white background
+        let transparent = (0..width * height)
+            .flat_map(|i| [0, if i % width < width / 2 { 0 } else { 255 }])
+            .collect::<Vec<u8>>();
+        let composited = transparent
+            .chunks_exact(2)
+            .map(|pixel| if pixel[1] == 0 { 255 } else { pixel[0] })
+            .collect::<Vec<u8>>();
+
+        assert_eq!(
+            HashFamily::compute(&transparent, width, height, 2, HashKinds::ALL),
+            HashFamily::compute(&composited, width, height, 1, HashKinds::ALL),
+        );
+        assert_ne!(
+            HashFamily::compute(&transparent, width, height, 2, HashKinds::ALL),
+            HashFamily::compute(&vec![0; bytes.len() / 2], width, height, 1, HashKinds::ALL),
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Opening, decoding and hashing an image file, or every frame of an
+//! animation, in one call.
+use crate::{Dhash, DhashError};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, Delay, DynamicImage, ImageError, ImageFormat, ImageReader,
+};
+use std::{
+    error, fmt,
+    io::{self, BufRead, Seek},
+    path::Path,
+    time::Duration,
+};
+
+/// Error of [`hash_file`] and [`hash_frames`], by step
+#[derive(Debug)]
+pub enum HashFileError {
+    /// Opening or reading the file failed
+    Io(io::Error),
+    /// The format is unknown or the image is malformed
+    Decode(ImageError),
+    /// The decoded image could not be hashed
+    Hash(DhashError),
+}
+
+impl fmt::Display for HashFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::Decode(error) => write!(f, "{}", error),
+            Self::Hash(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for HashFileError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Decode(error) => Some(error),
+            Self::Hash(error) => Some(error),
+        }
+    }
+}
+
+/// Opens, decodes and hashes the image at `path` with
+/// [`Dhash::try_from_image`].
+///
+/// The format is guessed from the content of the file, so images with a
+/// wrong or missing extension are still hashed.
+pub fn hash_file(path: impl AsRef<Path>) -> Result<Dhash, HashFileError> {
+    let image = ImageReader::open(path)
+        .and_then(ImageReader::with_guessed_format)
+        .map_err(HashFileError::Io)?
+        .decode()
+        .map_err(HashFileError::Decode)?;
+
+    Dhash::try_from_image(&image).map_err(HashFileError::Hash)
+}
+
+/// Decodes and hashes every frame of an animated GIF, WebP or APNG, with
+/// its delay.
+///
+/// Frames are hashed as displayed, partial frames being composited over
+/// the canvas the previous ones left according to their disposal method.
+/// Any other image, including a still WebP or PNG, is a single frame
+/// without delay.
+pub fn hash_frames<R: BufRead + Seek>(reader: R) -> Result<Vec<(Dhash, Delay)>, HashFileError> {
+    let reader = ImageReader::new(reader)
+        .with_guessed_format()
+        .map_err(HashFileError::Io)?;
+
+    // NOTE: the frame iterators of the image crate yield the composited
+    // canvas rather than the partial frames
+    let frames = match reader.format() {
+        Some(ImageFormat::Gif) => GifDecoder::new(reader.into_inner())
+            .map_err(HashFileError::Decode)?
+            .into_frames(),
+        Some(ImageFormat::WebP) => {
+            let decoder = WebPDecoder::new(reader.into_inner()).map_err(HashFileError::Decode)?;
+
+            if !decoder.has_animation() {
+                return hash_still(DynamicImage::from_decoder(decoder));
+            }
+
+            decoder.into_frames()
+        }
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader.into_inner()).map_err(HashFileError::Decode)?;
+
+            if !decoder.is_apng().map_err(HashFileError::Decode)? {
+                return hash_still(DynamicImage::from_decoder(decoder));
+            }
+
+            decoder.apng().map_err(HashFileError::Decode)?.into_frames()
+        }
+        _ => return hash_still(reader.decode()),
+    };
+
+    frames
+        .map(|frame| {
+            let frame = frame.map_err(HashFileError::Decode)?;
+            let delay = frame.delay();
+            let buffer = frame.into_buffer();
+
+            let hash = Dhash::try_new(buffer.as_raw(), buffer.width(), buffer.height(), 4)
+                .map_err(HashFileError::Hash)?;
+
+            Ok((hash, delay))
+        })
+        .collect()
+}
+
+/// Like [`hash_frames`], merging consecutive frames with the same hash
+/// into the first one, displayed for the sum of their delays.
+pub fn hash_unique_frames<R: BufRead + Seek>(
+    reader: R,
+) -> Result<Vec<(Dhash, Delay)>, HashFileError> {
+    let mut frames: Vec<(Dhash, Delay)> = Vec::new();
+
+    for (hash, delay) in hash_frames(reader)? {
+        match frames.last_mut() {
+            Some((last, last_delay)) if *last == hash => {
+                *last_delay = Delay::from_saturating_duration(
+                    Duration::from(*last_delay) + Duration::from(delay),
+                );
+            }
+            _ => frames.push((hash, delay)),
+        }
+    }
+
+    Ok(frames)
+}
+
+fn hash_still(
+    image: Result<DynamicImage, ImageError>,
+) -> Result<Vec<(Dhash, Delay)>, HashFileError> {
+    let image = image.map_err(HashFileError::Decode)?;
+    let hash = Dhash::try_from_image(&image).map_err(HashFileError::Hash)?;
+
+    Ok(vec![(hash, Delay::from_numer_denom_ms(0, 1))])
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_file, hash_frames, hash_unique_frames, HashFileError};
+    use std::{env, fs, io::Cursor, time::Duration};
+
+    #[test]
+    fn reference_hashes() {
+        assert_eq!(
+            hash_file(".test/grad.ffff.jpg").unwrap().hash,
+            0xffffffffffffffff
+        );
+        assert_eq!(
+            hash_file(".test/grad.0000.jpg").unwrap().hash,
+            0x0000000000000000
+        );
+        assert_eq!(
+            hash_file(".test/radial.jpg").unwrap().hash,
+            0xf0f0e8cccce8f0f0
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let dir = env::temp_dir().join(format!("fast-dhash-file-{}", std::process::id()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        // NOTE: a jpeg with a png extension
+        let mislabeled = dir.join("radial.png");
+        let broken = dir.join("broken.jpg");
+
+        fs::copy(".test/radial.jpg", &mislabeled).unwrap();
+        fs::write(&broken, "not a jpeg").unwrap();
+
+        assert_eq!(hash_file(&mislabeled).unwrap().hash, 0xf0f0e8cccce8f0f0);
+        assert!(matches!(hash_file(&broken), Err(HashFileError::Decode(_))));
+        assert!(matches!(
+            hash_file(dir.join("missing.jpg")),
+            Err(HashFileError::Io(_))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn delays_ms(frames: &[(crate::Dhash, image::Delay)]) -> Vec<u128> {
+        frames
+            .iter()
+            .map(|&(_, delay)| Duration::from(delay).as_millis())
+            .collect()
+    }
+
+    #[test]
+    fn frames() {
+        // NOTE: the gif draws partial frames, one with transparent pixels
+        // and one restoring the previous canvas, the png is its re-encoding
+        // with the displayed canvases as full frames
+        let gif = hash_frames(Cursor::new(fs::read(".test/anim.gif").unwrap())).unwrap();
+        let apng = hash_frames(Cursor::new(fs::read(".test/anim.png").unwrap())).unwrap();
+
+        assert_eq!(gif.len(), 5);
+        assert_eq!(delays_ms(&gif), [100, 50, 50, 200, 200]);
+        assert_eq!(delays_ms(&apng), delays_ms(&gif));
+
+        for ((gif, _), (apng, _)) in gif.iter().zip(&apng) {
+            assert_eq!(gif, apng);
+        }
+
+        // NOTE: the last frame draws the same pixels as the one before
+        let unique = hash_unique_frames(Cursor::new(fs::read(".test/anim.gif").unwrap())).unwrap();
+
+        assert_eq!(delays_ms(&unique), [100, 50, 50, 400]);
+        assert_eq!(unique[3].0, gif[4].0);
+
+        let still = hash_frames(Cursor::new(fs::read(".test/radial.jpg").unwrap())).unwrap();
+
+        assert_eq!(still.len(), 1);
+        assert_eq!(still[0].0.hash, 0xf0f0e8cccce8f0f0);
+    }
+}
@@ -0,0 +1,148 @@
+//! Chains of hash based predicates, e.g. for content moderation.
+use crate::Dhash;
+
+/// Rules tested in the order they were added, the first one matching a
+/// candidate decides the [`FilterResult`].
+///
+/// ```
+/// use fast_dhash::{Dhash, DhashFilter, FilterResult};
+///
+/// let banned = Dhash { hash: 0xf0f0e8cccce8f0f0 };
+///
+/// let filter = DhashFilter::block_exact(Dhash { hash: 0 })
+///     .or_block_similar(banned, 4)
+///     .or_block_density_below(0.1);
+///
+/// let candidate = Dhash { hash: banned.hash ^ 0b11 };
+///
+/// assert_eq!(
+///     filter.test(candidate),
+///     FilterResult::Similar { rule: 1, distance: 2 }
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DhashFilter {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Rule {
+    Exact(Dhash),
+    Similar(Dhash, u32),
+    DensityBelow(f64),
+}
+
+/// The rule a candidate matched, `rule` being its index in the order the
+/// rules were added.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterResult {
+    /// No rule matched
+    Clear,
+    Exact {
+        rule: usize,
+    },
+    Similar {
+        rule: usize,
+        distance: u32,
+    },
+    DensityBelow {
+        rule: usize,
+        density: f64,
+    },
+}
+
+impl DhashFilter {
+    /// Filter blocking `hash` itself.
+    pub fn block_exact(hash: Dhash) -> Self {
+        Self::default().or_block_exact(hash)
+    }
+
+    pub fn or_block_exact(mut self, hash: Dhash) -> Self {
+        self.rules.push(Rule::Exact(hash));
+        self
+    }
+
+    /// Also blocks the hashes at a hamming distance of at most `threshold`
+    /// from `hash`, inclusive unlike [`SimilarTo`](crate::SimilarTo).
+    pub fn or_block_similar(mut self, hash: Dhash, threshold: u32) -> Self {
+        self.rules.push(Rule::Similar(hash, threshold));
+        self
+    }
+
+    /// Also blocks the hashes with a [`Dhash::bit_density`] strictly below
+    /// `min`, e.g. images too flat to be meaningfully compared.
+    pub fn or_block_density_below(mut self, min: f64) -> Self {
+        self.rules.push(Rule::DensityBelow(min));
+        self
+    }
+
+    /// The first rule `candidate` matches, a distance equal to the
+    /// threshold of a similarity rule matches it.
+    pub fn test(&self, candidate: Dhash) -> FilterResult {
+        for (rule, &kind) in self.rules.iter().enumerate() {
+            match kind {
+                Rule::Exact(hash) if hash == candidate => return FilterResult::Exact { rule },
+                Rule::Similar(hash, threshold) => {
+                    let distance = hash.hamming_distance(&candidate);
+
+                    if distance <= threshold {
+                        return FilterResult::Similar { rule, distance };
+                    }
+                }
+                Rule::DensityBelow(min) => {
+                    let density = candidate.bit_density();
+
+                    if density < min {
+                        return FilterResult::DensityBelow { rule, density };
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        FilterResult::Clear
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhashFilter, FilterResult};
+    use crate::Dhash;
+
+    #[test]
+    fn filter() {
+        let banned = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        let filter = DhashFilter::block_exact(banned)
+            .or_block_similar(banned, 3)
+            .or_block_density_below(0.25);
+
+        assert_eq!(filter.test(banned), FilterResult::Exact { rule: 0 });
+        // NOTE: the threshold is inclusive, 3 matches and 4 does not
+        assert_eq!(
+            filter.test(Dhash {
+                hash: banned.hash ^ 0b111
+            }),
+            FilterResult::Similar {
+                rule: 1,
+                distance: 3
+            }
+        );
+        assert_eq!(
+            filter.test(Dhash { hash: 0xff }),
+            FilterResult::DensityBelow {
+                rule: 2,
+                density: 0.125
+            }
+        );
+        assert_eq!(
+            filter.test(Dhash {
+                hash: banned.hash ^ 0b1111
+            }),
+            FilterResult::Clear
+        );
+        assert_eq!(DhashFilter::default().test(banned), FilterResult::Clear);
+    }
+}
@@ -0,0 +1,1596 @@
+//! The luma grid the hash is computed from.
+use crate::{hasher::WorkerPool, CellAggregation, ChannelOrder, DhashError, RoundingMode};
+#[cfg(feature = "image")]
+use image::{GenericImageView, Pixel, Primitive};
+#[cfg(feature = "image")]
+use num_traits::ToPrimitive;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(not(feature = "rayon"))]
+use std::thread;
+use std::{fmt, iter, ops::Range};
+
+/// Number of pixels under which grids are computed on the calling thread.
+///
+/// Set from `bench_parallel_threshold` on a single core, RGB images:
+///
+/// | pixels    | inline  | thread per row |
+/// |-----------|---------|----------------|
+/// | 4,096     | 11µs    | 201µs          |
+/// | 65,536    | 117µs   | 283µs          |
+/// | 99,856    | 233µs   | 418µs          |
+/// | 1,048,576 | 2.1ms   | 2.4ms          |
+///
+/// Spawning and joining the 8 threads costs about 190µs, reading takes
+/// about 2.2ns per pixel. Split over 8 cores the threads start to win once
+/// reading takes 8/7 of the spawn cost, at about 100,000 pixels, with fewer
+/// cores the crossover is higher, up to about 170,000 pixels on 2. The
+/// multi-core crossover follows from these timings, it was not measured on
+/// more than one core, tune it with
+/// [`DhashBuilder::parallel_threshold`](crate::DhashBuilder::parallel_threshold).
+pub const PARALLEL_THRESHOLD: usize = 100_000;
+
+/// Mean luma of the 9x8 cells the image is divided in, each of the 64 bits
+/// of a [`Dhash`](crate::Dhash) compares a cell with its right neighbor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    cells: [[f64; 9]; 8],
+}
+
+impl Grid {
+    pub fn new(cells: [[f64; 9]; 8]) -> Self {
+        Self { cells }
+    }
+
+    /// Cell at column `x` and row `y`.
+    pub fn get(&self, x: usize, y: usize) -> Option<f64> {
+        self.cells.get(y).and_then(|row| row.get(x)).copied()
+    }
+
+    pub fn rows(&self) -> &[[f64; 9]; 8] {
+        &self.cells
+    }
+
+    /// Cells rescaled to `[0, 1]`, from the darkest to the brightest, a flat
+    /// grid is all zeros.
+    pub fn normalized(&self) -> Self {
+        let cells = self.cells.iter().flatten();
+
+        let min = cells.clone().cloned().fold(f64::MAX, f64::min);
+        let max = cells.cloned().fold(f64::MIN, f64::max);
+
+        let mut normalized = *self;
+
+        for cell in normalized.cells.iter_mut().flatten() {
+            *cell = if max > min {
+                (*cell - min) / (max - min)
+            } else {
+                0.0
+            };
+        }
+
+        normalized
+    }
+
+    /// The cell means as a 9x8 grayscale image, rounded and clamped to
+    /// `u8`, a set bit of the hash is a pixel brighter than its right
+    /// neighbor.
+    pub fn to_thumbnail(&self) -> [[u8; 9]; 8] {
+        self.cells
+            .map(|row| row.map(|cell| cell.round().clamp(0.0, u8::MAX as f64) as u8))
+    }
+}
+
+/// Aligned table of the cell means rounded to integers, one line per row.
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cells = self
+            .cells
+            .iter()
+            .flatten()
+            .map(|cell| format!("{:.0}", cell))
+            .collect::<Vec<_>>();
+
+        let width = cells.iter().map(String::len).max().unwrap_or(0);
+
+        for (i, cell) in cells.iter().enumerate() {
+            match i % 9 {
+                0 if i > 0 => writeln!(f)?,
+                0 => {}
+                _ => write!(f, " ")?,
+            }
+
+            write!(f, "{:>width$}", cell)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Computes the [`Grid`] of an image, same inputs as
+/// [`Dhash::new`](crate::Dhash::new).
+pub fn compute_grid(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    channel_count: u8,
+) -> Result<Grid, DhashError> {
+    luma_grid(bytes, width, height, channel_count, CellAggregation::Sum).map(|cells| Grid { cells })
+}
+
+/// Luma statistics gathered while computing a [`Grid`], to flag flat, dark
+/// or otherwise low-confidence hashes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GridStats {
+    /// Mean luma of each cell, the same values as the [`Grid`].
+    pub means: [[f64; 9]; 8],
+    /// Luma variance of the pixels of each cell.
+    pub variances: [[f64; 9]; 8],
+    /// Darkest pixel luma.
+    pub min: f64,
+    /// Brightest pixel luma.
+    pub max: f64,
+    /// Mean luma of the whole grid.
+    pub mean: f64,
+    /// Number of pixels at each luma, rounded, only the pixels within the
+    /// cells are counted.
+    pub histogram: [u32; 256],
+}
+
+impl GridStats {
+    /// Variance of the luma of all the pixels of the grid, both within and
+    /// between cells.
+    pub fn variance(&self) -> f64 {
+        let cells = self
+            .means
+            .iter()
+            .flatten()
+            .zip(self.variances.iter().flatten());
+
+        cells
+            .map(|(mean, variance)| variance + (mean - self.mean).powi(2))
+            .sum::<f64>()
+            / 72.0
+    }
+
+    /// From `1.0` for a uniform image to `0.0` for the highest possible
+    /// contrast, half black and half white, the luma standard deviation
+    /// rescaled.
+    pub fn flatness_score(&self) -> f64 {
+        (1.0 - self.variance().sqrt() / (u8::MAX as f64 / 2.0)).clamp(0.0, 1.0)
+    }
+}
+
+/// Computes the [`Grid`] of an image along with its [`GridStats`] in the
+/// same pass.
+pub fn compute_grid_with_stats(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    channel_count: u8,
+) -> Result<(Grid, GridStats), DhashError> {
+    let width = width as usize;
+    let height = height as usize;
+    let channel_count = channel_count as usize;
+
+    // NOTE: Very important, prevents possible segfault
+    check_dimensions(bytes.len(), width, height, channel_count)?;
+
+    // NOTE: the pixels are read in the order of `compute_grid`, and RGB
+    // channels summed separately as in `grid_from_rgb`, so that the means
+    // are its cells bit for bit
+    let stats = if channel_count >= 3 {
+        stats_from_pixels(
+            width,
+            height,
+            ScanOrder::Rows,
+            |[r, g, b]| r * 0.299 + g * 0.587 + b * 0.114,
+            |i| {
+                let i = i * channel_count;
+
+                // SAFETY: the pixel index is below `width * height`, see
+                // `grid_from_rgb`, there are `channel_count >= 3` bytes per
+                // pixel as checked above, so `i + 2` is within the pixel
+                unsafe {
+                    [
+                        *bytes.get_unchecked(i) as f64,
+                        *bytes.get_unchecked(i + 1) as f64,
+                        *bytes.get_unchecked(i + 2) as f64,
+                    ]
+                }
+            },
+        )
+    } else if channel_count == 2 {
+        stats_from_pixels(
+            width,
+            height,
+            ScanOrder::Columns,
+            |[luma, ..]| luma,
+            |i| {
+                let i = i * 2;
+
+                // SAFETY: the pixel index is below `width * height` and there
+                // are exactly 2 bytes per pixel as checked above
+                let (luma, alpha) = unsafe {
+                    (
+                        *bytes.get_unchecked(i) as f64,
+                        *bytes.get_unchecked(i + 1) as f64,
+                    )
+                };
+
+                let max = u8::MAX as f64;
+
+                [composite_on_white(luma, alpha, max), 0.0, 0.0]
+            },
+        )
+    } else {
+        stats_from_pixels(
+            width,
+            height,
+            ScanOrder::Rows,
+            |[luma, ..]| luma,
+            |i| {
+                // SAFETY: the pixel index is below `width * height` and there
+                // is at least one byte per pixel, 0 channels being rejected above
+                let luma = unsafe { *bytes.get_unchecked(i * channel_count) as f64 };
+
+                [luma, 0.0, 0.0]
+            },
+        )
+    };
+
+    Ok((Grid { cells: stats.means }, stats))
+}
+
+/// Single pass over the cells of the hash accumulating the sums of the
+/// channels, the sum of squares and the extremes of the luma, `pixel` as
+/// in [`grid_from_rgb`], the pixels read in `order`.
+///
+/// `luma` combines the channels of a pixel, it must be linear, as it also
+/// combines the sums of a cell into its mean.
+fn stats_from_pixels<L, P>(
+    width: usize,
+    height: usize,
+    order: ScanOrder,
+    luma: L,
+    pixel: P,
+) -> GridStats
+where
+    L: Fn([f64; 3]) -> f64 + Sync,
+    P: Fn(usize) -> [f64; 3] + Sync,
+{
+    let mut stats = GridStats {
+        means: [[0.0; 9]; 8],
+        variances: [[0.0; 9]; 8],
+        min: f64::MAX,
+        max: f64::MIN,
+        mean: 0.0,
+        histogram: [0; 256],
+    };
+
+    let col_bounds = RoundingMode::Floor.bounds(9, width);
+    let row_bounds = RoundingMode::Floor.bounds(8, height);
+    let cell_size = ((width / 9) * (height / 8)) as f64;
+
+    let workers = workers_for(width * row_bounds[8], None, PARALLEL_THRESHOLD);
+
+    let rows = map_rows(8, workers, |y| {
+        let mut sums = [[0f64; 3]; 9];
+        let mut squares = [0f64; 9];
+        let mut min = f64::MAX;
+        let mut max = f64::MIN;
+        // NOTE: one histogram per row, merged afterwards
+        let mut histogram = [0u32; 256];
+
+        let rows = &row_bounds[y..y + 2];
+
+        scan_cells(width, &col_bounds, rows, 1, order, |x, run| {
+            let [mut rs, mut gs, mut bs] = sums[x];
+
+            for i in run {
+                let [r, g, b] = pixel(i);
+                let value = luma([r, g, b]);
+
+                rs += r;
+                gs += g;
+                bs += b;
+                squares[x] += value * value;
+                min = min.min(value);
+                max = max.max(value);
+                histogram[value.round().clamp(0.0, 255.0) as usize] += 1;
+            }
+
+            sums[x] = [rs, gs, bs];
+        });
+
+        let mut means = [0f64; 9];
+        let mut variances = [0f64; 9];
+
+        if cell_size > 0.0 {
+            for x in 0..9 {
+                means[x] = luma(sums[x]) / cell_size;
+                // NOTE: rounding can make it slightly negative
+                variances[x] = (squares[x] / cell_size - means[x] * means[x]).max(0.0);
+            }
+        }
+
+        (means, variances, min, max, histogram)
+    });
+
+    for (y, (means, variances, min, max, histogram)) in rows.into_iter().enumerate() {
+        stats.means[y] = means;
+        stats.variances[y] = variances;
+        stats.min = stats.min.min(min);
+        stats.max = stats.max.max(max);
+
+        for (total, count) in stats.histogram.iter_mut().zip(histogram) {
+            *total += count;
+        }
+    }
+
+    if cell_size == 0.0 {
+        stats.min = 0.0;
+        stats.max = 0.0;
+    }
+
+    stats.mean = stats.means.iter().flatten().sum::<f64>() / 72.0;
+
+    stats
+}
+
+/// Computes the [`Grid`] of an image and returns it as a 9x8 grayscale
+/// thumbnail, see [`Grid::to_thumbnail`].
+pub fn compute_thumbnail(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    channel_count: u8,
+) -> Result<[[u8; 9]; 8], DhashError> {
+    compute_grid(bytes, width, height, channel_count).map(|grid| grid.to_thumbnail())
+}
+
+/// The grid of the image rotated by 90° clockwise, the 9x8 grid of the hash
+/// becoming 9 rows of 8 cells and back.
+pub fn rotate_grid_90<const W: usize, const H: usize>(grid: &[[f64; W]; H]) -> [[f64; H]; W] {
+    // NOTE: the top row of the rotated image is the left column of the
+    // image read from the bottom
+    std::array::from_fn(|y| std::array::from_fn(|x| grid[H - 1 - x][y]))
+}
+
+/// The grid of the image mirrored left to right.
+pub fn flip_grid_horizontal<const W: usize, const H: usize>(grid: &[[f64; W]; H]) -> [[f64; W]; H] {
+    grid.map(|mut row| {
+        row.reverse();
+        row
+    })
+}
+
+/// The grid of the image mirrored top to bottom.
+pub fn flip_grid_vertical<const W: usize, const H: usize>(grid: &[[f64; W]; H]) -> [[f64; W]; H] {
+    let mut grid = *grid;
+    grid.reverse();
+    grid
+}
+
+/// A single channel value of a pixel.
+pub(crate) trait Sample: Copy + Sync {
+    /// Fully opaque alpha value
+    const MAX: f64;
+    /// Whether the values are integers, whose sums do not depend on the
+    /// order they are added in
+    const INTEGER: bool;
+
+    fn to_f64(self) -> f64;
+}
+
+impl Sample for u8 {
+    const MAX: f64 = 255.0;
+    const INTEGER: bool = true;
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Sample for u16 {
+    const MAX: f64 = 65535.0;
+    const INTEGER: bool = true;
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Sample for f32 {
+    const MAX: f64 = 1.0;
+    const INTEGER: bool = false;
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl Sample for f64 {
+    const MAX: f64 = 1.0;
+    const INTEGER: bool = false;
+
+    #[inline(always)]
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+/// Luma of a pixel of `luma` and `alpha` composited on white, as if the
+/// image was shown on a white background, `max` being the fully opaque
+/// alpha: transparent pixels read as white.
+#[inline(always)]
+pub(crate) fn composite_on_white(luma: f64, alpha: f64, max: f64) -> f64 {
+    (luma * alpha + max * (max - alpha)) / max
+}
+
+/// Channels of the pixel at index `i` (`image_y * width + image_x`) of
+/// `bytes` as the grids sum them: red, green and blue with 3 channels or
+/// more, otherwise the luma, composited on white for LumaA, then zeros.
+#[inline(always)]
+pub(crate) fn pixel_channels(bytes: &[u8], channel_count: usize, i: usize) -> [f64; 3] {
+    let i = i * channel_count;
+
+    match channel_count {
+        3.. => [bytes[i] as f64, bytes[i + 1] as f64, bytes[i + 2] as f64],
+        2 => {
+            let (luma, alpha) = (bytes[i] as f64, bytes[i + 1] as f64);
+            [composite_on_white(luma, alpha, u8::MAX as f64), 0.0, 0.0]
+        }
+        _ => [bytes[i] as f64, 0.0, 0.0],
+    }
+}
+
+/// Luma of the channels of a pixel, or of their sums over a cell, as
+/// returned by [`pixel_channels`].
+#[inline(always)]
+pub(crate) fn channels_luma([r, g, b]: [f64; 3], channel_count: usize) -> f64 {
+    match channel_count {
+        3.. => r * 0.299 + g * 0.587 + b * 0.114,
+        _ => r,
+    }
+}
+
+/// Checks that `len` samples are exactly the pixels of a `width` by
+/// `height` image with `channel_count` channels, before any unchecked read.
+///
+/// The expected length saturates rather than overflowing, and an image
+/// without channels is rejected, as reading its first channel would be out
+/// of bounds.
+pub(crate) fn check_dimensions(
+    len: usize,
+    width: usize,
+    height: usize,
+    channel_count: usize,
+) -> Result<(), DhashError> {
+    let expected = width
+        .saturating_mul(height)
+        .saturating_mul(channel_count.max(1));
+
+    if channel_count == 0 || expected != len {
+        return Err(DhashError::InvalidDimensions {
+            expected,
+            actual: len,
+        });
+    }
+
+    Ok(())
+}
+
+/// Luma grid of `W` x `H` cells, cells reduced with
+/// [`CellAggregation::Sum`] hold the mean luma.
+pub(crate) fn luma_grid<const W: usize, const H: usize, T: Sample>(
+    samples: &[T],
+    width: u32,
+    height: u32,
+    channel_count: u8,
+    aggregation: CellAggregation,
+) -> Result<[[f64; W]; H], DhashError> {
+    let reduction = Reduction {
+        aggregation,
+        workers: workers_for(width as usize * height as usize, None, PARALLEL_THRESHOLD),
+        ..Default::default()
+    };
+
+    subsampled_luma_grid(samples, width, height, channel_count, reduction)
+}
+
+/// How [`subsampled_luma_grid`] reduces the pixels to the grid.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Reduction<'a> {
+    pub aggregation: CellAggregation,
+    pub rounding: RoundingMode,
+    pub channel_order: ChannelOrder,
+    /// Reads one pixel every `step` columns and rows of each cell, 0 reads
+    /// as 1
+    pub step: usize,
+    pub workers: Workers<'a>,
+}
+
+/// Same as [`luma_grid`], reading one pixel every `step` columns and rows
+/// of each cell, starting from its top left pixel, with the cells and the
+/// workers of `reduction`.
+pub(crate) fn subsampled_luma_grid<const W: usize, const H: usize, T: Sample>(
+    samples: &[T],
+    width: u32,
+    height: u32,
+    channel_count: u8,
+    reduction: Reduction<'_>,
+) -> Result<[[f64; W]; H], DhashError> {
+    let width = width as usize;
+    let height = height as usize;
+    let channel_count = channel_count as usize;
+
+    // NOTE: Very important, prevents possible segfault
+    check_dimensions(samples.len(), width, height, channel_count)?;
+
+    let Reduction {
+        aggregation,
+        rounding,
+        channel_order,
+        step,
+        workers,
+    } = reduction;
+
+    let col_bounds = rounding.bounds(W, width);
+    let row_bounds = rounding.bounds(H, height);
+    let step = step.max(1);
+    let order = ScanOrder::for_values(T::INTEGER);
+
+    let mut grid = if channel_count >= 3 {
+        let (red, blue) = match channel_order {
+            ChannelOrder::Rgb => (0, 2),
+            ChannelOrder::Bgr => (2, 0),
+        };
+
+        let pixel = |i: usize| {
+            let i = i * channel_count;
+
+            // SAFETY: the pixel index is below `width * height`, see
+            // `grid_from_rgb`, there are `channel_count >= 3` samples per
+            // pixel as checked above, so `i + 2` is within the pixel
+            unsafe {
+                [
+                    samples.get_unchecked(i + red).to_f64(),
+                    samples.get_unchecked(i + 1).to_f64(),
+                    samples.get_unchecked(i + blue).to_f64(),
+                ]
+            }
+        };
+
+        match aggregation {
+            CellAggregation::Sum => {
+                grid_from_rgb(width, &col_bounds, &row_bounds, step, order, workers, pixel)
+            }
+            _ => grid_from_grayscale(
+                width,
+                &col_bounds,
+                &row_bounds,
+                step,
+                order,
+                aggregation,
+                workers,
+                |i| {
+                    let [r, g, b] = pixel(i);
+                    r * 0.299 + g * 0.587 + b * 0.114
+                },
+            ),
+        }
+    } else if channel_count == 2 {
+        // NOTE: transparent pixels read as white, as if the image was
+        // shown on a white background, the composited luma is not an
+        // integer
+        grid_from_grayscale(
+            width,
+            &col_bounds,
+            &row_bounds,
+            step,
+            ScanOrder::Columns,
+            aggregation,
+            workers,
+            |i| {
+                let i = i * 2;
+
+                // SAFETY: the pixel index is below `width * height` and there
+                // are exactly 2 samples per pixel as checked above
+                let (luma, alpha) = unsafe {
+                    (
+                        samples.get_unchecked(i).to_f64(),
+                        samples.get_unchecked(i + 1).to_f64(),
+                    )
+                };
+
+                composite_on_white(luma, alpha, T::MAX)
+            },
+        )
+    } else {
+        // SAFETY: the pixel index is below `width * height` and there is
+        // at least one sample per pixel, 0 channels being rejected above
+        grid_from_grayscale(
+            width,
+            &col_bounds,
+            &row_bounds,
+            step,
+            order,
+            aggregation,
+            workers,
+            |i| unsafe { samples.get_unchecked(i * channel_count).to_f64() },
+        )
+    };
+
+    if aggregation == CellAggregation::Sum {
+        for (y, row) in grid.iter_mut().enumerate() {
+            let cell_height = row_bounds[y + 1] - row_bounds[y];
+
+            for (x, cell) in row.iter_mut().enumerate() {
+                let cell_width = col_bounds[x + 1] - col_bounds[x];
+                let cell_size = (cell_width.div_ceil(step) * cell_height.div_ceil(step)) as f64;
+
+                if cell_size > 0.0 {
+                    *cell /= cell_size;
+                }
+            }
+        }
+    }
+
+    Ok(grid)
+}
+
+/// Same as [`luma_grid`] with the 9x8 grid of the hash, reading only the
+/// first channel of each pixel as its luma, whatever `channel_count`.
+pub(crate) fn first_channel_grid(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    channel_count: u8,
+) -> Result<[[f64; 9]; 8], DhashError> {
+    let width = width as usize;
+    let height = height as usize;
+    let channel_count = channel_count as usize;
+
+    // NOTE: Very important, prevents possible segfault
+    check_dimensions(bytes.len(), width, height, channel_count)?;
+
+    let cell_width = width / 9;
+    let cell_height = height / 8;
+
+    // SAFETY: the pixel index is below `width * height`, see
+    // `grid_from_rgb`, and there is at least one byte per pixel, 0 channels
+    // being rejected above
+    let mut grid = grid_from_grayscale(
+        width,
+        &RoundingMode::Floor.bounds(9, width),
+        &RoundingMode::Floor.bounds(8, height),
+        1,
+        ScanOrder::Rows,
+        CellAggregation::Sum,
+        workers_for(width * height, None, PARALLEL_THRESHOLD),
+        |i| unsafe { *bytes.get_unchecked(i * channel_count) as f64 },
+    );
+
+    let cell_size = (cell_width * cell_height) as f64;
+
+    if cell_size > 0.0 {
+        for cell in grid.iter_mut().flatten() {
+            *cell /= cell_size;
+        }
+    }
+
+    Ok(grid)
+}
+
+/// Same as [`luma_grid`] with the 9x8 grid of the hash, reading the pixels
+/// of `view` one at a time with [`GenericImageView::get_pixel`].
+#[cfg(feature = "image")]
+pub(crate) fn view_luma_grid<V>(view: &V) -> [[f64; 9]; 8]
+where
+    V: GenericImageView + Sync,
+{
+    let (width, height) = view.dimensions();
+    let max = <<V::Pixel as Pixel>::Subpixel as Primitive>::DEFAULT_MAX_VALUE
+        .to_f64()
+        .unwrap_or(1.0);
+
+    let x_of = |i: usize| (i % width as usize) as u32;
+    let y_of = |i: usize| (i / width as usize) as u32;
+
+    // NOTE: floating point subpixels are the only ones whose maximum is 1
+    let order = ScanOrder::for_values(max > 1.0);
+
+    indexed_luma_grid(
+        width as usize,
+        height as usize,
+        V::Pixel::CHANNEL_COUNT as usize,
+        max,
+        order,
+        |i| {
+            let pixel = view.get_pixel(x_of(i), y_of(i));
+            let mut channels = [0.0; 3];
+
+            for (channel, sample) in channels.iter_mut().zip(pixel.channels()) {
+                *channel = sample.to_f64().unwrap_or(0.0);
+            }
+
+            channels
+        },
+    )
+}
+
+/// Same as [`luma_grid`] with the 9x8 grid of the hash, for pixels that are
+/// not laid out as interleaved samples, e.g. strided or planar tensors.
+///
+/// `pixel` reads up to the first three channels of the pixel at the given
+/// index (`image_y * width + image_x`), interpreted as [`luma_grid`] does
+/// depending on `channel_count`, `max` being the fully opaque alpha, and
+/// `order` suits its values, see [`ScanOrder`].
+#[cfg(any(feature = "image", feature = "ndarray"))]
+pub(crate) fn indexed_luma_grid<P>(
+    width: usize,
+    height: usize,
+    channel_count: usize,
+    max: f64,
+    order: ScanOrder,
+    pixel: P,
+) -> [[f64; 9]; 8]
+where
+    P: Fn(usize) -> [f64; 3] + Sync,
+{
+    let workers = workers_for(width * height, None, PARALLEL_THRESHOLD);
+
+    indexed_luma_grid_on(width, height, channel_count, max, order, workers, pixel)
+}
+
+/// Same as [`indexed_luma_grid`], the rows being computed by `workers`.
+pub(crate) fn indexed_luma_grid_on<P>(
+    width: usize,
+    height: usize,
+    channel_count: usize,
+    max: f64,
+    order: ScanOrder,
+    workers: Workers<'_>,
+    pixel: P,
+) -> [[f64; 9]; 8]
+where
+    P: Fn(usize) -> [f64; 3] + Sync,
+{
+    let cell_width = width / 9;
+    let cell_height = height / 8;
+    let col_bounds = RoundingMode::Floor.bounds(9, width);
+    let row_bounds = RoundingMode::Floor.bounds(8, height);
+
+    let mut grid = match channel_count {
+        3.. => grid_from_rgb(width, &col_bounds, &row_bounds, 1, order, workers, pixel),
+        2 => grid_from_grayscale(
+            width,
+            &col_bounds,
+            &row_bounds,
+            1,
+            ScanOrder::Columns,
+            CellAggregation::Sum,
+            workers,
+            |i| {
+                let [luma, alpha, _] = pixel(i);
+                composite_on_white(luma, alpha, max)
+            },
+        ),
+        _ => grid_from_grayscale(
+            width,
+            &col_bounds,
+            &row_bounds,
+            1,
+            order,
+            CellAggregation::Sum,
+            workers,
+            |i| pixel(i)[0],
+        ),
+    };
+
+    let cell_size = (cell_width * cell_height) as f64;
+
+    if cell_size > 0.0 {
+        for cell in grid.iter_mut().flatten() {
+            *cell /= cell_size;
+        }
+    }
+
+    grid
+}
+
+/// `pixel` reads the red, green and blue values of the pixel at the given
+/// index (`image_y * width + image_x`), bounds are checked by the caller.
+///
+/// Cell `x` spans the columns `col_bounds[x]..col_bounds[x + 1]` and cell
+/// `y` the rows `row_bounds[y]..row_bounds[y + 1]`, see
+/// [`RoundingMode::bounds`]. Only the pixels of the cells are read,
+/// `image_x < col_bounds[W]` and `image_y < row_bounds[H]`, at most the
+/// width and the height, which the unchecked reads of the callers rely on.
+///
+/// The pixels are read in `order`, see [`ScanOrder`], and the rows are
+/// computed by `workers`, see [`map_rows`].
+pub(crate) fn grid_from_rgb<const W: usize, const H: usize, P>(
+    width: usize,
+    col_bounds: &[usize],
+    row_bounds: &[usize],
+    step: usize,
+    order: ScanOrder,
+    workers: Workers<'_>,
+    pixel: P,
+) -> [[f64; W]; H]
+where
+    P: Fn(usize) -> [f64; 3] + Sync,
+{
+    let mut grid = [[0f64; W]; H];
+
+    let rows = map_rows(H, workers, |y| {
+        let mut sums = [[0f64; 3]; W];
+
+        let rows = &row_bounds[y..y + 2];
+
+        scan_cells(width, col_bounds, rows, step, order, |x, run| {
+            let [mut rs, mut gs, mut bs] = sums[x];
+
+            for i in run {
+                let [r, g, b] = pixel(i);
+
+                rs += r;
+                gs += g;
+                bs += b;
+            }
+
+            sums[x] = [rs, gs, bs];
+        });
+
+        sums.map(|[rs, gs, bs]| rs * 0.299 + gs * 0.587 + bs * 0.114)
+    });
+
+    for (y, row) in rows.into_iter().enumerate() {
+        grid[y] = row;
+    }
+
+    grid
+}
+
+/// `pixel` reads the luma of the pixel at the given index
+/// (`image_y * width + image_x`), bounds are checked by the caller, only
+/// the pixels of the cells are read as in [`grid_from_rgb`].
+#[allow(clippy::too_many_arguments)]
+fn grid_from_grayscale<const W: usize, const H: usize, P>(
+    width: usize,
+    col_bounds: &[usize],
+    row_bounds: &[usize],
+    step: usize,
+    order: ScanOrder,
+    aggregation: CellAggregation,
+    workers: Workers<'_>,
+    pixel: P,
+) -> [[f64; W]; H]
+where
+    P: Fn(usize) -> f64 + Sync,
+{
+    let mut grid = [[0f64; W]; H];
+
+    let rows = map_rows(H, workers, |y| {
+        let mut row = [0f64; W];
+        let rows = &row_bounds[y..y + 2];
+
+        match aggregation {
+            CellAggregation::Sum => {
+                scan_cells(width, col_bounds, rows, step, order, |x, run| {
+                    row[x] = run.fold(row[x], |sum, i| sum + pixel(i))
+                });
+            }
+            CellAggregation::Max => {
+                scan_cells(width, col_bounds, rows, step, order, |x, run| {
+                    row[x] = run.fold(row[x], |max, i| max.max(pixel(i)))
+                });
+            }
+            CellAggregation::Median => {
+                let mut cells = [(); W].map(|_| Vec::new());
+                let mut buffer = Vec::new();
+
+                scan_cells(width, col_bounds, rows, step, order, |x, run| {
+                    cells[x].extend(run.map(&pixel))
+                });
+
+                for (cell, pixels) in row.iter_mut().zip(cells) {
+                    *cell = aggregation.reduce(pixels.into_iter(), &mut buffer);
+                }
+            }
+        }
+
+        row
+    });
+
+    for (y, row) in rows.into_iter().enumerate() {
+        grid[y] = row;
+    }
+
+    grid
+}
+
+/// Order [`scan_cells`] reads the pixels of a row of cells in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ScanOrder {
+    /// Image row by image row, from left to right, in memory order
+    Rows,
+    /// Cell by cell, column by column, jumping a whole image row per pixel
+    Columns,
+}
+
+impl ScanOrder {
+    /// [`ScanOrder::Rows`] for integer values, whose sums are exact whatever
+    /// the order, otherwise [`ScanOrder::Columns`], the order sums have
+    /// always been computed in, so that their rounding does not change.
+    pub(crate) fn for_values(integer: bool) -> Self {
+        match integer {
+            true => Self::Rows,
+            false => Self::Columns,
+        }
+    }
+}
+
+/// Calls `f` with the cell column and the indices of a run of pixels read
+/// in it, for the row of cells spanning `rows[0]..rows[1]`, each image row
+/// from left to right or each image column from top to bottom, depending on
+/// `order`.
+pub(crate) fn scan_cells<F>(
+    width: usize,
+    col_bounds: &[usize],
+    rows: &[usize],
+    step: usize,
+    order: ScanOrder,
+    mut f: F,
+) where
+    F: FnMut(usize, iter::StepBy<Range<usize>>),
+{
+    match order {
+        ScanOrder::Rows => {
+            for image_y in (rows[0]..rows[1]).step_by(step) {
+                let line = image_y * width;
+
+                for (x, cols) in col_bounds.windows(2).enumerate() {
+                    f(x, (line + cols[0]..line + cols[1]).step_by(step));
+                }
+            }
+        }
+        ScanOrder::Columns => {
+            for (x, cols) in col_bounds.windows(2).enumerate() {
+                for image_x in (cols[0]..cols[1]).step_by(step) {
+                    let column = rows[0] * width + image_x..rows[1] * width + image_x;
+
+                    f(x, column.step_by(width * step));
+                }
+            }
+        }
+    }
+}
+
+/// Where the rows of a grid are computed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Workers<'a> {
+    /// Split across this many spawned threads, `None` for one per row, 0 or
+    /// 1 on the calling thread, without spawning any
+    Threads(Option<usize>),
+    /// Split in chunks of this many consecutive rows, a spawned thread each,
+    /// all the rows or more on the calling thread, 0 reads as 1
+    Chunks(usize),
+    /// Split across the long-lived workers of a
+    /// [`DhashHasher`](crate::DhashHasher)
+    Pool(&'a WorkerPool),
+}
+
+impl Default for Workers<'_> {
+    fn default() -> Self {
+        Self::Threads(None)
+    }
+}
+
+/// `threads` if set, otherwise the calling thread alone for fewer than
+/// `parallel_threshold` pixels and a thread per row for more.
+pub(crate) fn workers_for(
+    pixels: usize,
+    threads: Option<usize>,
+    parallel_threshold: usize,
+) -> Workers<'static> {
+    Workers::Threads(threads.or((pixels < parallel_threshold).then_some(1)))
+}
+
+/// Computes the rows of a grid, in order, with `workers`.
+pub(crate) fn map_rows<T, F>(rows: usize, workers: Workers<'_>, row: F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    let chunk = match workers {
+        Workers::Threads(threads) => {
+            let threads = threads.unwrap_or(rows).clamp(1, rows.max(1));
+            rows.div_ceil(threads)
+        }
+        Workers::Chunks(chunk) => chunk.max(1),
+        Workers::Pool(pool) => return pool.map_rows(rows, &row),
+    };
+
+    match chunk >= rows {
+        true => (0..rows).map(row).collect(),
+        false => spawn_rows(rows, chunk, &row),
+    }
+}
+
+/// Chunks of `chunk` consecutive rows on a scoped thread each.
+#[cfg(not(feature = "rayon"))]
+fn spawn_rows<T, F>(rows: usize, chunk: usize, row: &F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    thread::scope(|s| {
+        let handles = (0..rows)
+            .step_by(chunk)
+            .map(|from| {
+                s.spawn(move || {
+                    (from..(from + chunk).min(rows))
+                        .map(row)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    })
+}
+
+/// Chunks of at least `chunk` consecutive rows on the current rayon thread
+/// pool rather than spawning threads.
+#[cfg(feature = "rayon")]
+fn spawn_rows<T, F>(rows: usize, chunk: usize, row: &F) -> Vec<T>
+where
+    T: Send,
+    F: Fn(usize) -> T + Sync,
+{
+    (0..rows)
+        .into_par_iter()
+        .with_min_len(chunk)
+        .map(row)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        compute_grid, compute_grid_with_stats, compute_thumbnail, flip_grid_horizontal,
+        flip_grid_vertical, grid_from_grayscale, grid_from_rgb, luma_grid, rotate_grid_90, Grid,
+        ScanOrder, Workers,
+    };
+    use crate::{CellAggregation, Dhash, HashFamily, HashKinds, RoundingMode};
+    use proptest::prelude::*;
+
+    #[test]
+    fn rotate_and_flip() {
+        let grid: [[f64; 9]; 8] =
+            std::array::from_fn(|y| std::array::from_fn(|x| (y * 9 + x) as f64));
+
+        let rotated = rotate_grid_90(&grid);
+
+        assert_eq!(rotated[0], [63.0, 54.0, 45.0, 36.0, 27.0, 18.0, 9.0, 0.0]);
+        assert_eq!(rotated[8], [71.0, 62.0, 53.0, 44.0, 35.0, 26.0, 17.0, 8.0]);
+        assert_eq!(
+            rotate_grid_90(&rotate_grid_90(&rotated)),
+            flip_grid_vertical(&flip_grid_horizontal(&rotated))
+        );
+        assert_eq!(
+            rotate_grid_90(&rotate_grid_90(&rotate_grid_90(&rotated))),
+            grid
+        );
+
+        assert_eq!(flip_grid_horizontal(&grid)[0][0], 8.0);
+        assert_eq!(flip_grid_vertical(&grid)[0][0], 63.0);
+        assert_eq!(flip_grid_horizontal(&flip_grid_horizontal(&grid)), grid);
+        assert_eq!(flip_grid_vertical(&flip_grid_vertical(&grid)), grid);
+    }
+
+    #[test]
+    fn scan_order() {
+        let (width, height) = (131, 97);
+
+        let bytes = (0..width * height * 3)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let rgb = |i: usize| std::array::from_fn(|c| bytes[i * 3 + c] as f64);
+        let luma = |i: usize| bytes[i * 3] as f64;
+
+        for step in [1, 3] {
+            let col_bounds = [0, 10, 25, 40, 41, 60, 80, 100, 120, 131];
+            let row_bounds = [0, 12, 24, 36, 48, 60, 72, 84, 97];
+
+            // NOTE: the cells read column by column, as they used to be
+            let cell = |x: usize, y: usize| {
+                (col_bounds[x]..col_bounds[x + 1])
+                    .step_by(step)
+                    .flat_map(|image_x| {
+                        (row_bounds[y]..row_bounds[y + 1])
+                            .step_by(step)
+                            .map(move |image_y| image_y * width + image_x)
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let grid: [[f64; 9]; 8] = grid_from_rgb(
+                width,
+                &col_bounds,
+                &row_bounds,
+                step,
+                ScanOrder::Rows,
+                Workers::default(),
+                rgb,
+            );
+
+            for (y, row) in grid.iter().enumerate() {
+                for (x, &value) in row.iter().enumerate() {
+                    let [rs, gs, bs] = cell(x, y)
+                        .into_iter()
+                        .map(rgb)
+                        .fold([0.0; 3], |s, p| [s[0] + p[0], s[1] + p[1], s[2] + p[2]]);
+
+                    assert_eq!(value, rs * 0.299 + gs * 0.587 + bs * 0.114);
+                }
+            }
+
+            for aggregation in [
+                CellAggregation::Sum,
+                CellAggregation::Max,
+                CellAggregation::Median,
+            ] {
+                let grid: [[f64; 9]; 8] = grid_from_grayscale(
+                    width,
+                    &col_bounds,
+                    &row_bounds,
+                    step,
+                    ScanOrder::Rows,
+                    aggregation,
+                    Workers::default(),
+                    luma,
+                );
+
+                for (y, row) in grid.iter().enumerate() {
+                    for (x, &value) in row.iter().enumerate() {
+                        let pixels = cell(x, y).into_iter().map(luma);
+
+                        assert_eq!(value, aggregation.reduce(pixels, &mut Vec::new()));
+                    }
+                }
+            }
+        }
+
+        // NOTE: the composited luma of 2 channels and floating point samples
+        // are not integers, their sums keep the column order
+        let floats = bytes.iter().map(|&b| b as f32 / 255.0).collect::<Vec<_>>();
+        let col_bounds = RoundingMode::Floor.bounds(9, width);
+        let row_bounds = RoundingMode::Floor.bounds(8, height);
+        let cell_size = ((width / 9) * (height / 8)) as f64;
+
+        let column_order = |luma: &dyn Fn(usize) -> f64| -> [[f64; 9]; 8] {
+            std::array::from_fn(|y| {
+                std::array::from_fn(|x| {
+                    let mut sum = 0f64;
+
+                    for image_x in col_bounds[x]..col_bounds[x + 1] {
+                        for image_y in row_bounds[y]..row_bounds[y + 1] {
+                            sum += luma(image_y * width + image_x);
+                        }
+                    }
+
+                    sum / cell_size
+                })
+            })
+        };
+
+        let (w, h) = (width as u32, height as u32);
+        let composite =
+            |luma: f64, alpha: f64, max: f64| (luma * alpha + max * (max - alpha)) / max;
+
+        assert_eq!(
+            luma_grid(&bytes[..width * height * 2], w, h, 2, CellAggregation::Sum).unwrap(),
+            column_order(&|i| composite(bytes[i * 2] as f64, bytes[i * 2 + 1] as f64, 255.0))
+        );
+        assert_eq!(
+            luma_grid(&floats[..width * height * 2], w, h, 2, CellAggregation::Sum).unwrap(),
+            column_order(&|i| composite(floats[i * 2] as f64, floats[i * 2 + 1] as f64, 1.0))
+        );
+        assert_eq!(
+            luma_grid(&floats[..width * height], w, h, 1, CellAggregation::Sum).unwrap(),
+            column_order(&|i| floats[i] as f64)
+        );
+
+        let grid: [[f64; 9]; 8] = luma_grid(&floats, w, h, 3, CellAggregation::Sum).unwrap();
+
+        for (y, row) in grid.iter().enumerate() {
+            for (x, &value) in row.iter().enumerate() {
+                let channel = |c: usize| {
+                    let mut sum = 0f64;
+
+                    for image_x in col_bounds[x]..col_bounds[x + 1] {
+                        for image_y in row_bounds[y]..row_bounds[y + 1] {
+                            sum += floats[(image_y * width + image_x) * 3 + c] as f64;
+                        }
+                    }
+
+                    sum
+                };
+
+                let luma = channel(0) * 0.299 + channel(1) * 0.587 + channel(2) * 0.114;
+
+                assert_eq!(value, luma / cell_size);
+            }
+        }
+    }
+
+    #[test]
+    fn map_rows() {
+        let current = std::thread::current().id();
+
+        for workers in [
+            Workers::Threads(Some(0)),
+            Workers::Threads(Some(1)),
+            Workers::Chunks(8),
+            Workers::Chunks(100),
+        ] {
+            let rows = super::map_rows(8, workers, |y| (y, std::thread::current().id()));
+
+            assert_eq!(rows, (0..8).map(|y| (y, current)).collect::<Vec<_>>());
+        }
+
+        for workers in [
+            Workers::Threads(None),
+            Workers::Threads(Some(2)),
+            Workers::Threads(Some(3)),
+            Workers::Threads(Some(8)),
+            Workers::Threads(Some(100)),
+            Workers::Chunks(0),
+            Workers::Chunks(2),
+            Workers::Chunks(5),
+        ] {
+            assert_eq!(
+                super::map_rows(9, workers, |y| y * y),
+                (0..9).map(|y| y * y).collect::<Vec<_>>()
+            );
+        }
+
+        assert!(super::map_rows(0, Workers::Threads(None), |y| y).is_empty());
+    }
+
+    #[test]
+    fn cell_means() {
+        // NOTE: 2x2 cells, every cell is a single gray level, the top left
+        // pixel of the cell is brighter
+        let bytes = (0..18 * 16)
+            .map(|i| {
+                let (x, y) = (i % 18, i / 18);
+                let level = (y / 2 * 9 + x / 2) as u8;
+
+                if x % 2 == 0 && y % 2 == 0 {
+                    level + 4
+                } else {
+                    level
+                }
+            })
+            .collect::<Vec<u8>>();
+
+        let grid = compute_grid(&bytes, 18, 16, 1).expect("invalid dimensions");
+
+        for y in 0..8 {
+            for x in 0..9 {
+                assert_eq!(grid.get(x, y), Some((y * 9 + x) as f64 + 1.0));
+            }
+        }
+
+        assert_eq!(grid.get(9, 0), None);
+        assert_eq!(grid.rows()[7][8], 72.0);
+
+        let normalized = grid.normalized();
+
+        assert_eq!(normalized.get(0, 0), Some(0.0));
+        assert_eq!(normalized.get(8, 7), Some(1.0));
+
+        assert!(compute_grid(&bytes, 18, 15, 1).is_err());
+
+        assert_eq!(
+            grid.to_string().lines().next(),
+            Some(" 1  2  3  4  5  6  7  8  9")
+        );
+        assert_eq!(
+            grid.to_string().lines().last(),
+            Some("64 65 66 67 68 69 70 71 72")
+        );
+    }
+
+    #[test]
+    fn thumbnail() {
+        // NOTE: 27x16 rgb, the left 13 columns are white, the rest is
+        // black, the boundary splits the fifth column of cells 1:2
+        let bytes = (0..27 * 16)
+            .flat_map(|i| if i % 27 < 13 { [255; 3] } else { [0; 3] })
+            .collect::<Vec<u8>>();
+
+        let thumbnail = compute_thumbnail(&bytes, 27, 16, 3).expect("invalid dimensions");
+
+        for row in thumbnail {
+            assert_eq!(row, [255, 255, 255, 255, 85, 0, 0, 0, 0]);
+        }
+
+        let hash = Dhash::new(&bytes, 27, 16, 3);
+
+        for (y, row) in thumbnail.iter().enumerate() {
+            for x in 0..8 {
+                let bit = hash.hash >> (y * 8 + x) & 1 == 1;
+                assert_eq!(bit, row[x] > row[x + 1]);
+            }
+        }
+
+        let clamped = Grid::new([[-1.0, 0.4, 0.6, 254.5, 300.0, 0.0, 0.0, 0.0, 0.0]; 8]);
+
+        assert_eq!(clamped.to_thumbnail()[0][..5], [0, 0, 1, 255, 255]);
+    }
+
+    #[test]
+    fn stats() {
+        let (width, height) = (90, 80);
+
+        let uniform = vec![128u8; width * height];
+        let gradient = (0..width * height)
+            .map(|i| (i % width * 255 / (width - 1)) as u8)
+            .collect::<Vec<u8>>();
+        let contrast = (0..width * height)
+            .map(|i| {
+                if (i % width + i / width) % 2 == 0 {
+                    0
+                } else {
+                    255
+                }
+            })
+            .collect::<Vec<u8>>();
+
+        let stats = |bytes: &[u8]| {
+            let (grid, stats) = compute_grid_with_stats(bytes, 90, 80, 1).unwrap();
+            assert_eq!(Some(grid), compute_grid(bytes, 90, 80, 1).ok());
+            stats
+        };
+
+        let uniform = stats(&uniform);
+        let gradient = stats(&gradient);
+        let contrast = stats(&contrast);
+
+        assert_eq!(
+            (uniform.min, uniform.max, uniform.mean),
+            (128.0, 128.0, 128.0)
+        );
+        assert_eq!(uniform.variances, [[0.0; 9]; 8]);
+        assert_eq!(uniform.flatness_score(), 1.0);
+
+        assert_eq!(
+            (contrast.min, contrast.max, contrast.mean),
+            (0.0, 255.0, 127.5)
+        );
+        assert_eq!(contrast.variances[0][0], 127.5 * 127.5);
+        assert_eq!(contrast.flatness_score(), 0.0);
+
+        assert_eq!((gradient.min, gradient.max), (0.0, 255.0));
+        assert!(gradient.flatness_score() > 0.0);
+        assert!(gradient.flatness_score() < 1.0);
+
+        // NOTE: sizes not multiple of the grid, the means of RGB, LumaA and
+        // RGBA must be the grid bit for bit
+        for channel_count in 2..=4 {
+            let (width, height) = (101, 83);
+            let bytes = (0..width * height * channel_count)
+                .map(|i| (i * 7 + i / 13 * 31 + i % 5) as u8)
+                .collect::<Vec<u8>>();
+
+            let (grid, stats) =
+                compute_grid_with_stats(&bytes, width as u32, height as u32, channel_count as u8)
+                    .unwrap();
+
+            assert_eq!(
+                Some(grid),
+                compute_grid(&bytes, width as u32, height as u32, channel_count as u8).ok()
+            );
+            assert_eq!(
+                stats.histogram.iter().sum::<u32>(),
+                (width / 9 * 9 * (height / 8 * 8)) as u32
+            );
+        }
+
+        assert!(compute_grid_with_stats(&[0; 10], 90, 80, 1).is_err());
+    }
+
+    /// Cell means of the 9x8 grid with checked indexing, an out of bounds
+    /// read panics rather than being undefined behavior.
+    fn checked_grid(
+        bytes: &[u8],
+        width: usize,
+        height: usize,
+        channel_count: usize,
+    ) -> [[f64; 9]; 8] {
+        let (cell_width, cell_height) = (width / 9, height / 8);
+        let mut grid = [[0.0; 9]; 8];
+
+        for (y, row) in grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                for image_y in y * cell_height..(y + 1) * cell_height {
+                    for image_x in x * cell_width..(x + 1) * cell_width {
+                        let i = (image_y * width + image_x) * channel_count;
+                        let pixel = &bytes[i..i + channel_count];
+
+                        *cell += match *pixel {
+                            [luma] => luma as f64,
+                            [luma, alpha] => {
+                                let (luma, alpha) = (luma as f64, alpha as f64);
+                                (luma * alpha + 255.0 * (255.0 - alpha)) / 255.0
+                            }
+                            [r, g, b, ..] => r as f64 * 0.299 + g as f64 * 0.587 + b as f64 * 0.114,
+                            [] => unreachable!(),
+                        };
+                    }
+                }
+
+                if cell_width * cell_height > 0 {
+                    *cell /= (cell_width * cell_height) as f64;
+                }
+            }
+        }
+
+        grid
+    }
+
+    // NOTE: small enough to run under Miri, `cargo +nightly miri test
+    // grid::test::unchecked_reads`, each size leaves a remainder past the
+    // last cells or has empty cells
+    #[test]
+    fn unchecked_reads() {
+        let sizes = [
+            (0, 0),
+            (1, 1),
+            (8, 7),
+            (9, 8),
+            (10, 9),
+            (17, 15),
+            (26, 23),
+            (9, 1),
+            (1, 8),
+            (9, 40),
+            (45, 8),
+        ];
+
+        for (width, height) in sizes {
+            for channel_count in 1..=5u8 {
+                let len = width * height * channel_count as usize;
+                let bytes = (0..len).map(|i| (i * 37 % 251) as u8).collect::<Vec<_>>();
+
+                let expected = checked_grid(&bytes, width, height, channel_count as usize);
+                let close = |cells: &[[f64; 9]; 8]| {
+                    cells
+                        .iter()
+                        .flatten()
+                        .zip(expected.iter().flatten())
+                        .all(|(a, b)| (a - b).abs() < 1e-9)
+                };
+
+                let (w, h) = (width as u32, height as u32);
+
+                assert!(close(
+                    &compute_grid(&bytes, w, h, channel_count).unwrap().cells
+                ));
+                assert!(close(
+                    &compute_grid_with_stats(&bytes, w, h, channel_count)
+                        .unwrap()
+                        .0
+                        .cells
+                ));
+
+                for aggregation in [CellAggregation::Sum, CellAggregation::Median] {
+                    luma_grid::<9, 8, u8>(&bytes, w, h, channel_count, aggregation).unwrap();
+                }
+
+                Dhash::new_subsampled(&bytes, w, h, channel_count, 3);
+                Dhash::new_assume_grayscale(&bytes, w, h, channel_count);
+
+                if channel_count == 3 {
+                    let planes = (0..3)
+                        .map(|c| bytes.iter().skip(c).step_by(3).copied().collect::<Vec<_>>())
+                        .collect::<Vec<_>>();
+
+                    assert_eq!(
+                        Dhash::from_rgb_planar(&planes[0], &planes[1], &planes[2], w, h),
+                        Dhash::try_new(&bytes, w, h, 3)
+                    );
+                }
+            }
+        }
+    }
+
+    // NOTE: the grid is read with unchecked indexing, run these under Miri
+    // with `cargo +nightly miri test grid::test::any` to catch out of
+    // bounds reads rather than only panics
+    fn dimension() -> impl Strategy<Value = u32> {
+        prop_oneof![0u32..40, any::<u32>()]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(if cfg!(miri) { 8 } else { 256 }))]
+
+        #[test]
+        fn any_valid_dimensions(
+            width in 0u32..40,
+            height in 0u32..40,
+            channel_count in 0u8..6,
+            step in 0usize..12,
+            seed: u8,
+        ) {
+            let len = (width * height) as usize * channel_count as usize;
+            let bytes = (0..len)
+                .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+                .collect::<Vec<_>>();
+            let samples = bytes.iter().map(|&b| b as u16 * 257).collect::<Vec<_>>();
+
+            // NOTE: without channels there are no samples to read
+            if channel_count == 0 {
+                prop_assert!(Dhash::try_new(&bytes, width, height, 0).is_err());
+                prop_assert!(luma_grid::<9, 8, u16>(&[], width, height, 0, CellAggregation::Sum).is_err());
+                return Ok(());
+            }
+
+            prop_assert!(Dhash::try_new(&bytes, width, height, channel_count).is_ok());
+            prop_assert!(compute_grid_with_stats(&bytes, width, height, channel_count).is_ok());
+            prop_assert!(luma_grid::<9, 8, u16>(
+                &samples,
+                width,
+                height,
+                channel_count,
+                CellAggregation::Median,
+            )
+            .is_ok());
+            prop_assert!(
+                HashFamily::compute(&bytes, width, height, channel_count, HashKinds::ALL).is_ok()
+            );
+
+            Dhash::new_subsampled(&bytes, width, height, channel_count, step);
+            Dhash::new_assume_grayscale(&bytes, width, height, channel_count);
+        }
+
+        #[test]
+        fn any_invalid_dimensions(
+            width in dimension(),
+            height in dimension(),
+            channel_count: u8,
+            len in 0usize..512,
+        ) {
+            let bytes = vec![0; len];
+            let valid = channel_count > 0
+                && width as u128 * height as u128 * channel_count as u128 == len as u128;
+
+            prop_assert_eq!(Dhash::try_new(&bytes, width, height, channel_count).is_ok(), valid);
+            prop_assert_eq!(
+                compute_grid_with_stats(&bytes, width, height, channel_count).is_ok(),
+                valid
+            );
+            prop_assert_eq!(
+                HashFamily::compute(&bytes, width, height, channel_count, HashKinds::ALL).is_ok(),
+                valid
+            );
+            prop_assert_eq!(
+                Dhash::from_rgb_planar(&bytes, &bytes, &bytes, width, height).is_ok(),
+                width as u128 * height as u128 == len as u128
+            );
+        }
+    }
+}
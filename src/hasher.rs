@@ -0,0 +1,306 @@
+//! Hashing on long-lived worker threads, rather than spawning threads for
+//! each hash.
+use crate::{
+    grid::{subsampled_luma_grid, Reduction, Workers, PARALLEL_THRESHOLD},
+    Dhash, DhashError, Grid,
+};
+use std::{
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Arc, Mutex},
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Hashes images on a fixed set of worker threads, shared by all the calls,
+/// e.g. one hasher for a whole application hashing many images.
+///
+/// ```
+/// use fast_dhash::{Dhash, DhashHasher};
+///
+/// let hasher = DhashHasher::new(4);
+///
+/// let bytes = vec![0; 900 * 800];
+/// let hash = hasher.hash(&bytes, 900, 800, 1).unwrap();
+///
+/// assert_eq!(hash, Dhash::new(&bytes, 900, 800, 1));
+/// ```
+///
+/// Clones share the same workers, which are stopped once the last clone is
+/// dropped.
+#[derive(Debug, Clone)]
+pub struct DhashHasher {
+    pool: Arc<WorkerPool>,
+}
+
+impl DhashHasher {
+    /// Starts `num_threads` workers, at least one.
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            pool: Arc::new(WorkerPool::new(num_threads.max(1))),
+        }
+    }
+
+    /// Same as [`Dhash::try_new`], the rows of the grid being split across
+    /// the workers. Images under [`PARALLEL_THRESHOLD`] pixels are hashed on
+    /// the calling thread, as with [`Dhash::new`].
+    pub fn hash(
+        &self,
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Dhash, DhashError> {
+        let workers = match (width as usize * height as usize) < PARALLEL_THRESHOLD {
+            true => Workers::Threads(Some(1)),
+            false => Workers::Pool(&self.pool),
+        };
+
+        let reduction = Reduction {
+            workers,
+            ..Default::default()
+        };
+
+        subsampled_luma_grid(bytes, width, height, channel_count, reduction)
+            .map(|cells| Dhash::from_grid(&Grid::new(cells)))
+    }
+
+    /// Same as [`DhashHasher::hash`], waiting for the workers on tokio's
+    /// blocking thread pool so that hashing does not stall the async runtime.
+    ///
+    /// Dropping the future does not stop the blocking task, which finishes
+    /// hashing and then releases the buffer and its clone of the hasher.
+    #[cfg(feature = "async")]
+    pub async fn hash_async<B>(
+        &self,
+        bytes: B,
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Dhash, DhashError>
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        let hasher = self.clone();
+
+        match tokio::task::spawn_blocking(move || {
+            hasher.hash(bytes.as_ref(), width, height, channel_count)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(error) => panic::resume_unwind(error.into_panic()),
+        }
+    }
+}
+
+/// Worker threads fed through a channel, stopped and joined when dropped.
+#[derive(Debug)]
+pub(crate) struct WorkerPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+
+                thread::spawn(move || loop {
+                    // NOTE: the lock is released before running the job
+                    let job = match receiver.lock() {
+                        Ok(receiver) => receiver.recv(),
+                        Err(_) => return,
+                    };
+
+                    match job {
+                        // NOTE: a panicking job drops its result sender,
+                        // the caller sees the missing rows
+                        Ok(job) => _ = panic::catch_unwind(AssertUnwindSafe(job)),
+                        Err(_) => return,
+                    }
+                })
+            })
+            .collect();
+
+        Self {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Computes the rows of a grid, in order, in chunks of consecutive rows
+    /// on the workers, blocking until all of them are done.
+    pub(crate) fn map_rows<T, F>(&self, rows: usize, row: &F) -> Vec<T>
+    where
+        T: Send,
+        F: Fn(usize) -> T + Sync,
+    {
+        // NOTE: only taken when dropped
+        let sender = self.sender.as_ref().expect("the pool is running");
+
+        let chunk = rows.div_ceil(self.workers.len()).max(1);
+        let (results, received) = mpsc::channel();
+
+        for from in (0..rows).step_by(chunk) {
+            let results = results.clone();
+
+            let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+                for y in from..(from + chunk).min(rows) {
+                    _ = results.send((y, row(y)));
+                }
+            });
+
+            // SAFETY: the job borrows `row`, and through it the pixels, for
+            // less than 'static. Every job owns a clone of `results` until it
+            // is dropped, after running, panicking or being discarded with
+            // the queue, and `received` is drained below until all of them
+            // are, so no job outlives this call and its borrows.
+            let job = unsafe { mem::transmute::<Box<dyn FnOnce() + Send + '_>, Job>(job) };
+
+            // NOTE: on error the job is dropped here, never run
+            _ = sender.send(job);
+        }
+
+        drop(results);
+
+        let mut grid = (0..rows).map(|_| None).collect::<Vec<_>>();
+
+        for (y, row) in received {
+            grid[y] = Some(row);
+        }
+
+        grid.into_iter()
+            .map(|row| row.expect("a worker panicked"))
+            .collect()
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        // NOTE: closing the channel stops the workers once the queue is empty
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhashHasher;
+    use crate::Dhash;
+    use image::ImageReader;
+    use std::thread;
+
+    #[test]
+    fn parity() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+
+        for threads in [0, 1, 3, 8, 16] {
+            let hasher = DhashHasher::new(threads);
+
+            for (bytes, channel_count) in [
+                (image.to_rgb8().into_raw(), 3),
+                (image.to_luma_alpha8().into_raw(), 2),
+                (image.to_luma8().into_raw(), 1),
+            ] {
+                assert_eq!(
+                    hasher.hash(&bytes, width, height, channel_count),
+                    Ok(Dhash::new(&bytes, width, height, channel_count))
+                );
+            }
+
+            assert!(hasher.hash(&[0; 10], width, height, 3).is_err());
+        }
+    }
+
+    #[test]
+    fn concurrent() {
+        fn shared<T: Send + Sync>(value: T) -> T {
+            value
+        }
+
+        let hasher = shared(DhashHasher::new(4));
+
+        let images = (0..16u32)
+            .map(|i| {
+                (0..900 * 800)
+                    .map(|j| ((j % 900 * (i + 1) + j / 900) % 251) as u8)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        thread::scope(|s| {
+            for bytes in &images {
+                let hasher = &hasher;
+
+                s.spawn(move || {
+                    for _ in 0..4 {
+                        assert_eq!(
+                            hasher.hash(bytes, 900, 800, 1),
+                            Ok(Dhash::new(bytes, 900, 800, 1))
+                        );
+                    }
+                });
+            }
+        });
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn hash_async() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        let hasher = DhashHasher::new(2);
+
+        let bytes = (0..2000 * 1500)
+            .map(|i| (i % 2000 * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let expected = Dhash::try_new(&bytes, 2000, 1500, 1);
+        let started = Arc::new(AtomicBool::new(false));
+
+        let hashing = tokio::spawn({
+            let hasher = hasher.clone();
+            let started = started.clone();
+
+            async move {
+                started.store(true, Ordering::SeqCst);
+                hasher.hash_async(bytes, 2000, 1500, 1).await
+            }
+        });
+
+        // NOTE: the runtime is single threaded, a hash computed inline would
+        // be over by the end of the poll that starts it, before any tick
+        while !started.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+
+        let mut ticks = 0;
+
+        while !hashing.is_finished() {
+            tokio::task::yield_now().await;
+            ticks += 1;
+        }
+
+        assert!(ticks > 0);
+        assert_eq!(hashing.await.unwrap(), expected);
+
+        assert!(hasher.hash_async(vec![0; 10], 90, 80, 1).await.is_err());
+    }
+}
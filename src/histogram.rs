@@ -0,0 +1,484 @@
+//! Distribution of the pairwise distances of a collection of hashes.
+use crate::Dhash;
+use std::{fmt, thread};
+
+/// Width of the longest bar of the [`fmt::Display`] chart
+const BAR_WIDTH: u64 = 50;
+
+/// Number of pairs under which [`DhashHistogram::compute`] and
+/// [`collection_stats`] run on the calling thread, an estimate: spawning a
+/// thread costs about as much as tens of thousands of hamming distances.
+const PARALLEL_PAIRS: usize = 100_000;
+
+/// Number of pairs at each hamming distance, from 0 to 64.
+///
+/// Near duplicates cluster close to 0 and unrelated images around 32, the
+/// valley in between is where a similarity threshold belongs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhashHistogram {
+    buckets: [u64; 65],
+}
+
+impl DhashHistogram {
+    /// Bins the distances of all the `n * (n - 1) / 2` pairs, spread over
+    /// the available threads, or on the calling thread below 100,000 pairs.
+    pub fn compute(hashes: &[Dhash]) -> Self {
+        let pairs = hashes.len().saturating_mul(hashes.len().saturating_sub(1)) / 2;
+
+        let threads = match pairs < PARALLEL_PAIRS {
+            true => 1,
+            false => thread::available_parallelism().map_or(1, |n| n.get()),
+        };
+
+        // NOTE: rows are interleaved, the first ones have the most pairs
+        let bin = |t: usize| {
+            let mut buckets = [0u64; 65];
+
+            for i in (t..hashes.len()).step_by(threads) {
+                for other in &hashes[i + 1..] {
+                    buckets[hashes[i].hamming_distance(other) as usize] += 1;
+                }
+            }
+
+            buckets
+        };
+
+        if threads == 1 {
+            return Self { buckets: bin(0) };
+        }
+
+        let buckets = thread::scope(|s| {
+            let handles = (0..threads)
+                .map(|t| s.spawn(move || bin(t)))
+                .collect::<Vec<_>>();
+
+            let mut buckets = [0u64; 65];
+
+            for handle in handles {
+                for (total, count) in buckets.iter_mut().zip(handle.join().unwrap()) {
+                    *total += count;
+                }
+            }
+
+            buckets
+        });
+
+        Self { buckets }
+    }
+
+    /// Number of pairs at exactly `distance`, 0 past 64.
+    pub fn bucket(&self, distance: u32) -> u64 {
+        self.buckets.get(distance as usize).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Largest threshold `t` such that at most `target_fpr` of the pairs are
+    /// within `t`, assuming the collection is made of unrelated images.
+    ///
+    /// 0, the strictest threshold, when there are no pairs to measure or
+    /// when even the identical pairs exceed the target, check
+    /// [`bucket(0)`](Self::bucket) to tell the latter apart.
+    pub fn threshold_for_false_positive_rate(&self, target_fpr: f64) -> u32 {
+        let total = self.total() as f64;
+
+        if total == 0.0 {
+            return 0;
+        }
+
+        let mut within = 0u64;
+        let mut threshold = 0;
+
+        for (distance, &count) in self.buckets.iter().enumerate() {
+            within += count;
+
+            if within as f64 / total > target_fpr {
+                break;
+            }
+
+            threshold = distance as u32;
+        }
+
+        threshold
+    }
+}
+
+/// Probability that two independent uniformly random `bits`-bit hashes are
+/// within `threshold` hamming distance, the binomial `P(X <= threshold)`
+/// with `X ~ B(bits, 1/2)`.
+///
+/// Real hashes are not uniform, [`DhashHistogram`] measures the actual rate
+/// of a collection.
+pub fn false_match_probability(threshold: u32, bits: u32) -> f64 {
+    if threshold >= bits {
+        return 1.0;
+    }
+
+    let n = bits as f64;
+
+    // NOTE: in log space, the binomial coefficients overflow past ~1000 bits
+    let mut ln_coefficient = 0f64;
+    let mut probability = 0f64;
+
+    for k in 0..=threshold {
+        let k = k as f64;
+
+        if k > 0.0 {
+            ln_coefficient += (n - k + 1.0).ln() - k.ln();
+        }
+
+        probability += (ln_coefficient - n * std::f64::consts::LN_2).exp();
+    }
+
+    probability.min(1.0)
+}
+
+/// Pairwise distance statistics of a collection of hashes, telling apart
+/// a set of near-identical images from a visually diverse one.
+///
+/// All zeros when there are fewer than two hashes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DhashCollectionStats {
+    pub mean_pairwise_distance: f64,
+    /// Population standard deviation of the pairwise distances.
+    pub std_pairwise_distance: f64,
+    pub min_distance: u32,
+    pub max_distance: u32,
+    /// Number of pairs at a distance of at most 10, inclusive unlike
+    /// [`SimilarTo`](crate::SimilarTo).
+    pub duplicate_count: usize,
+    /// Number of pairs at a distance of at most 20, inclusive, duplicates
+    /// included.
+    pub near_duplicate_count: usize,
+}
+
+/// Computes the [`DhashCollectionStats`] of all the `n * (n - 1) / 2`
+/// pairs, spread over the available threads, or on the calling thread below
+/// 100,000 pairs, in constant memory.
+pub fn collection_stats(hashes: &[Dhash]) -> DhashCollectionStats {
+    let pairs = hashes.len().saturating_mul(hashes.len().saturating_sub(1)) / 2;
+
+    let threads = match pairs < PARALLEL_PAIRS {
+        true => 1,
+        false => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    // NOTE: rows are interleaved, the first ones have the most pairs
+    let accumulate = |t: usize| {
+        let mut moments = Moments::default();
+
+        for i in (t..hashes.len()).step_by(threads) {
+            for other in &hashes[i + 1..] {
+                moments.push(hashes[i].hamming_distance(other));
+            }
+        }
+
+        moments
+    };
+
+    let moments = match threads {
+        1 => accumulate(0),
+        _ => thread::scope(|s| {
+            let handles = (0..threads)
+                .map(|t| s.spawn(move || accumulate(t)))
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .fold(Moments::default(), Moments::merge)
+        }),
+    };
+
+    if moments.count == 0 {
+        return DhashCollectionStats {
+            mean_pairwise_distance: 0.0,
+            std_pairwise_distance: 0.0,
+            min_distance: 0,
+            max_distance: 0,
+            duplicate_count: 0,
+            near_duplicate_count: 0,
+        };
+    }
+
+    DhashCollectionStats {
+        mean_pairwise_distance: moments.mean,
+        std_pairwise_distance: (moments.m2 / moments.count as f64).sqrt(),
+        min_distance: moments.min,
+        max_distance: moments.max,
+        duplicate_count: moments.duplicates,
+        near_duplicate_count: moments.near_duplicates,
+    }
+}
+
+/// Binary entropy `H(p)`, in bits, of each bit position, `p` being the
+/// fraction of the hashes with the bit set, all zeros for no hashes.
+///
+/// A calibration diagnostic of the hashed collection: positions close to 1
+/// split the images evenly and discriminate them best, positions close to 0
+/// are nearly constant and carry little information.
+pub fn population_entropy(hashes: &[Dhash]) -> [f64; 64] {
+    let mut counts = [0usize; 64];
+
+    for hash in hashes {
+        for (bit, count) in counts.iter_mut().enumerate() {
+            *count += (hash.hash >> bit & 1) as usize;
+        }
+    }
+
+    counts.map(|count| {
+        let p = count as f64 / hashes.len().max(1) as f64;
+
+        [p, 1.0 - p]
+            .into_iter()
+            .filter(|&p| p > 0.0)
+            .map(|p| -p * p.log2())
+            .sum()
+    })
+}
+
+/// Welford's running mean and sum of squared deviations, along with the
+/// extremes and the duplicate counts.
+#[derive(Debug, Clone, Copy)]
+struct Moments {
+    count: usize,
+    mean: f64,
+    m2: f64,
+    min: u32,
+    max: u32,
+    duplicates: usize,
+    near_duplicates: usize,
+}
+
+impl Default for Moments {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: u32::MAX,
+            max: 0,
+            duplicates: 0,
+            near_duplicates: 0,
+        }
+    }
+}
+
+impl Moments {
+    fn push(&mut self, distance: u32) {
+        let value = distance as f64;
+
+        self.count += 1;
+
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+
+        self.min = self.min.min(distance);
+        self.max = self.max.max(distance);
+        self.duplicates += (distance <= 10) as usize;
+        self.near_duplicates += (distance <= 20) as usize;
+    }
+
+    /// Chan et al. parallel combination of two running states.
+    fn merge(self, other: Self) -> Self {
+        if self.count == 0 {
+            return other;
+        }
+
+        if other.count == 0 {
+            return self;
+        }
+
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+
+        Self {
+            count,
+            mean: self.mean + delta * other.count as f64 / count as f64,
+            m2: self.m2
+                + other.m2
+                + delta * delta * self.count as f64 * other.count as f64 / count as f64,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            duplicates: self.duplicates + other.duplicates,
+            near_duplicates: self.near_duplicates + other.near_duplicates,
+        }
+    }
+}
+
+/// One bar per distance, from the smallest to the largest non-empty bucket.
+impl fmt::Display for DhashHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = self.buckets.iter().copied().max().unwrap_or(0);
+
+        let first = self.buckets.iter().position(|&count| count > 0);
+        let last = self.buckets.iter().rposition(|&count| count > 0);
+
+        let (Some(first), Some(last)) = (first, last) else {
+            return Ok(());
+        };
+
+        for distance in first..=last {
+            let count = self.buckets[distance];
+            let bar = (count * BAR_WIDTH).div_ceil(max) as usize;
+
+            if distance > first {
+                writeln!(f)?;
+            }
+
+            write!(f, "{:>2} | {} {}", distance, "#".repeat(bar), count)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{collection_stats, false_match_probability, population_entropy, DhashHistogram};
+    use crate::Dhash;
+
+    #[test]
+    fn compute() {
+        let hashes = [0x0, 0x1, 0x3, 0xff].map(|hash| Dhash { hash });
+
+        let histogram = DhashHistogram::compute(&hashes);
+
+        // NOTE: 1, 2, 8, 1, 7, 6
+        assert_eq!(histogram.total(), 6);
+        assert_eq!(histogram.bucket(1), 2);
+        assert_eq!(histogram.bucket(2), 1);
+        assert_eq!(histogram.bucket(65), 0);
+
+        assert_eq!(histogram.threshold_for_false_positive_rate(0.5), 5);
+        assert_eq!(histogram.threshold_for_false_positive_rate(0.1), 0);
+        assert_eq!(histogram.threshold_for_false_positive_rate(1.0), 64);
+
+        let empty = DhashHistogram::compute(&[]);
+
+        assert_eq!(empty.total(), 0);
+        assert_eq!(empty.threshold_for_false_positive_rate(1.0), 0);
+
+        // NOTE: 124,750 pairs, past the parallel threshold
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let hashes = (0..500)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                Dhash { hash: state }
+            })
+            .collect::<Vec<_>>();
+
+        let random = DhashHistogram::compute(&hashes);
+
+        for distance in 0..=64 {
+            let count = (0..hashes.len())
+                .flat_map(|i| (i + 1..hashes.len()).map(move |j| (i, j)))
+                .filter(|&(i, j)| hashes[i].hamming_distance(&hashes[j]) == distance)
+                .count();
+
+            assert_eq!(random.bucket(distance), count as u64);
+        }
+
+        let chart = histogram.to_string();
+        let lines = chart.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0], format!(" 1 | {} 2", "#".repeat(50)));
+        assert_eq!(lines[1], format!(" 2 | {} 1", "#".repeat(25)));
+        assert_eq!(lines[2], " 3 |  0");
+    }
+
+    #[test]
+    fn false_match() {
+        let close = |a: f64, b: f64| (a - b).abs() <= b * 1e-9;
+
+        assert!(close(false_match_probability(11, 64), 5.029289922416297e-8));
+        assert!(close(false_match_probability(32, 64), 0.5496733768739834));
+        assert!(close(false_match_probability(0, 64), 2f64.powi(-64)));
+        assert_eq!(false_match_probability(1, 2), 0.75);
+        assert_eq!(false_match_probability(64, 64), 1.0);
+        assert_eq!(false_match_probability(0, 0), 1.0);
+        assert!(close(
+            false_match_probability(1024, 2048),
+            0.5088143862024233
+        ));
+    }
+
+    #[test]
+    fn entropy() {
+        assert_eq!(population_entropy(&[]), [0.0; 64]);
+
+        // NOTE: bit 0 always set, bit 1 set in half of the hashes, bit 2 in
+        // a quarter
+        let hashes = [0b011, 0b001, 0b111, 0b001].map(|hash| Dhash { hash });
+        let entropy = population_entropy(&hashes);
+
+        assert_eq!(entropy[0], 0.0);
+        assert_eq!(entropy[1], 1.0);
+        assert!((entropy[2] - 0.8112781244591328).abs() < 1e-12);
+        assert!(entropy[3..].iter().all(|&h| h == 0.0));
+    }
+
+    #[test]
+    fn stats_identical() {
+        let hashes = [Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        }; 100];
+
+        let stats = collection_stats(&hashes);
+
+        assert_eq!(stats.mean_pairwise_distance, 0.0);
+        assert_eq!(stats.std_pairwise_distance, 0.0);
+        assert_eq!((stats.min_distance, stats.max_distance), (0, 0));
+        assert_eq!(stats.duplicate_count, 4950);
+        assert_eq!(stats.near_duplicate_count, 4950);
+
+        // NOTE: the bounds are inclusive
+        let counts = |distance: u32| {
+            let stats = collection_stats(&[0, (1 << distance) - 1].map(|hash| Dhash { hash }));
+            (stats.duplicate_count, stats.near_duplicate_count)
+        };
+
+        assert_eq!(counts(10), (1, 1));
+        assert_eq!(counts(11), (0, 1));
+        assert_eq!(counts(20), (0, 1));
+        assert_eq!(counts(21), (0, 0));
+
+        assert_eq!(collection_stats(&hashes[..1]), collection_stats(&[]));
+        assert_eq!(collection_stats(&[]).duplicate_count, 0);
+    }
+
+    #[test]
+    fn stats_random() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+
+        let hashes = (0..500)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                Dhash { hash: state }
+            })
+            .collect::<Vec<_>>();
+
+        // NOTE: 124,750 pairs, over PARALLEL_PAIRS unlike stats_identical
+        let stats = collection_stats(&hashes);
+
+        // NOTE: B(64, 1/2), mean 32 and standard deviation 4
+        assert!((stats.mean_pairwise_distance - 32.0).abs() < 0.5);
+        assert!((stats.std_pairwise_distance - 4.0).abs() < 0.5);
+        assert!(stats.min_distance > 10 && stats.max_distance < 64);
+        assert_eq!(stats.duplicate_count, 0);
+
+        let histogram = DhashHistogram::compute(&hashes);
+        let within = |t: u32| (0..=t).map(|d| histogram.bucket(d)).sum::<u64>() as usize;
+
+        assert_eq!(stats.near_duplicate_count, within(20));
+        assert!(stats.near_duplicate_count < 124750 / 100);
+    }
+}
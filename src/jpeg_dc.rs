@@ -0,0 +1,871 @@
+//! Hashing baseline JPEGs from the DC coefficients of their luma blocks,
+//! without the inverse DCT.
+use crate::{Dhash, Grid};
+use std::{error, fmt};
+
+/// Error of [`hash_jpeg_dc`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashJpegDcError {
+    /// The data is not a JPEG, or is truncated or corrupt
+    Malformed,
+    /// A coding process only a full decoder reads, named
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for HashJpegDcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => write!(f, "Malformed JPEG"),
+            Self::Unsupported(process) => write!(f, "Unsupported JPEG, {}", process),
+        }
+    }
+}
+
+impl error::Error for HashJpegDcError {}
+
+/// Computes the hash of a baseline JPEG from the DC coefficient of each
+/// luma block, the mean of its 8x8 pixels, skipping the AC coefficients
+/// and the inverse DCT.
+///
+/// The cells average the block means they cover, weighted by the covered
+/// area, so the hash is typically within a few bits of the hash of the
+/// full decode, the luma of the full decode being weighted from the RGB
+/// values rather than read from the Y channel. Chroma subsampling and
+/// restart markers are supported, progressive, arithmetic coded, lossless
+/// and 12 bits JPEGs are not.
+pub fn hash_jpeg_dc(bytes: &[u8]) -> Result<Dhash, HashJpegDcError> {
+    if bytes.get(..2) != Some(&[0xFF, 0xD8]) {
+        return Err(HashJpegDcError::Malformed);
+    }
+
+    let mut pos = 2;
+    let mut frame = None;
+    let mut tables = Tables::default();
+    let mut restart_interval = 0;
+    let mut cells = None;
+
+    loop {
+        let marker = next_marker(bytes, &mut pos)?;
+
+        match marker {
+            // NOTE: end of image
+            0xD9 => break,
+            // NOTE: standalone markers, restarts outside of a scan are stray
+            0x01 | 0xD0..=0xD7 => continue,
+            _ => {}
+        }
+
+        let segment = segment(bytes, &mut pos)?;
+
+        match marker {
+            0xC0 | 0xC1 => frame = Some(Frame::parse(segment)?),
+            0xC2 | 0xC6 | 0xCA | 0xCE => return Err(HashJpegDcError::Unsupported("progressive")),
+            0xC3 | 0xC7 | 0xCB | 0xCF => return Err(HashJpegDcError::Unsupported("lossless")),
+            0xC5 => return Err(HashJpegDcError::Unsupported("hierarchical")),
+            0xC9 | 0xCD => return Err(HashJpegDcError::Unsupported("arithmetic coding")),
+            0xC4 => tables.parse_huffman(segment)?,
+            0xDB => tables.parse_quantization(segment)?,
+            0xDD => {
+                restart_interval = match segment {
+                    [high, low, ..] => u16::from_be_bytes([*high, *low]) as usize,
+                    _ => return Err(HashJpegDcError::Malformed),
+                }
+            }
+            0xDA => {
+                let frame = frame.as_ref().ok_or(HashJpegDcError::Malformed)?;
+                let cells = cells.get_or_insert_with(|| CellSums::new(frame));
+
+                pos = decode_scan(bytes, pos, segment, frame, &tables, restart_interval, cells)?;
+            }
+            _ => {}
+        }
+    }
+
+    let cells = cells.ok_or(HashJpegDcError::Malformed)?;
+
+    Ok(Dhash::from_grid(&cells.grid()))
+}
+
+/// Moves `pos` past the next marker and returns it, fill bytes included.
+fn next_marker(bytes: &[u8], pos: &mut usize) -> Result<u8, HashJpegDcError> {
+    if bytes.get(*pos) != Some(&0xFF) {
+        return Err(HashJpegDcError::Malformed);
+    }
+
+    while bytes.get(*pos) == Some(&0xFF) {
+        *pos += 1;
+    }
+
+    let marker = *bytes.get(*pos).ok_or(HashJpegDcError::Malformed)?;
+    *pos += 1;
+
+    Ok(marker)
+}
+
+/// Moves `pos` past the length prefixed segment at `pos` and returns it,
+/// without its length.
+fn segment<'a>(bytes: &'a [u8], pos: &mut usize) -> Result<&'a [u8], HashJpegDcError> {
+    let len = match bytes.get(*pos..*pos + 2) {
+        Some(&[high, low]) => u16::from_be_bytes([high, low]) as usize,
+        _ => return Err(HashJpegDcError::Malformed),
+    };
+
+    let segment = bytes
+        .get(*pos + 2..*pos + len.max(2))
+        .ok_or(HashJpegDcError::Malformed)?;
+
+    *pos += len.max(2);
+
+    Ok(segment)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quantization: usize,
+}
+
+#[derive(Debug)]
+struct Frame {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    h_max: usize,
+    v_max: usize,
+}
+
+impl Frame {
+    fn parse(segment: &[u8]) -> Result<Self, HashJpegDcError> {
+        let &[precision, h0, h1, w0, w1, count, ref components @ ..] = segment else {
+            return Err(HashJpegDcError::Malformed);
+        };
+
+        if precision != 8 {
+            return Err(HashJpegDcError::Unsupported("12 bits precision"));
+        }
+
+        let components = components
+            .chunks_exact(3)
+            .take(count as usize)
+            .map(|component| Component {
+                id: component[0],
+                h: (component[1] >> 4).max(1) as usize,
+                v: (component[1] & 15).max(1) as usize,
+                quantization: (component[2] & 3) as usize,
+            })
+            .collect::<Vec<_>>();
+
+        if components.is_empty() || components.len() != count as usize {
+            return Err(HashJpegDcError::Malformed);
+        }
+
+        Ok(Self {
+            width: u16::from_be_bytes([w0, w1]) as usize,
+            height: u16::from_be_bytes([h0, h1]) as usize,
+            h_max: components.iter().map(|c| c.h).max().unwrap_or(1),
+            v_max: components.iter().map(|c| c.v).max().unwrap_or(1),
+            components,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tables {
+    dc: [Option<Huffman>; 4],
+    ac: [Option<Huffman>; 4],
+    /// First coefficient of each quantization table, the DC one
+    quantization: [u16; 4],
+}
+
+impl Tables {
+    fn parse_huffman(&mut self, mut segment: &[u8]) -> Result<(), HashJpegDcError> {
+        while let [class_id, counts @ ..] = segment {
+            let counts: &[u8; 16] = counts
+                .get(..16)
+                .and_then(|counts| counts.try_into().ok())
+                .ok_or(HashJpegDcError::Malformed)?;
+
+            let len = counts.iter().map(|&count| count as usize).sum::<usize>();
+            let values = segment
+                .get(17..17 + len)
+                .ok_or(HashJpegDcError::Malformed)?;
+
+            let table = Some(Huffman::new(counts, values));
+
+            match class_id >> 4 {
+                0 => self.dc[(class_id & 3) as usize] = table,
+                _ => self.ac[(class_id & 3) as usize] = table,
+            }
+
+            segment = &segment[17 + len..];
+        }
+
+        Ok(())
+    }
+
+    fn parse_quantization(&mut self, mut segment: &[u8]) -> Result<(), HashJpegDcError> {
+        while let [precision_id, values @ ..] = segment {
+            let (first, len) = match precision_id >> 4 {
+                0 => (values.first().map(|&q| q as u16), 64),
+                _ => (
+                    values.get(..2).map(|q| u16::from_be_bytes([q[0], q[1]])),
+                    128,
+                ),
+            };
+
+            self.quantization[(precision_id & 3) as usize] =
+                first.ok_or(HashJpegDcError::Malformed)?;
+
+            segment = segment.get(1 + len..).ok_or(HashJpegDcError::Malformed)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Canonical Huffman table, decoded one bit at a time as in the JPEG
+/// specification (F.2.2.3).
+#[derive(Debug, Clone)]
+struct Huffman {
+    /// Largest code of each length, -1 if there are none
+    max_code: [i32; 17],
+    /// Code of the first value of each length, and its index in `values`
+    min_code: [i32; 17],
+    first_value: [usize; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Self {
+        let mut max_code = [-1; 17];
+        let mut min_code = [0; 17];
+        let mut first_value = [0; 17];
+
+        let mut code = 0i32;
+        let mut value = 0usize;
+
+        for len in 1..=16 {
+            let count = counts[len - 1] as usize;
+
+            if count > 0 {
+                first_value[len] = value;
+                min_code[len] = code;
+                code += count as i32;
+                value += count;
+                max_code[len] = code - 1;
+            }
+
+            code <<= 1;
+        }
+
+        Self {
+            max_code,
+            min_code,
+            first_value,
+            values: values.to_vec(),
+        }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u8, HashJpegDcError> {
+        let mut code = 0i32;
+
+        for len in 1..=16 {
+            code = code << 1 | reader.bit()? as i32;
+
+            if code <= self.max_code[len] {
+                let index = self.first_value[len] + (code - self.min_code[len]) as usize;
+                return self
+                    .values
+                    .get(index)
+                    .copied()
+                    .ok_or(HashJpegDcError::Malformed);
+            }
+        }
+
+        Err(HashJpegDcError::Malformed)
+    }
+}
+
+/// Entropy coded data reader, removing the stuffed zero bytes.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, HashJpegDcError> {
+        if self.count == 0 {
+            let byte = match self.bytes.get(self.pos..self.pos + 2) {
+                Some(&[0xFF, 0x00]) => {
+                    self.pos += 2;
+                    0xFF
+                }
+                // NOTE: the data of the scan is over, reading past it would
+                // otherwise spin on zeros, e.g. for a huge frame
+                Some(&[0xFF, _]) => return Err(HashJpegDcError::Malformed),
+                _ => {
+                    let byte = *self.bytes.get(self.pos).ok_or(HashJpegDcError::Malformed)?;
+                    self.pos += 1;
+                    byte
+                }
+            };
+
+            self.bits = byte as u32;
+            self.count = 8;
+        }
+
+        self.count -= 1;
+
+        Ok(self.bits >> self.count & 1)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, HashJpegDcError> {
+        (0..count).try_fold(0, |value, _| Ok(value << 1 | self.bit()?))
+    }
+
+    /// Value of a `size` bits difference, negative ones are stored as
+    /// their one's complement.
+    fn receive_extend(&mut self, size: u8) -> Result<i32, HashJpegDcError> {
+        if size == 0 {
+            return Ok(0);
+        }
+
+        let value = self.bits(size)? as i32;
+
+        Ok(if value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        })
+    }
+
+    /// Drops the padding bits and moves past the restart marker.
+    fn restart(&mut self) -> Result<(), HashJpegDcError> {
+        self.count = 0;
+
+        match self.bytes.get(self.pos..self.pos + 2) {
+            Some(&[0xFF, 0xD0..=0xD7]) => {
+                self.pos += 2;
+                Ok(())
+            }
+            _ => Err(HashJpegDcError::Malformed),
+        }
+    }
+
+    /// Position of the first marker past the scan, restarts excluded.
+    fn end(&self) -> usize {
+        let mut pos = self.pos;
+
+        while let Some(&[0xFF, marker]) = self.bytes.get(pos..pos + 2) {
+            if marker != 0x00 && !(0xD0..=0xD7).contains(&marker) {
+                break;
+            }
+
+            pos += 2;
+        }
+
+        while pos + 1 < self.bytes.len() {
+            match self.bytes[pos..pos + 2] {
+                [0xFF, 0x00] | [0xFF, 0xD0..=0xD7] => pos += 2,
+                [0xFF, _] => break,
+                _ => pos += 1,
+            }
+        }
+
+        pos
+    }
+}
+
+/// Decodes the scan whose header is `header` and whose data starts at
+/// `pos`, adding the luma blocks to `cells`, returns the position of the
+/// next marker.
+fn decode_scan(
+    bytes: &[u8],
+    pos: usize,
+    header: &[u8],
+    frame: &Frame,
+    tables: &Tables,
+    restart_interval: usize,
+    cells: &mut CellSums,
+) -> Result<usize, HashJpegDcError> {
+    let count = *header.first().ok_or(HashJpegDcError::Malformed)? as usize;
+
+    let scan = header
+        .get(1..1 + count * 2)
+        .ok_or(HashJpegDcError::Malformed)?
+        .chunks_exact(2)
+        .map(|selector| {
+            let index = frame
+                .components
+                .iter()
+                .position(|component| component.id == selector[0])
+                .ok_or(HashJpegDcError::Malformed)?;
+
+            let dc = tables.dc[(selector[1] >> 4 & 3) as usize].as_ref();
+            let ac = tables.ac[(selector[1] & 3) as usize].as_ref();
+
+            Ok((index, dc, ac))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reader = BitReader {
+        bytes,
+        pos,
+        bits: 0,
+        count: 0,
+    };
+
+    // NOTE: a scan without the luma, e.g. of a non-interleaved chroma
+    // component, is skipped without decoding it
+    if !scan.iter().any(|&(index, _, _)| index == 0) {
+        return Ok(reader.end());
+    }
+
+    let quantization = tables.quantization[frame.components[0].quantization] as f64;
+
+    // NOTE: a single component scan is made of its blocks, without the
+    // padding to a whole number of MCUs
+    let (mcus_x, mcus_y) = match scan.as_slice() {
+        [(index, _, _)] => {
+            let component = frame.components[*index];
+            let width = (frame.width * component.h).div_ceil(frame.h_max);
+            let height = (frame.height * component.v).div_ceil(frame.v_max);
+
+            (width.div_ceil(8), height.div_ceil(8))
+        }
+        _ => (
+            frame.width.div_ceil(8 * frame.h_max),
+            frame.height.div_ceil(8 * frame.v_max),
+        ),
+    };
+
+    let mut predictions = vec![0i32; frame.components.len()];
+
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+            predictions.fill(0);
+        }
+
+        let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+
+        for &(index, dc, ac) in &scan {
+            let component = frame.components[index];
+
+            let (h, v) = match scan.len() {
+                1 => (1, 1),
+                _ => (component.h, component.v),
+            };
+
+            for block in 0..h * v {
+                let dc = dc.ok_or(HashJpegDcError::Malformed)?;
+                let ac = ac.ok_or(HashJpegDcError::Malformed)?;
+
+                let size = dc.decode(&mut reader)?;
+
+                // NOTE: Very important, larger sizes overflow the shifts
+                // of `receive_extend`
+                if size > 11 {
+                    return Err(HashJpegDcError::Malformed);
+                }
+
+                predictions[index] = predictions[index].wrapping_add(reader.receive_extend(size)?);
+
+                let mut k = 1;
+
+                while k < 64 {
+                    let run_size = ac.decode(&mut reader)?;
+                    let (run, size) = (run_size >> 4, run_size & 15);
+
+                    if size == 0 && run != 15 {
+                        break;
+                    }
+
+                    if size > 10 {
+                        return Err(HashJpegDcError::Malformed);
+                    }
+
+                    reader.bits(size)?;
+                    k += run as usize + 1;
+                }
+
+                if index == 0 {
+                    let mean = predictions[0] as f64 * quantization / 8.0 + 128.0;
+
+                    cells.add_block(
+                        mcu_x * h + block % h,
+                        mcu_y * v + block / h,
+                        mean.clamp(0.0, u8::MAX as f64),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(reader.end())
+}
+
+/// Luma of the cells of the grid, summed over the area of the blocks they
+/// cover.
+struct CellSums {
+    sums: [[f64; 9]; 8],
+    cell_width: usize,
+    cell_height: usize,
+    /// Size of a luma block in pixels, larger than 8 if the luma is
+    /// subsampled
+    block_width: usize,
+    block_height: usize,
+}
+
+impl CellSums {
+    fn new(frame: &Frame) -> Self {
+        let luma = frame.components[0];
+
+        Self {
+            sums: [[0.0; 9]; 8],
+            cell_width: frame.width / 9,
+            cell_height: frame.height / 8,
+            block_width: 8 * (frame.h_max / luma.h).max(1),
+            block_height: 8 * (frame.v_max / luma.v).max(1),
+        }
+    }
+
+    fn add_block(&mut self, block_x: usize, block_y: usize, mean: f64) {
+        if self.cell_width == 0 || self.cell_height == 0 {
+            return;
+        }
+
+        let overlaps = |from: usize, size: usize, cell_size: usize, cells: usize| {
+            let to = (from + size).min(cell_size * cells);
+
+            (from / cell_size..cells)
+                .take_while(move |cell| cell * cell_size < to)
+                .map(move |cell| {
+                    let start = from.max(cell * cell_size);
+                    let end = to.min((cell + 1) * cell_size);
+
+                    (cell, end.saturating_sub(start))
+                })
+        };
+
+        let x = block_x * self.block_width;
+        let y = block_y * self.block_height;
+
+        for (cell_y, height) in overlaps(y, self.block_height, self.cell_height, 8) {
+            for (cell_x, width) in overlaps(x, self.block_width, self.cell_width, 9) {
+                self.sums[cell_y][cell_x] += mean * (width * height) as f64;
+            }
+        }
+    }
+
+    fn grid(&self) -> Grid {
+        let area = (self.cell_width * self.cell_height) as f64;
+
+        Grid::new(
+            self.sums
+                .map(|row| row.map(|sum| if area > 0.0 { sum / area } else { 0.0 })),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_jpeg_dc, HashJpegDcError};
+    use crate::Dhash;
+    use proptest::prelude::*;
+    use std::fs;
+
+    /// Bits written most significant first, with the stuffed zero bytes
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: u32,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, count: u32) {
+            for i in (0..count).rev() {
+                self.bits = self.bits << 1 | (value >> i & 1);
+                self.count += 1;
+
+                if self.count == 8 {
+                    self.bytes.push(self.bits as u8);
+
+                    if self.bits == 0xFF {
+                        self.bytes.push(0x00);
+                    }
+
+                    self.bits = 0;
+                    self.count = 0;
+                }
+            }
+        }
+
+        /// Pads the last byte with ones
+        fn flush(&mut self) {
+            while self.count != 0 {
+                self.write(1, 1);
+            }
+        }
+    }
+
+    /// Baseline JPEG whose blocks only have a DC coefficient, `mean`
+    /// giving the mean of the luma block at the given position, the chroma
+    /// blocks are neutral.
+    fn dc_only_jpeg(
+        width: usize,
+        height: usize,
+        chroma: bool,
+        restart_interval: usize,
+        mean: impl Fn(usize, usize) -> i32,
+    ) -> Vec<u8> {
+        // NOTE: Annex K luminance DC table, the AC table only codes the end
+        // of block, as a single 0 bit
+        let dc_counts = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+        let dc_codes = [
+            (0b00, 2),
+            (0b010, 3),
+            (0b011, 3),
+            (0b100, 3),
+            (0b101, 3),
+            (0b110, 3),
+            (0b1110, 4),
+            (0b11110, 5),
+            (0b111110, 6),
+            (0b1111110, 7),
+            (0b11111110, 8),
+            (0b111111110, 9),
+        ];
+
+        let mut jpeg = vec![0xFF, 0xD8];
+        let mut segment = |marker: u8, data: &[u8]| {
+            jpeg.extend([0xFF, marker]);
+            jpeg.extend(((data.len() + 2) as u16).to_be_bytes());
+            jpeg.extend(data);
+        };
+
+        let mut quantization = vec![0];
+        quantization.extend([1; 64]);
+        segment(0xDB, &quantization);
+
+        let (luma_sampling, components) = if chroma { (0x22, 3) } else { (0x11, 1) };
+        let mut frame = vec![8];
+        frame.extend((height as u16).to_be_bytes());
+        frame.extend((width as u16).to_be_bytes());
+        frame.push(components);
+        frame.extend([1, luma_sampling, 0]);
+
+        if chroma {
+            frame.extend([2, 0x11, 0, 3, 0x11, 0]);
+        }
+
+        segment(0xC0, &frame);
+
+        let mut huffman = vec![0x00];
+        huffman.extend(dc_counts);
+        huffman.extend(0..12);
+        huffman.push(0x10);
+        huffman.extend([1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        huffman.push(0x00);
+        segment(0xC4, &huffman);
+
+        if restart_interval > 0 {
+            segment(0xDD, &(restart_interval as u16).to_be_bytes());
+        }
+
+        let mut scan = vec![components];
+
+        for id in 1..=components {
+            scan.extend([id, 0x00]);
+        }
+
+        scan.extend([0, 63, 0]);
+        segment(0xDA, &scan);
+
+        let (h, v) = if chroma { (2, 2) } else { (1, 1) };
+        let mcus_x = width.div_ceil(8 * h);
+        let mcus_y = height.div_ceil(8 * v);
+
+        let mut writer = BitWriter::default();
+        let mut predictions = [0i32; 3];
+        let block =
+            |writer: &mut BitWriter, predictions: &mut [i32; 3], component: usize, dc: i32| {
+                let difference = dc - predictions[component];
+                predictions[component] = dc;
+
+                let size = 32 - difference.unsigned_abs().leading_zeros();
+                let (code, len) = dc_codes[size as usize];
+
+                writer.write(code, len);
+
+                let value = if difference < 0 {
+                    difference + (1 << size) - 1
+                } else {
+                    difference
+                };
+
+                writer.write(value as u32, size);
+                // NOTE: end of block
+                writer.write(0, 1);
+            };
+
+        for mcu in 0..mcus_x * mcus_y {
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+                writer.flush();
+                writer
+                    .bytes
+                    .extend([0xFF, 0xD0 + ((mcu / restart_interval - 1) % 8) as u8]);
+                predictions = [0; 3];
+            }
+
+            let (mcu_x, mcu_y) = (mcu % mcus_x, mcu / mcus_x);
+
+            for i in 0..h * v {
+                let dc = (mean(mcu_x * h + i % h, mcu_y * v + i / h) - 128) * 8;
+                block(&mut writer, &mut predictions, 0, dc);
+            }
+
+            if chroma {
+                block(&mut writer, &mut predictions, 1, 0);
+                block(&mut writer, &mut predictions, 2, 0);
+            }
+        }
+
+        writer.flush();
+
+        jpeg.extend(writer.bytes);
+        jpeg.extend([0xFF, 0xD9]);
+
+        jpeg
+    }
+
+    fn full_decode(bytes: &[u8]) -> Dhash {
+        let image = image::load_from_memory(bytes).unwrap().to_rgb8();
+
+        Dhash::new(image.as_raw(), image.width(), image.height(), 3)
+    }
+
+    #[test]
+    fn close_to_full_decode() {
+        for path in [
+            ".test/grad.ffff.jpg",
+            ".test/grad.0000.jpg",
+            ".test/radial.jpg",
+        ] {
+            let bytes = fs::read(path).unwrap();
+            let distance = hash_jpeg_dc(&bytes)
+                .unwrap()
+                .hamming_distance(&full_decode(&bytes));
+
+            assert!(distance <= 4, "{}: {} bits", path, distance);
+        }
+    }
+
+    #[test]
+    fn subsampling_and_restarts() {
+        let mean = |x: usize, y: usize| ((x * 7 + y * 3) % 200 + (x * y) % 50) as i32;
+
+        // NOTE: neither size is a multiple of the MCU, nor the row of MCUs
+        // of the restart interval
+        for (chroma, restart_interval) in [(true, 0), (true, 7), (false, 5), (false, 0)] {
+            let bytes = dc_only_jpeg(203, 157, chroma, restart_interval, mean);
+            let distance = hash_jpeg_dc(&bytes)
+                .unwrap()
+                .hamming_distance(&full_decode(&bytes));
+
+            assert!(
+                distance <= 1,
+                "{} {}: {} bits",
+                chroma,
+                restart_interval,
+                distance
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut progressive = fs::read(".test/radial.jpg").unwrap();
+        let sof = progressive
+            .windows(2)
+            .position(|marker| marker == [0xFF, 0xC0])
+            .unwrap();
+
+        progressive[sof + 1] = 0xC2;
+
+        assert_eq!(
+            hash_jpeg_dc(&progressive),
+            Err(HashJpegDcError::Unsupported("progressive"))
+        );
+        assert_eq!(hash_jpeg_dc(b"not a jpeg"), Err(HashJpegDcError::Malformed));
+
+        let bytes = fs::read(".test/radial.jpg").unwrap();
+
+        assert_eq!(
+            hash_jpeg_dc(&bytes[..bytes.len() / 2]),
+            Err(HashJpegDcError::Malformed)
+        );
+    }
+
+    #[test]
+    fn crafted() {
+        let mut bytes = dc_only_jpeg(64, 64, false, 0, |_, _| 128);
+        let dht = bytes
+            .windows(2)
+            .position(|marker| marker == [0xFF, 0xC4])
+            .unwrap();
+
+        // NOTE: a DC table whose only symbol, coded as a single 0 bit, is a
+        // 40 bits difference
+        let mut dc = vec![0x00, 1];
+        dc.extend([0; 15]);
+        dc.push(40);
+
+        let len = u16::from_be_bytes([bytes[dht + 2], bytes[dht + 3]]) - 11;
+        bytes[dht + 2..dht + 4].copy_from_slice(&len.to_be_bytes());
+        bytes.splice(dht + 4..dht + 33, dc);
+
+        assert_eq!(hash_jpeg_dc(&bytes), Err(HashJpegDcError::Malformed));
+
+        // NOTE: a 65535x65535 frame whose scan only has the data of 2 blocks
+        let mut bytes = dc_only_jpeg(16, 8, false, 0, |_, _| 128);
+        let sof = bytes
+            .windows(2)
+            .position(|marker| marker == [0xFF, 0xC0])
+            .unwrap();
+
+        bytes[sof + 5..sof + 9].fill(0xFF);
+
+        assert_eq!(hash_jpeg_dc(&bytes), Err(HashJpegDcError::Malformed));
+    }
+
+    proptest! {
+        #[test]
+        fn mutated(
+            fixture in 0usize..2,
+            mutations in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..8),
+            truncate: prop::sample::Index,
+        ) {
+            let mut bytes = match fixture {
+                0 => fs::read(".test/radial.jpg").unwrap(),
+                _ => dc_only_jpeg(43, 37, true, 3, |x, y| (x * 31 + y * 17) as i32 % 256),
+            };
+
+            for (index, byte) in mutations {
+                let len = bytes.len();
+                bytes[index.index(len)] = byte;
+            }
+
+            let len = bytes.len();
+            bytes.truncate(len - truncate.index(len / 4));
+
+            // NOTE: only checks nothing panics, either result is fine
+            let _ = hash_jpeg_dc(&bytes);
+        }
+    }
+}
@@ -27,244 +27,2562 @@
 //! println!("hash: {}", hash);
 //! // hash: f0f0e8cccce8f0f0
 //! ```
+//!
+//! ## Features
+//!
+//! - `serde` *(default)*: `Serialize` and `Deserialize` implementations for the hash types, disable default features to drop the dependency.
+//! - `async`: `Dhash::new_async`, `Dhash::new_async_owned` and `DhashHasher::hash_async`, hashing on tokio's blocking thread pool, and `DhashStreamExt::dhash_frames`, hashing a `Stream` of frames a few at a time with *futures-util*.
+//! - `simd`: AVX-512 `Dhash::hamming_distance_batch`, detected at runtime with a scalar fallback.
+//! - `image`: `Dhash::from_image`, hashing a `DynamicImage` of any color type, 16 bits and floating point ones included, `hash_file`, opening and decoding the image first, `hash_frames`, hashing every frame of an animated GIF, WebP or APNG, `Dhash::from_source` for typed `ImageBuffer`s and `Dhash::from_view` for any `GenericImageView`, crops included.
+//! - `walk`: `hash_directory`, decoding and hashing every image of a directory tree in parallel with *image* and *rayon*.
+//! - `borsh`: `BorshSerialize` and `BorshDeserialize` for `Dhash`, as its little-endian `u64`.
+//! - `rkyv`: `Archive`, `Serialize` and `Deserialize` for `Dhash`, an archived `[Dhash]` can be scanned in place, e.g. from a memory-mapped file.
+//! - `constant-time`: `Dhash::hamming_distance_ct` and `subtle`'s `ConstantTimeEq`, comparisons written without data-dependent branches, a best-effort guarantee.
+//! - `ndarray`: `Dhash::from_ndarray` and `Dhash::from_ndarray_f32`, hashing HWC or CHW `ArrayView3` tensors in place, strided views included, and `Dhash::luma_grid_as_array` and `Dhash::from_luma_array`, the luma grid as an `Array2`.
+//! - `sqlite`: `DhashDatabase`, hashes and their metadata persisted in an SQLite database with *rusqlite*, similarity searches going through indexed 16 bits chunks of the hashes.
+//! - `rand`: `Dhash::perturb`, flipping a given number of random bits, to generate near duplicates at a known distance.
+//! - `jpeg-dc`: `hash_jpeg_dc`, hashing baseline JPEGs from the DC coefficients of their luma blocks, without the inverse DCT nor any dependency.
+//! - `rayon`: computes the rows of the grid on the current rayon thread pool instead of spawning a thread each, for services already running on one, the hashes are the same.
+//! - `crypto`: `DhashSigner`, HMAC-SHA256 signatures of hashes with *hmac* and *sha2*, to detect tampered entries in a hash store, the key being zeroed on drop with *zeroize*.
+//! - `msgpack`: `Dhash::to_msgpack` and `Dhash::from_msgpack`, the hash as a MessagePack `[hash]` array with *rmp-serde*, at most 10 bytes.
+
+#![warn(clippy::undocumented_unsafe_blocks)]
+use accumulator::DhashAccumulator;
+#[cfg(feature = "borsh")]
+use borsh::{BorshDeserialize, BorshSerialize};
+use builder::wrapped_bits;
+use grid::{
+    channels_luma, check_dimensions, first_channel_grid, grid_from_rgb, luma_grid, pixel_channels,
+    subsampled_luma_grid, workers_for, Reduction, Sample, ScanOrder,
+};
+#[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
-use std::{fmt, num, str, thread};
+use std::{error, fmt, io, iter, num, str};
+
+mod accumulator;
+pub mod average;
+pub mod bank;
+mod batch;
+pub mod builder;
+pub mod codec;
+pub mod crop;
+#[cfg(feature = "sqlite")]
+pub mod database;
+pub mod ensemble;
+pub mod family;
+#[cfg(feature = "image")]
+pub mod file;
+pub mod filter;
+pub mod grid;
+pub mod hasher;
+pub mod histogram;
+#[cfg(feature = "jpeg-dc")]
+pub mod jpeg_dc;
+mod many;
+pub mod multiscale;
+pub mod radial;
+pub mod ring;
+pub mod sequence;
+pub mod set;
+#[cfg(feature = "crypto")]
+pub mod signer;
+pub mod source;
+#[cfg(feature = "async")]
+pub mod stream;
+#[cfg(feature = "ndarray")]
+pub mod tensor;
+mod thumbnail;
+pub mod versioned;
+pub mod video;
+pub mod vp_tree;
+#[cfg(feature = "walk")]
+pub mod walk;
+
+pub use accumulator::GridBuilder;
+pub use average::AverageHash;
+pub use bank::DhashBank;
+pub use builder::{CellAggregation, ChannelOrder, Comparison, DhashBuilder, RoundingMode};
+pub use crop::CropResistantHash;
+#[cfg(feature = "sqlite")]
+pub use database::DhashDatabase;
+pub use ensemble::EnsembleHash;
+pub use family::{HashFamily, HashKinds};
+#[cfg(feature = "image")]
+pub use file::{hash_file, hash_frames, hash_unique_frames, HashFileError};
+pub use filter::{DhashFilter, FilterResult};
+pub use grid::{
+    compute_grid, compute_grid_with_stats, compute_thumbnail, flip_grid_horizontal,
+    flip_grid_vertical, rotate_grid_90, Grid, GridStats, PARALLEL_THRESHOLD,
+};
+pub use hasher::DhashHasher;
+pub use histogram::{
+    collection_stats, false_match_probability, population_entropy, DhashCollectionStats,
+    DhashHistogram,
+};
+#[cfg(feature = "jpeg-dc")]
+pub use jpeg_dc::{hash_jpeg_dc, HashJpegDcError};
+pub use many::{hash_many, ImageRef};
+pub use multiscale::{DhashN, MultiScaleHash};
+pub use radial::RadialHash;
+pub use ring::DhashRing;
+pub use sequence::{
+    ClipMatch, DhashSequence, SequenceAlignment, SequenceDistance, VideoFingerprint,
+};
+pub use set::{ClassId, DhashSet};
+#[cfg(feature = "crypto")]
+pub use signer::DhashSigner;
+pub use source::{HashableImage, PixelSource, SyntheticGradient};
+#[cfg(feature = "async")]
+pub use stream::{DhashStreamExt, Frame};
+#[cfg(feature = "ndarray")]
+pub use tensor::TensorLayout;
+pub use versioned::{VersionedDhash, CURRENT_VERSION};
+pub use video::{frame_diff, scene_changes, FrameDiff, SceneDetector, VideoHasher};
+pub use vp_tree::DhashVPTree;
+#[cfg(feature = "walk")]
+pub use walk::hash_directory;
+
+/// Fuzzy comparison of perceptual hashes.
+///
+/// `PartialEq` is exact, two hashes are equal only if all of their bits are.
+///
+/// ## Migration
+///
+/// Up to version 1.1, `==` on [`Dhash`] was fuzzy, true below a hamming
+/// distance of 11, it is exact since 2.0, replace `a == b` with
+/// `a.similar_to(&b, 11)` to keep the previous behavior.
+pub trait SimilarTo {
+    /// Whether the hamming distance is strictly below `threshold`.
+    ///
+    /// The thresholds taken elsewhere in the crate, e.g. by
+    /// `DhashDatabase::find_similar`, [`DhashFilter::or_block_similar`] or
+    /// [`DhashCollectionStats`], are inclusive: `similar_to(other, t)` is
+    /// their threshold `t - 1`.
+    fn similar_to(&self, other: &Self, threshold: u32) -> bool;
+}
+
+/// Exact equality, see [`SimilarTo`] for fuzzy comparisons.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "borsh", derive(BorshDeserialize, BorshSerialize))]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Deserialize, rkyv::Serialize),
+    rkyv(derive(Debug, PartialEq, Eq))
+)]
+pub struct Dhash {
+    /// Prefer [`Dhash::as_u64`], the field may become private in a future
+    /// major version.
+    pub hash: u64,
+}
+
+impl Dhash {
+    /// With 3 or more channels the first three are read as RGB, with 2 as
+    /// luma and alpha, transparent pixels reading as white.
+    pub fn new(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        match compute_grid(bytes, width, height, channel_count) {
+            Ok(grid) => Self::from_grid(&grid),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Same as [`Dhash::new`], reading the pixels and their layout from
+    /// `source`, any [`PixelSource`] or, with the `image` feature, typed
+    /// image buffers.
+    pub fn from_source<S: HashableImage + ?Sized>(source: &S) -> Self {
+        match source.try_dhash() {
+            Ok(hash) => hash,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Same as [`Dhash::new`], returning an error rather than panicking on
+    /// invalid dimensions.
+    pub fn try_new(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        compute_grid(bytes, width, height, channel_count).map(|grid| Self::from_grid(&grid))
+    }
+
+    /// Approximate [`Dhash::new`] reading one pixel every `step` columns and
+    /// rows of each cell, about `1 / step²` of the pixels, meant as a cheap
+    /// prefilter before exact hashing.
+    ///
+    /// Cells are still averaged over their whole area, so the hash only
+    /// drifts when details are finer than `step` pixels, typically by a few
+    /// bits on photos and more on noise, text or fine patterns, which can
+    /// alias. A `step` of 0 is read as 1 and at least the top left pixel of
+    /// each cell is read, however large the step.
+    pub fn new_subsampled(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+        step: usize,
+    ) -> Self {
+        let step = step.max(1);
+        let pixels = (width as usize).div_ceil(step) * (height as usize).div_ceil(step);
+        let reduction = Reduction {
+            step,
+            workers: workers_for(pixels, None, PARALLEL_THRESHOLD),
+            ..Default::default()
+        };
+
+        match subsampled_luma_grid(bytes, width, height, channel_count, reduction) {
+            Ok(grid) => Self::from_grid(&Grid::new(grid)),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Hash of the image box downsampled by `2^shift`, each block of
+    /// `2^shift` x `2^shift` pixels averaged into a working buffer, as a mip
+    /// level would be, before being hashed as with [`Dhash::new`].
+    ///
+    /// Every pixel is still read once, on the calling thread, summed as
+    /// integers, the grid then only goes through `1 / 4^shift` of them: on a
+    /// 4K frame, a shift of 3 is about 1.5 times faster than [`Dhash::new`]
+    /// on a single core, while a shift of 1 is 3 to 4 times slower, its f64
+    /// working buffer being twice the size of the image. To read fewer
+    /// pixels, at the cost of aliasing, see [`Dhash::new_subsampled`].
+    ///
+    /// The cells are averages of whole blocks, the pixels past the last full
+    /// block of a row or a column are ignored. When the width and height are
+    /// multiples of `9 * 2^shift` and `8 * 2^shift`, the cells are those of
+    /// [`Dhash::new`] up to floating point rounding, so is the hash but for
+    /// near ties, otherwise it is within a few bits on photos. LumaA is the
+    /// exception: blocks are composited on white after their alpha is
+    /// averaged, so partially transparent blocks whose alpha varies differ
+    /// from [`Dhash::new`], which composites each pixel.
+    ///
+    /// The downsampled image must be at least 9x8 pixels, otherwise the
+    /// error is [`DhashError::InvalidShape`] with its rows and columns.
+    pub fn new_downsample_pow2(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+        shift: u32,
+    ) -> Result<Self, DhashError> {
+        let width = width as usize;
+        let height = height as usize;
+        let channel_count = channel_count as usize;
+
+        // NOTE: Very important, prevents possible segfault
+        check_dimensions(bytes.len(), width, height, channel_count)?;
+
+        let reduced_width = width.checked_shr(shift).unwrap_or(0);
+        let reduced_height = height.checked_shr(shift).unwrap_or(0);
+
+        if reduced_width < 9 || reduced_height < 8 {
+            return Err(DhashError::InvalidShape {
+                expected: (8, 9),
+                actual: (reduced_height, reduced_width),
+            });
+        }
+
+        let block = 1 << shift;
+        let row_len = reduced_width * channel_count;
+
+        // NOTE: block means in [0, 1], the range of f64 samples, alpha included
+        let scale = (block * block) as f64 * u8::MAX as f64;
+
+        // NOTE: exact integer sums, 255 * 4^shift fits in a u64 for any
+        // shift leaving a 9x8 image, one row of blocks at a time
+        let mut sums = vec![0u64; row_len];
+        let mut reduced = vec![0.0; row_len * reduced_height];
+
+        for (y, row) in reduced.chunks_exact_mut(row_len).enumerate() {
+            sums.fill(0);
+
+            for image_y in y * block..(y + 1) * block {
+                let line = &bytes[image_y * width * channel_count..][..row_len * block];
+
+                match channel_count {
+                    1 => add_blocks::<1>(&mut sums, line, block),
+                    2 => add_blocks::<2>(&mut sums, line, block),
+                    3 => add_blocks::<3>(&mut sums, line, block),
+                    4 => add_blocks::<4>(&mut sums, line, block),
+                    _ => {
+                        for (sum, blocks) in sums
+                            .chunks_exact_mut(channel_count)
+                            .zip(line.chunks_exact(channel_count * block))
+                        {
+                            for pixel in blocks.chunks_exact(channel_count) {
+                                for (sum, &sample) in sum.iter_mut().zip(pixel) {
+                                    *sum += sample as u64;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            for (mean, &sum) in row.iter_mut().zip(&sums) {
+                *mean = sum as f64 / scale;
+            }
+        }
+
+        luma_grid(
+            &reduced,
+            reduced_width as u32,
+            reduced_height as u32,
+            channel_count as u8,
+            CellAggregation::Sum,
+        )
+        .map(|grid| Self::from_grid(&Grid::new(grid)))
+    }
+
+    /// Same as [`Dhash::new`] for images already in grayscale whatever their
+    /// channel count, e.g. RGB with R = G = B, reading only the first channel
+    /// of each pixel as its luma and skipping the weighted sum.
+    ///
+    /// The hash differs from [`Dhash::new`] when the channels actually
+    /// differ, and alpha is ignored.
+    pub fn new_assume_grayscale(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        match first_channel_grid(bytes, width, height, channel_count) {
+            Ok(grid) => Self::from_grid(&Grid::new(grid)),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Same as [`Dhash::new`], also returning the luma histogram of the
+    /// hashed pixels gathered in the same pass, see [`GridStats::histogram`].
+    pub fn new_with_histogram(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> (Self, [u32; 256]) {
+        match compute_grid_with_stats(bytes, width, height, channel_count) {
+            Ok((grid, stats)) => (Self::from_grid(&grid), stats.histogram),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Same as [`Dhash::new`], also returning the mean luma of the hashed
+    /// pixels, rounded, to flag near black or near white images, whose hash
+    /// is mostly noise.
+    ///
+    /// Computed from the cell means of the grid, without another pass over
+    /// the pixels.
+    pub fn with_brightness(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> (Self, u8) {
+        match compute_grid(bytes, width, height, channel_count) {
+            Ok(grid) => {
+                let mean = grid.rows().iter().flatten().sum::<f64>() / 72.0;
+
+                (
+                    Self::from_grid(&grid),
+                    mean.round().clamp(0.0, u8::MAX as f64) as u8,
+                )
+            }
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Alternative algorithm comparing each cell of an 8x8 grid with the
+    /// median of the grid, rather than with its right neighbor.
+    ///
+    /// The hash describes where the image is brighter or darker than its
+    /// median, so about half of the bits are always set and local horizontal
+    /// gradients, that flip neighbor comparisons, barely affect it. On the
+    /// other hand images with the same bright and dark regions but different
+    /// details collide more often than with [`Dhash::new`].
+    ///
+    /// The result has the same 64 bits layout and works with
+    /// [`Dhash::hamming_distance`], but is only comparable with hashes
+    /// computed the same way.
+    pub fn new_median(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let grid: [[f64; 8]; 8] =
+            match luma_grid(bytes, width, height, channel_count, CellAggregation::Sum) {
+                Ok(grid) => grid,
+                Err(error) => panic!("{}", error),
+            };
+
+        Self {
+            hash: family::median_bits(&grid),
+        }
+    }
+
+    /// Average hash, comparing each cell of an 8x8 grid with the mean of
+    /// the grid, only comparable with hashes computed the same way.
+    pub fn new_average(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let grid: [[f64; 8]; 8] =
+            match luma_grid(bytes, width, height, channel_count, CellAggregation::Sum) {
+                Ok(grid) => grid,
+                Err(error) => panic!("{}", error),
+            };
+
+        Self {
+            hash: family::average_bits(&grid),
+        }
+    }
+
+    /// The dhash and the average hash of the image, from a single scan of
+    /// its pixels, see [`EnsembleHash`].
+    ///
+    /// Each hash is equal to its standalone computation, [`Dhash::new`] and
+    /// [`AverageHash::new`].
+    pub fn ensemble(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> EnsembleHash {
+        match EnsembleHash::try_new(bytes, width, height, channel_count) {
+            Ok(hash) => hash,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Vertical dhash, comparing each cell of an 8x9 grid with the one
+    /// below it, only comparable with hashes computed the same way.
+    pub fn new_vertical(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let grid: [[f64; 8]; 9] =
+            match luma_grid(bytes, width, height, channel_count, CellAggregation::Sum) {
+                Ok(grid) => grid,
+                Err(error) => panic!("{}", error),
+            };
+
+        Self {
+            hash: family::vertical_bits(&grid),
+        }
+    }
+
+    /// Hash of the transpose of the image, as [`Dhash::new`] would compute
+    /// it on the transposed pixels, without transposing them.
+    ///
+    /// The image is reduced to 9 rows of 8 cells, read as the 8 rows of 9
+    /// cells of its transpose, e.g. to compare a portrait image with the
+    /// landscape relayout of another.
+    pub fn transposed(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let grid: [[f64; 8]; 9] =
+            match luma_grid(bytes, width, height, channel_count, CellAggregation::Sum) {
+                Ok(grid) => grid,
+                Err(error) => panic!("{}", error),
+            };
+
+        let mut cells = [[0f64; 9]; 8];
+
+        for (x, row) in grid.iter().enumerate() {
+            for (y, cell) in row.iter().enumerate() {
+                cells[y][x] = *cell;
+            }
+        }
+
+        Self::from_grid(&Grid::new(cells))
+    }
+
+    /// Smallest of the hashes of the image rotated by 0°, 90°, 180° and
+    /// 270°, so that rotated copies of an image, e.g. portrait and landscape
+    /// uploads of a photo, share the same hash.
+    ///
+    /// The image is reduced once to 8 rows of 9 cells and once to 9 rows of
+    /// 8 cells, the grids of the rotations are rotations of these, the
+    /// pixels are not. The cells match those of the rotated image exactly
+    /// when both sides are multiples of 72, otherwise the remainder pixels
+    /// left out are not the same and rotated copies may differ by a few bits.
+    pub fn new_canonical(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let (wide, tall): ([[f64; 9]; 8], [[f64; 8]; 9]) = match (
+            luma_grid(bytes, width, height, channel_count, CellAggregation::Sum),
+            luma_grid(bytes, width, height, channel_count, CellAggregation::Sum),
+        ) {
+            (Ok(wide), Ok(tall)) => (wide, tall),
+            (Err(error), _) | (_, Err(error)) => panic!("{}", error),
+        };
+
+        // NOTE: clockwise, 180° is both mirrorings and 270° is 90° upside down
+        smallest_hash(&[
+            wide,
+            rotate_grid_90(&tall),
+            flip_grid_vertical(&flip_grid_horizontal(&wide)),
+            rotate_grid_90(&flip_grid_vertical(&flip_grid_horizontal(&tall))),
+        ])
+    }
+
+    /// Smallest of the hashes of the image, mirrored horizontally,
+    /// vertically and both, so that mirrored copies of an image share the
+    /// same hash.
+    ///
+    /// The mirrored grids are the grid of the image with its columns or
+    /// rows reversed, they match those of the mirrored image exactly when
+    /// the width is a multiple of 9 and the height of 8, otherwise the
+    /// remainder pixels left out are not the same and mirrored copies may
+    /// differ by a few bits.
+    pub fn new_flip_canonical(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let grid: [[f64; 9]; 8] =
+            match luma_grid(bytes, width, height, channel_count, CellAggregation::Sum) {
+                Ok(grid) => grid,
+                Err(error) => panic!("{}", error),
+            };
+
+        smallest_hash(&[
+            grid,
+            flip_grid_horizontal(&grid),
+            flip_grid_vertical(&grid),
+            flip_grid_vertical(&flip_grid_horizontal(&grid)),
+        ])
+    }
+
+    /// Same as [`Dhash::try_new`], running on tokio's blocking thread pool
+    /// so that hashing does not stall the async runtime.
+    ///
+    /// The bytes are copied, as the blocking task cannot borrow them.
+    #[cfg(feature = "async")]
+    pub async fn new_async(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        let bytes = bytes.to_vec();
+
+        match tokio::task::spawn_blocking(move || {
+            Self::try_new(&bytes, width, height, channel_count)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+
+    /// Same as [`Dhash::new_async`], taking ownership of the buffer, e.g. a
+    /// `Vec<u8>` or the `Bytes` of a request body, rather than copying it.
+    ///
+    /// Dropping the future does not stop the blocking task, which finishes
+    /// hashing and then releases the buffer.
+    #[cfg(feature = "async")]
+    pub async fn new_async_owned<B>(
+        bytes: B,
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError>
+    where
+        B: AsRef<[u8]> + Send + 'static,
+    {
+        match tokio::task::spawn_blocking(move || {
+            Self::try_new(bytes.as_ref(), width, height, channel_count)
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+
+    /// Computes the hash from `f32` samples, e.g. the pixels of an HDR
+    /// (OpenEXR) image, without quantizing them to `u8` first.
+    ///
+    /// Samples are accumulated as they are, values outside `[0, 1]` are NOT
+    /// clamped: since the hash only compares neighboring cells, any linear
+    /// range works and clamping would flatten the highlights the HDR data is
+    /// meant to preserve. Clamp beforehand to mimic a tone-mapped image.
+    /// Non-finite samples (`NaN`, `inf`) propagate to the cells they fall in
+    /// and make the corresponding bits meaningless.
+    pub fn new_f32(samples: &[f32], width: u32, height: u32, channel_count: u8) -> Self {
+        Self::from_samples(samples, width, height, channel_count)
+    }
+
+    /// Same as [`Dhash::new_f32`], for `f64` samples.
+    pub fn new_f64(samples: &[f64], width: u32, height: u32, channel_count: u8) -> Self {
+        Self::from_samples(samples, width, height, channel_count)
+    }
+
+    /// Computes the hash of a decoded image, 16 bits and floating point
+    /// images are read at their full precision rather than as bytes.
+    #[cfg(feature = "image")]
+    pub fn from_image(image: &image::DynamicImage) -> Self {
+        match Self::try_from_image(image) {
+            Ok(hash) => hash,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// Same as [`Dhash::from_image`], returning an error rather than
+    /// panicking if the buffer does not match the image dimensions.
+    #[cfg(feature = "image")]
+    pub fn try_from_image(image: &image::DynamicImage) -> Result<Self, DhashError> {
+        use image::DynamicImage;
+
+        let (width, height) = (image.width(), image.height());
+        let channel_count = image.color().channel_count();
+
+        match image {
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => {
+                Self::try_new(image.as_bytes(), width, height, channel_count)
+            }
+            DynamicImage::ImageLuma16(buffer) => {
+                Self::try_from_samples(buffer.as_raw(), width, height, channel_count)
+            }
+            DynamicImage::ImageLumaA16(buffer) => {
+                Self::try_from_samples(buffer.as_raw(), width, height, channel_count)
+            }
+            DynamicImage::ImageRgb16(buffer) => {
+                Self::try_from_samples(buffer.as_raw(), width, height, channel_count)
+            }
+            DynamicImage::ImageRgba16(buffer) => {
+                Self::try_from_samples(buffer.as_raw(), width, height, channel_count)
+            }
+            DynamicImage::ImageRgb32F(buffer) => {
+                Self::try_from_samples(buffer.as_raw(), width, height, channel_count)
+            }
+            DynamicImage::ImageRgba32F(buffer) => {
+                Self::try_from_samples(buffer.as_raw(), width, height, channel_count)
+            }
+            // NOTE: DynamicImage is non exhaustive
+            image => Self::try_new(&image.to_rgba8(), width, height, 4),
+        }
+    }
+
+    /// Computes the hash of any image view, e.g. an `ImageBuffer` or a
+    /// cropped `SubImage`, without copying its pixels first.
+    ///
+    /// Pixels are read one at a time through the trait, which is slower than
+    /// the raw samples read by [`Dhash::from_image`] and
+    /// [`Dhash::from_source`], prefer them for whole images.
+    #[cfg(feature = "image")]
+    pub fn from_view<V>(view: &V) -> Self
+    where
+        V: image::GenericImageView + Sync,
+    {
+        Self::from_grid(&Grid::new(grid::view_luma_grid(view)))
+    }
+
+    /// Computes the hash of an `ndarray` image tensor, reading it through its
+    /// strides, so sliced and transposed views are hashed without copying.
+    ///
+    /// Channels are interpreted as by [`Dhash::new`], the error is
+    /// [`DhashError::InvalidChannelCount`] if there are none.
+    #[cfg(feature = "ndarray")]
+    pub fn from_ndarray(
+        view: ndarray::ArrayView3<u8>,
+        layout: TensorLayout,
+    ) -> Result<Self, DhashError> {
+        tensor::tensor_luma_grid(view, layout).map(|cells| Self::from_grid(&Grid::new(cells)))
+    }
+
+    /// Same as [`Dhash::from_ndarray`], for `f32` samples read as by
+    /// [`Dhash::new_f32`].
+    #[cfg(feature = "ndarray")]
+    pub fn from_ndarray_f32(
+        view: ndarray::ArrayView3<f32>,
+        layout: TensorLayout,
+    ) -> Result<Self, DhashError> {
+        tensor::tensor_luma_grid(view, layout).map(|cells| Self::from_grid(&Grid::new(cells)))
+    }
+
+    /// The [`Grid`] of an image, same inputs as [`Dhash::new`], as an 8x9
+    /// array of rows and columns.
+    #[cfg(feature = "ndarray")]
+    pub fn luma_grid_as_array(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<ndarray::Array2<f64>, DhashError> {
+        compute_grid(bytes, width, height, channel_count)
+            .map(|grid| ndarray::Array2::from_shape_fn((8, 9), |(y, x)| grid.rows()[y][x]))
+    }
+
+    /// Computes the hash of an 8x9 luma grid, as returned by
+    /// [`Dhash::luma_grid_as_array`], errors on any other shape.
+    #[cfg(feature = "ndarray")]
+    pub fn from_luma_array(array: &ndarray::Array2<f64>) -> Result<Self, DhashError> {
+        if array.dim() != (8, 9) {
+            return Err(DhashError::InvalidShape {
+                expected: (8, 9),
+                actual: array.dim(),
+            });
+        }
+
+        let mut cells = [[0f64; 9]; 8];
+
+        for ((y, x), cell) in array.indexed_iter() {
+            cells[y][x] = *cell;
+        }
+
+        Ok(Self::from_grid(&Grid::new(cells)))
+    }
+
+    fn from_samples<T: Sample>(samples: &[T], width: u32, height: u32, channel_count: u8) -> Self {
+        match Self::try_from_samples(samples, width, height, channel_count) {
+            Ok(hash) => hash,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    fn try_from_samples<T: Sample>(
+        samples: &[T],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        luma_grid(samples, width, height, channel_count, CellAggregation::Sum)
+            .map(|cells| Self::from_grid(&Grid::new(cells)))
+    }
+
+    /// Computes the hash from separate red, green and blue planes, as exposed
+    /// by some video codecs and camera APIs, without interleaving them first.
+    pub fn from_rgb_planar(
+        r: &[u8],
+        g: &[u8],
+        b: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Self, DhashError> {
+        let width = width as usize;
+        let height = height as usize;
+
+        for plane in [r, g, b] {
+            check_dimensions(plane.len(), width, height, 1)?;
+        }
+
+        let workers = workers_for(width * height, None, PARALLEL_THRESHOLD);
+        let col_bounds = RoundingMode::Floor.bounds(9, width);
+        let row_bounds = RoundingMode::Floor.bounds(8, height);
+
+        // SAFETY: the pixel index is below `width * height`, the length of
+        // each plane as checked above
+        let grid = grid_from_rgb(
+            width,
+            &col_bounds,
+            &row_bounds,
+            1,
+            ScanOrder::Rows,
+            workers,
+            |i| unsafe {
+                [
+                    *r.get_unchecked(i) as f64,
+                    *g.get_unchecked(i) as f64,
+                    *b.get_unchecked(i) as f64,
+                ]
+            },
+        );
+
+        Ok(Self::from_grid(&Grid::new(grid)))
+    }
+
+    /// Computes the hash of a planar YUV 4:2:0 frame, e.g. `yuv420p` frames
+    /// from FFmpeg or I420 camera buffers, from its Y plane alone, without
+    /// converting it to RGB: the hash only depends on the luma.
+    ///
+    /// The U and V planes are not read, only their sizes are checked, each
+    /// `⌈width / 2⌉ * ⌈height / 2⌉` bytes, the planes being tightly packed.
+    /// The Y plane is hashed as a grayscale image, as with
+    /// [`Dhash::try_new`], studio swing (16-235) and full range luma only
+    /// differ by an affine map, which keeps the comparisons of the cells.
+    pub fn from_yuv420(
+        y_plane: &[u8],
+        u_plane: &[u8],
+        v_plane: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Self, DhashError> {
+        let chroma_width = (width as usize).div_ceil(2);
+        let chroma_height = (height as usize).div_ceil(2);
+
+        for plane in [u_plane, v_plane] {
+            check_dimensions(plane.len(), chroma_width, chroma_height, 1)?;
+        }
+
+        Self::try_new(y_plane, width, height, 1)
+    }
+
+    /// Computes the hash reading the pixels from `reader` one row at a
+    /// time, without buffering the whole image.
+    ///
+    /// Errors if the reader ends before `width * height * channel_count`
+    /// bytes, the bytes past them are not read.
+    pub fn from_reader<R: io::Read>(
+        mut reader: R,
+        width: u32,
+        height: u32,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        let mut accumulator = DhashAccumulator::new(width, height, channel_count);
+
+        // NOTE: without channels the rows are empty, nothing would be read
+        accumulator.check_channels()?;
+
+        let mut row = vec![0u8; accumulator.row_len()];
+
+        for _ in 0..height {
+            let mut filled = 0;
+
+            while filled < row.len() {
+                match reader.read(&mut row[filled..]) {
+                    Ok(0) => {
+                        return Err(DhashError::InvalidDimensions {
+                            expected: height as usize * row.len(),
+                            actual: accumulator.rows() * row.len() + filled,
+                        })
+                    }
+                    Ok(n) => filled += n,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                    Err(error) => return Err(DhashError::Io(error.kind())),
+                }
+            }
+
+            accumulator.push_row(&row)?;
+        }
+
+        accumulator.finish().map(|grid| Self::from_grid(&grid))
+    }
+
+    /// Size of the region actually sampled for an image of the given size,
+    /// cells are `width / 9` by `height / 8` pixels and the remaining right
+    /// columns and bottom rows are ignored.
+    pub fn effective_dimensions(width: u32, height: u32) -> (u32, u32) {
+        (width / 9 * 9, height / 8 * 8)
+    }
+
+    /// Sets each bit if the cell is brighter than its right neighbor, the
+    /// least significant bit being the top left cell.
+    pub fn from_grid(grid: &Grid) -> Self {
+        let mut hash = 0u64;
+
+        for (y, row) in grid.rows().iter().enumerate() {
+            for x in 0..8 {
+                if row[x] > row[x + 1] {
+                    hash |= 1 << (y * 8 + x);
+                }
+            }
+        }
+
+        Self { hash }
+    }
+
+    /// Hash of a thumbnail already reduced to exactly `cols` x `rows`
+    /// pixels, e.g. on the GPU, only comparing the pixels. 9x8 thumbnails
+    /// hash as with [`Dhash::new`], 8x8 ones as with [`Comparison::Wrap`].
+    pub fn from_thumbnail(
+        pixels: &[u8],
+        cols: usize,
+        rows: usize,
+        channel_count: u8,
+    ) -> Result<Self, DhashError> {
+        if rows != 8 || !matches!(cols, 8 | 9) {
+            return Err(DhashError::InvalidShape {
+                expected: (8, 9),
+                actual: (rows, cols),
+            });
+        }
+
+        let channel_count = channel_count as usize;
+
+        check_dimensions(pixels.len(), cols, rows, channel_count)?;
+
+        let luma = |x: usize, y: usize| {
+            channels_luma(
+                pixel_channels(pixels, channel_count, y * cols + x),
+                channel_count,
+            )
+        };
+
+        let hash = match cols {
+            9 => {
+                let cells = std::array::from_fn(|y| std::array::from_fn(|x| luma(x, y)));
+                Self::from_grid(&Grid::new(cells)).hash
+            }
+            _ => wrapped_bits(&std::array::from_fn(|y| {
+                std::array::from_fn(|x| luma(x, y))
+            })),
+        };
+
+        Ok(Self { hash })
+    }
+
+    /// The differences between each cell and its right neighbor, in the
+    /// order of the bits of the hash, scaled to a unit length for cosine
+    /// similarity, e.g. in an approximate nearest neighbor index.
+    ///
+    /// A component is positive where [`Dhash::new`] sets the bit, a flat
+    /// image is all zeros.
+    pub fn feature_vector(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> [f32; 64] {
+        let grid = match compute_grid(bytes, width, height, channel_count) {
+            Ok(grid) => grid,
+            Err(error) => panic!("{}", error),
+        };
+
+        let mut differences = [0f64; 64];
+
+        for (y, row) in grid.rows().iter().enumerate() {
+            for x in 0..8 {
+                differences[y * 8 + x] = row[x] - row[x + 1];
+            }
+        }
+
+        let norm = differences.iter().map(|d| d * d).sum::<f64>().sqrt();
+
+        differences.map(|d| if norm > 0.0 { (d / norm) as f32 } else { 0.0 })
+    }
+
+    /// The 64 bits of the hash, bit `y * 8 + x` compares the cells `x` and
+    /// `x + 1` of row `y`.
+    pub fn as_u64(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn hamming_distance(&self, other: &Self) -> u32 {
+        (self.hash ^ other.hash).count_ones()
+    }
+
+    /// Same as [`Dhash::hamming_distance`] with a raw hash, e.g. scanning a
+    /// column of `u64`s read from a database.
+    pub fn hamming_to_raw(&self, other: u64) -> u32 {
+        (self.hash ^ other).count_ones()
+    }
+
+    /// Number of cells brighter than their right neighbor, 64 for a strong
+    /// left to right gradient and 0 for the opposite one or a flat image.
+    pub fn set_bit_count(&self) -> u32 {
+        self.hash.count_ones()
+    }
+
+    /// Same as [`Dhash::set_bit_count`], the Hamming weight of the hash.
+    ///
+    /// Weights bound the distance from below, `|a.weight() - b.weight()| <=
+    /// a.hamming_distance(&b)`, each differing bit changing the weight by at
+    /// most one. Hashes bucketed by weight can then be searched within
+    /// distance `d` of a query looking only at the `2 * d + 1` buckets around
+    /// its weight.
+    pub fn weight(&self) -> u32 {
+        self.set_bit_count()
+    }
+
+    /// [`Dhash::set_bit_count`] over the 64 bits, in `[0, 1]`, a quick
+    /// diagnostic of the image.
+    ///
+    /// Noisy images tend towards 0.5, about as many cells brighter as darker
+    /// than their neighbor, while structured ones, gradients, flat areas or
+    /// lit from one side, often have extreme values.
+    pub fn bit_density(&self) -> f64 {
+        self.set_bit_count() as f64 / 64.0
+    }
+
+    /// Copy of the hash with exactly `n_bits` distinct bits flipped, at
+    /// random, so at a hamming distance of `n_bits`, e.g. to generate near
+    /// duplicates for similarity search tests and benchmarks.
+    ///
+    /// Panics if `n_bits` is greater than 64.
+    #[cfg(feature = "rand")]
+    pub fn perturb(&self, n_bits: usize, rng: &mut impl rand::Rng) -> Dhash {
+        use rand::seq::IteratorRandom;
+
+        assert!(
+            n_bits <= 64,
+            "cannot flip {} bits of a 64 bits hash",
+            n_bits
+        );
+
+        let mask = (0..64)
+            .choose_multiple(rng, n_bits)
+            .into_iter()
+            .fold(0u64, |mask, bit| mask | 1 << bit);
+
+        Dhash {
+            hash: self.hash ^ mask,
+        }
+    }
+
+    /// Same as [`Dhash::hamming_distance`], with a branchless popcount
+    /// whose timing does not depend on the hashes, e.g. to check a deny-list
+    /// without leaking how close the query was.
+    ///
+    /// Best effort, as with `subtle`, which has no popcount to build on: the
+    /// XOR goes through [`std::hint::black_box`], which Rust documents as a
+    /// hint rather than a guarantee, so a compiler could in principle still
+    /// branch on the bits, and the generated code is not verified. Compare
+    /// the result with `subtle::ConstantTimeGreater`, e.g.
+    /// `threshold.ct_gt(&distance)`, rather than `<`, to keep the check
+    /// itself branchless.
+    #[cfg(feature = "constant-time")]
+    pub fn hamming_distance_ct(&self, other: &Self) -> u32 {
+        // NOTE: the optimizer must not see through the bits, count_ones may
+        // lower to a lookup or a loop on targets without popcnt
+        let x = std::hint::black_box(self.hash ^ other.hash);
+
+        let x = x - ((x >> 1) & 0x5555555555555555);
+        let x = (x & 0x3333333333333333) + ((x >> 2) & 0x3333333333333333);
+        let x = (x + (x >> 4)) & 0x0f0f0f0f0f0f0f0f;
+
+        (x.wrapping_mul(0x0101010101010101) >> 56) as u32
+    }
+
+    /// 8 lines of 8 characters, `#` for set and `.` for unset bits, laid out
+    /// as the cells of the image, also the alternate `{:#}` display.
+    pub fn to_ascii_grid(&self) -> String {
+        format!("{:#}", self)
+    }
+
+    /// Parses the output of [`Dhash::to_ascii_grid`], surrounding whitespace
+    /// on each line is ignored.
+    pub fn from_ascii_grid(s: &str) -> Result<Self, ParseHashError> {
+        let lines = s.trim().lines().map(str::trim).collect::<Vec<_>>();
+
+        let columns = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .find(|&columns| columns != 8)
+            .unwrap_or(8);
+
+        if lines.len() != 8 || columns != 8 {
+            return Err(ParseHashError::InvalidGridShape {
+                rows: lines.len(),
+                columns,
+            });
+        }
+
+        let mut hash = 0u64;
+
+        for (i, c) in lines.iter().flat_map(|line| line.chars()).enumerate() {
+            match c {
+                '#' => hash |= 1 << i,
+                '.' => {}
+                c => return Err(ParseHashError::InvalidCharacter(c)),
+            }
+        }
+
+        Ok(Self { hash })
+    }
+
+    /// Distances of `query` to each of `candidates`, in the same order.
+    ///
+    /// With the `simd` feature, on x86-64 CPUs supporting AVX-512
+    /// `VPOPCNTQ`, eight candidates are compared per instruction.
+    pub fn hamming_distance_batch(query: Dhash, candidates: &[Dhash]) -> Vec<u32> {
+        batch::hamming_distances(query.hash, candidates)
+    }
+
+    /// Index and distance of the closest hash to `query` in `sorted_hashes`,
+    /// which must be sorted by [`Dhash::hash`], ties go to any of the closest.
+    ///
+    /// Exact, whole ranges of hashes sharing a too distant prefix are
+    /// skipped by binary search, it is fastest when a close match exists and
+    /// degrades towards a linear scan for unrelated queries.
+    pub fn nearest_sorted(query: Dhash, sorted_hashes: &[Dhash]) -> Option<(usize, u32)> {
+        batch::nearest_sorted(query.hash, sorted_hashes)
+    }
+
+    /// Hash whose bits are each the majority value of that bit across
+    /// `hashes`, a single representative hash of a short clip or a burst of
+    /// photos, `None` if `hashes` is empty.
+    ///
+    /// Ties, only possible with an even number of hashes, go to the bit of
+    /// the first hash.
+    pub fn consensus(hashes: &[Dhash]) -> Option<Dhash> {
+        majority(hashes.iter().map(|&hash| (hash, 1.0)))
+    }
+
+    /// Same as [`Dhash::consensus`], each hash voting with its weight, e.g.
+    /// the duration or the sharpness of the frame.
+    ///
+    /// Ties, the weights setting and clearing a bit being exactly equal, go
+    /// to the bit of the first hash. Weights are expected to be positive.
+    pub fn consensus_weighted(hashes: &[(Dhash, f64)]) -> Option<Dhash> {
+        majority(hashes.iter().copied())
+    }
+
+    /// The hash as a MessagePack array of its `u64`, `[hash]`, a 1 byte
+    /// array header before the integer, the layout of
+    /// [`rmp_serde::to_vec`].
+    #[cfg(feature = "msgpack")]
+    pub fn to_msgpack(&self) -> Vec<u8> {
+        rmp_serde::to_vec(self).expect("a hash is always serializable")
+    }
+
+    /// Reads a hash written by [`Dhash::to_msgpack`], or as a map
+    /// `{ "hash": hash }` by [`rmp_serde::to_vec_named`].
+    #[cfg(feature = "msgpack")]
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, DhashError> {
+        rmp_serde::from_slice(bytes).map_err(|error| DhashError::Decode(error.to_string()))
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Self {
+        Self {
+            hash: u64::from_be_bytes(bytes),
+        }
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 8] {
+        self.hash.to_be_bytes()
+    }
+
+    /// The hash as its 8 little-endian bytes, without going through serde.
+    ///
+    /// These are the bytes bincode 1 writes with its default options, which
+    /// add no framing to a struct of one `u64`. Other configurations differ,
+    /// e.g. bincode 2's `standard()` writes the integer as a varint.
+    ///
+    /// Fixed-size records make files of hashes memory-mappable and searchable
+    /// in place, the `i`th hash being at offset `8 * i`.
+    pub fn to_bincode(&self) -> [u8; 8] {
+        self.hash.to_le_bytes()
+    }
+
+    /// Reads a hash written by [`Dhash::to_bincode`].
+    pub fn from_bincode(bytes: &[u8; 8]) -> Self {
+        Self {
+            hash: u64::from_le_bytes(*bytes),
+        }
+    }
+}
+
+/// Adds the samples of each block of `block` pixels of `line` to the sums
+/// of the block, for pixels of `C` channels, the channel count being known
+/// at compile time for the common ones.
+fn add_blocks<const C: usize>(sums: &mut [u64], line: &[u8], block: usize) {
+    // NOTE: slices of C samples as arrays rather than `as_chunks`, which
+    // needs Rust 1.88, the conversions cannot fail
+    for (sum, blocks) in sums.chunks_exact_mut(C).zip(line.chunks_exact(C * block)) {
+        let sum: &mut [u64; C] = sum.try_into().unwrap();
+
+        for pixel in blocks.chunks_exact(C) {
+            let pixel: &[u8; C] = pixel.try_into().unwrap();
+
+            for (sum, &sample) in sum.iter_mut().zip(pixel) {
+                *sum += sample as u64;
+            }
+        }
+    }
+}
+
+/// Smallest hash of the grids.
+fn smallest_hash(grids: &[[[f64; 9]; 8]]) -> Dhash {
+    let hash = grids
+        .iter()
+        .map(|&cells| Dhash::from_grid(&Grid::new(cells)).hash)
+        .min()
+        .unwrap_or(0);
+
+    Dhash { hash }
+}
+
+fn majority(votes: impl Iterator<Item = (Dhash, f64)>) -> Option<Dhash> {
+    let mut votes = votes.peekable();
+    let first = votes.peek()?.0;
+
+    // NOTE: weight of the votes setting each bit minus the weight of the
+    // votes clearing it
+    let mut balances = [0f64; 64];
+
+    for (hash, weight) in votes {
+        for (bit, balance) in balances.iter_mut().enumerate() {
+            match hash.hash >> bit & 1 {
+                1 => *balance += weight,
+                _ => *balance -= weight,
+            }
+        }
+    }
+
+    let hash = balances
+        .iter()
+        .enumerate()
+        .fold(0, |hash, (bit, &balance)| {
+            let set = if balance > 0.0 {
+                1
+            } else if balance < 0.0 {
+                0
+            } else {
+                first.hash >> bit & 1
+            };
+
+            hash | set << bit
+        });
+
+    Some(Dhash { hash })
+}
+
+/// Reads exactly 8 big-endian bytes, as written by [`Dhash::to_be_bytes`].
+impl TryFrom<&[u8]> for Dhash {
+    type Error = DhashError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        match <[u8; 8]>::try_from(bytes) {
+            Ok(bytes) => Ok(Self::from_be_bytes(bytes)),
+            Err(_) => Err(DhashError::InvalidLength {
+                expected: 8,
+                actual: bytes.len(),
+            }),
+        }
+    }
+}
+
+/// [`Dhash::consensus`] of the hashes, all zeros if there are none.
+impl iter::Sum for Dhash {
+    fn sum<I: Iterator<Item = Dhash>>(hashes: I) -> Self {
+        majority(hashes.map(|hash| (hash, 1.0))).unwrap_or_default()
+    }
+}
+
+impl<'a> iter::Sum<&'a Dhash> for Dhash {
+    fn sum<I: Iterator<Item = &'a Dhash>>(hashes: I) -> Self {
+        hashes.copied().sum()
+    }
+}
+
+impl AsRef<u64> for Dhash {
+    fn as_ref(&self) -> &u64 {
+        &self.hash
+    }
+}
+
+/// The hash in the lower 64 bits, zero-extended.
+impl From<Dhash> for u128 {
+    fn from(hash: Dhash) -> Self {
+        hash.hash as u128
+    }
+}
+
+impl TryFrom<u128> for Dhash {
+    type Error = DhashError;
+
+    fn try_from(value: u128) -> Result<Self, Self::Error> {
+        match u64::try_from(value) {
+            Ok(hash) => Ok(Self { hash }),
+            Err(_) => Err(DhashError::ValueTooLarge),
+        }
+    }
+}
+
+#[cfg(feature = "constant-time")]
+impl subtle::ConstantTimeEq for Dhash {
+    fn ct_eq(&self, other: &Self) -> subtle::Choice {
+        self.hash.ct_eq(&other.hash)
+    }
+}
+
+impl SimilarTo for Dhash {
+    fn similar_to(&self, other: &Self, threshold: u32) -> bool {
+        self.hamming_distance(other) < threshold
+    }
+}
+
+impl fmt::Display for Dhash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !f.alternate() {
+            return write!(f, "{:016x}", &self.hash);
+        }
+
+        for y in 0..8 {
+            if y > 0 {
+                writeln!(f)?;
+            }
+
+            for x in 0..8 {
+                let bit = self.hash >> (y * 8 + x) & 1;
+                write!(f, "{}", if bit == 1 { '#' } else { '.' })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The 16 digits of the hash, `0x` prefixed in the alternate `{:#x}`.
+impl fmt::LowerHex for Dhash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.alternate() {
+            true => write!(f, "{:#018x}", &self.hash),
+            false => write!(f, "{:016x}", &self.hash),
+        }
+    }
+}
+
+/// The 16 digits of the hash, `0x` prefixed in the alternate `{:#X}`.
+impl fmt::UpperHex for Dhash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.alternate() {
+            true => write!(f, "{:#018X}", &self.hash),
+            false => write!(f, "{:016X}", &self.hash),
+        }
+    }
+}
+
+/// The 64 bits of the hash, most significant first, `0b` prefixed in the
+/// alternate `{:#b}`.
+impl fmt::Binary for Dhash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match f.alternate() {
+            true => write!(f, "{:#066b}", &self.hash),
+            false => write!(f, "{:064b}", &self.hash),
+        }
+    }
+}
+
+impl str::FromStr for Dhash {
+    type Err = num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match u64::from_str_radix(s, 16) {
+            Ok(hash) => Ok(Self { hash }),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DhashError {
+    /// The pixel buffer length does not match the image dimensions
+    InvalidDimensions { expected: usize, actual: usize },
+    /// A raw hash is not exactly as many bytes as the hash type
+    InvalidLength { expected: usize, actual: usize },
+    /// Reading the pixels failed
+    Io(io::ErrorKind),
+    /// A wider integer has bits set past the 64 bits of a hash
+    ValueTooLarge,
+    /// The pixels are not in one of the supported formats, named by the
+    /// library they come from
+    UnsupportedFormat(String),
+    /// An array does not have the expected number of rows and columns
+    InvalidShape {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    /// An image tensor whose channel axis has a length that cannot be
+    /// hashed, e.g. none
+    InvalidChannelCount(usize),
+    /// Versioned hashes computed by different versions of the algorithm
+    VersionMismatch { expected: u8, actual: u8 },
+    /// The bytes are not a valid encoding of a hash
+    Decode(String),
+}
+
+impl fmt::Display for DhashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidDimensions { expected, actual } => write!(
+                f,
+                "Invalid image dimensions, expected {} got {}",
+                expected, actual
+            ),
+            Self::InvalidLength { expected, actual } => write!(
+                f,
+                "Invalid hash length, expected {} bytes got {}",
+                expected, actual
+            ),
+            Self::Io(kind) => write!(f, "{}", io::Error::from(*kind)),
+            Self::ValueTooLarge => write!(f, "Value too large, the upper 64 bits are not zero"),
+            Self::UnsupportedFormat(format) => write!(f, "Unsupported pixel format {}", format),
+            Self::InvalidShape { expected, actual } => write!(
+                f,
+                "Invalid array shape, expected {}x{} got {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            Self::InvalidChannelCount(count) => {
+                write!(f, "Invalid channel count, got {} channels", count)
+            }
+            Self::VersionMismatch { expected, actual } => write!(
+                f,
+                "Hash version mismatch, expected v{} got v{}",
+                expected, actual
+            ),
+            Self::Decode(error) => write!(f, "Invalid encoded hash, {}", error),
+        }
+    }
+}
+
+impl error::Error for DhashError {}
+
+/// Error parsing the hex representation of a multi-word hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseHashError {
+    /// Wrong number of hex digits for the hash type
+    InvalidLength,
+    InvalidDigit(num::ParseIntError),
+    /// Neither `#` nor `.` in an ASCII grid, or a versioned hash without
+    /// its `v` prefix
+    InvalidCharacter(char),
+    /// An ASCII grid not made of 8 lines of 8 cells, with its number of
+    /// lines and the length of the first line that is not 8 cells long
+    InvalidGridShape {
+        rows: usize,
+        columns: usize,
+    },
+}
+
+impl fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength => write!(f, "invalid number of hex digits"),
+            Self::InvalidDigit(error) => write!(f, "{}", error),
+            Self::InvalidCharacter(c) => write!(f, "invalid character {:?}", c),
+            Self::InvalidGridShape { rows, columns } => write!(
+                f,
+                "invalid ASCII grid, expected 8 lines of 8 cells got {} lines of {}",
+                rows, columns
+            ),
+        }
+    }
+}
+
+impl error::Error for ParseHashError {}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        compute_grid, Comparison, CropResistantHash, Dhash, DhashBuilder, DhashError, Grid,
+        ParseHashError, RadialHash, SimilarTo,
+    };
+    use image::ImageReader;
+
+    #[test]
+    fn grad_ffff() {
+        let image = ImageReader::open(".test/grad.ffff.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let hash = Dhash::new(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().channel_count(),
+        );
+
+        assert_eq!(hash.hash, 0xffffffffffffffff);
+    }
+
+    #[test]
+    fn grad_0000() {
+        let image = ImageReader::open(".test/grad.0000.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let hash = Dhash::new(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().channel_count(),
+        );
+
+        assert_eq!(hash.hash, 0x0000000000000000);
+    }
+
+    #[test]
+    fn radial() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let hash = Dhash::new(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().channel_count(),
+        );
+
+        assert_eq!(hash.hash, 0xf0f0e8cccce8f0f0);
+    }
+
+    #[test]
+    fn radial_f32() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let samples = image.to_rgb32f();
+
+        let hash = Dhash::new_f32(samples.as_raw(), image.width(), image.height(), 3);
+
+        assert_eq!(hash.hash, 0xf0f0e8cccce8f0f0);
+
+        // NOTE: out of range values are not clamped
+        let hdr = samples.iter().map(|s| s * 16.0).collect::<Vec<_>>();
+
+        let hash = Dhash::new_f32(&hdr, image.width(), image.height(), 3);
+
+        assert_eq!(hash.hash, 0xf0f0e8cccce8f0f0);
+    }
+
+    #[test]
+    fn radial_rgb_planar() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+            .to_rgb8();
+
+        let plane = |c: usize| image.pixels().map(|p| p.0[c]).collect::<Vec<_>>();
+
+        let hash = Dhash::from_rgb_planar(
+            &plane(0),
+            &plane(1),
+            &plane(2),
+            image.width(),
+            image.height(),
+        )
+        .expect("invalid planes");
+
+        let interleaved = Dhash::new(image.as_raw(), image.width(), image.height(), 3);
+
+        assert_eq!(hash.hash, interleaved.hash);
+        assert_eq!(hash.hash, 0xf0f0e8cccce8f0f0);
+
+        assert!(
+            Dhash::from_rgb_planar(&plane(0), &plane(1), &[], image.width(), image.height())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn radial_yuv420() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+            .to_rgb8();
+
+        let (width, height) = (image.width(), image.height());
+
+        // NOTE: BT.601 studio swing luma
+        let y_plane = image
+            .pixels()
+            .map(|p| {
+                let [r, g, b] = p.0.map(|c| c as f64);
+                (16.0 + (65.481 * r + 128.553 * g + 24.966 * b) / 255.0).round() as u8
+            })
+            .collect::<Vec<_>>();
+
+        let chroma = vec![128; width.div_ceil(2) as usize * height.div_ceil(2) as usize];
+
+        assert_eq!(
+            Dhash::from_yuv420(&y_plane, &chroma, &chroma, width, height),
+            Ok(Dhash {
+                hash: 0xf0f0e8cccce8f0f0
+            })
+        );
+        assert_eq!(
+            Dhash::from_yuv420(&y_plane, &chroma, &chroma[1..], width, height),
+            Err(DhashError::InvalidDimensions {
+                expected: chroma.len(),
+                actual: chroma.len() - 1,
+            })
+        );
+        assert!(Dhash::from_yuv420(&y_plane[1..], &chroma, &chroma, width, height).is_err());
+
+        // NOTE: odd dimensions, the chroma planes are rounded up
+        let y_plane = (0..91 * 81).map(|i| (i % 91) as u8).collect::<Vec<_>>();
+        let chroma = vec![128; 46 * 41];
+
+        assert_eq!(
+            Dhash::from_yuv420(&y_plane, &chroma, &chroma, 91, 81),
+            Ok(Dhash::new(&y_plane, 91, 81, 1))
+        );
+    }
+
+    #[test]
+    fn luma_alpha() {
+        // NOTE: transparent black on the left, opaque black on the right
+        let bytes = (0..90 * 80)
+            .flat_map(|i| [0, if i % 90 < 45 { 0 } else { 255 }])
+            .collect::<Vec<u8>>();
+
+        let hash = Dhash::new(&bytes, 90, 80, 2);
+
+        assert_eq!(hash.hash, 0x1818181818181818);
+
+        // NOTE: the companion hashes composite on white too, the LumaA image
+        // reads as white on the left and black on the right
+        let composited = bytes
+            .chunks_exact(2)
+            .map(|pixel| if pixel[1] == 0 { 255 } else { pixel[0] })
+            .collect::<Vec<u8>>();
+
+        assert_eq!(
+            CropResistantHash::new(&bytes, 90, 80, 2),
+            CropResistantHash::new(&composited, 90, 80, 1)
+        );
+        assert_eq!(
+            RadialHash::new(&bytes, 90, 80, 2),
+            RadialHash::new(&composited, 90, 80, 1)
+        );
+        assert_ne!(
+            RadialHash::new(&bytes, 90, 80, 2),
+            RadialHash::new(&[0; 90 * 80], 90, 80, 1)
+        );
+    }
+
+    #[test]
+    fn similar_to() {
+        let a = Dhash { hash: 0x0 };
+        let b = Dhash { hash: 0x3ff };
+
+        assert_ne!(a, b);
+        assert!(a.similar_to(&b, 11));
+        assert!(!a.similar_to(&b, 10));
+    }
+
+    #[test]
+    fn try_from_bytes() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        let bytes = hash.to_be_bytes();
+
+        assert_eq!(bytes[0], 0xf0);
+        assert_eq!(Dhash::try_from(&bytes[..]), Ok(hash));
+        assert_eq!(
+            Dhash::try_from(&bytes[1..]),
+            Err(DhashError::InvalidLength {
+                expected: 8,
+                actual: 7
+            })
+        );
+    }
+
+    #[test]
+    fn bincode() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        let bytes = hash.to_bincode();
+
+        assert_eq!(bytes, [0xf0, 0xf0, 0xe8, 0xcc, 0xcc, 0xe8, 0xf0, 0xf0]);
+        assert_eq!(Dhash::from_bincode(&bytes), hash);
+
+        let hash = Dhash { hash: 1 };
+
+        assert_eq!(hash.to_bincode(), [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Dhash::from_bincode(&hash.to_bincode()), hash);
+    }
+
+    #[test]
+    fn as_u64() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        assert_eq!(hash.as_u64(), 0xf0f0e8cccce8f0f0);
+        assert_eq!(hash.as_ref(), &0xf0f0e8cccce8f0f0);
+    }
+
+    #[cfg(feature = "constant-time")]
+    #[test]
+    fn constant_time() {
+        use subtle::ConstantTimeEq;
+
+        let mut state = 0x9e3779b97f4a7c15u64;
+
+        for _ in 0..10_000 {
+            let a = Dhash { hash: state };
+
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            let b = Dhash { hash: state };
+
+            assert_eq!(a.hamming_distance_ct(&b), a.hamming_distance(&b));
+            assert_eq!(a.hamming_distance_ct(&a), 0);
+            assert!(bool::from(a.ct_eq(&a)));
+            assert!(!bool::from(a.ct_eq(&b)));
+        }
+
+        let (zero, max) = (Dhash { hash: 0 }, Dhash { hash: u64::MAX });
+
+        assert_eq!(zero.hamming_distance_ct(&max), 64);
+    }
+
+    #[test]
+    fn u128() {
+        let max = Dhash { hash: u64::MAX };
+
+        assert_eq!(u128::from(max), u64::MAX as u128);
+        assert_eq!(Dhash::try_from(u128::from(max)), Ok(max));
+        assert_eq!(Dhash::try_from(0u128), Ok(Dhash { hash: 0 }));
+        assert_eq!(
+            Dhash::try_from(u64::MAX as u128 + 1),
+            Err(DhashError::ValueTooLarge)
+        );
+        assert_eq!(
+            Dhash::try_from(7u128 << 64 | 1),
+            Err(DhashError::ValueTooLarge)
+        );
+    }
+
+    #[test]
+    fn from_grid() {
+        for path in [
+            ".test/grad.ffff.jpg",
+            ".test/grad.0000.jpg",
+            ".test/radial.jpg",
+        ] {
+            let image = ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image");
+
+            let bytes = image.as_bytes();
+            let channel_count = image.color().channel_count();
+
+            let grid = compute_grid(bytes, image.width(), image.height(), channel_count)
+                .expect("invalid dimensions");
+
+            assert_eq!(
+                Dhash::from_grid(&grid),
+                Dhash::new(bytes, image.width(), image.height(), channel_count)
+            );
+        }
+
+        // NOTE: only the first cell of each row is brighter than its neighbor
+        let mut cells = [[0f64; 9]; 8];
+
+        for row in cells.iter_mut() {
+            row[0] = 1.0;
+        }
+
+        assert_eq!(Dhash::from_grid(&Grid::new(cells)).hash, 0x0101010101010101);
+    }
+
+    #[test]
+    fn from_reader() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let bytes = image.as_bytes();
+        let channel_count = image.color().channel_count();
+
+        let hash = Dhash::from_reader(bytes, image.width(), image.height(), channel_count);
+
+        assert_eq!(
+            hash,
+            Ok(Dhash::new(
+                bytes,
+                image.width(),
+                image.height(),
+                channel_count
+            ))
+        );
+
+        let truncated = &bytes[..bytes.len() - 1];
+
+        assert_eq!(
+            Dhash::from_reader(truncated, image.width(), image.height(), channel_count),
+            Err(DhashError::InvalidDimensions {
+                expected: bytes.len(),
+                actual: bytes.len() - 1,
+            })
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn new_async() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        let bytes = (0..90 * 80).map(|i| (i % 90) as u8).collect::<Vec<_>>();
+
+        let mut tasks = tokio::task::JoinSet::new();
+
+        for _ in 0..100 {
+            let bytes = bytes.clone();
+            tasks.spawn(async move { Dhash::new_async(&bytes, 90, 80, 1).await });
+        }
+
+        while let Some(result) = tasks.join_next().await {
+            assert_eq!(result.unwrap(), Ok(Dhash { hash: 0 }));
+        }
+
+        let large = (0..2000 * 1500 * 3)
+            .map(|i| (i % 6000 / 3 * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let expected = Dhash::try_new(&large, 2000, 1500, 3);
+        let started = Arc::new(AtomicBool::new(false));
+
+        let hashing = tokio::spawn({
+            let started = started.clone();
+
+            async move {
+                started.store(true, Ordering::SeqCst);
+                Dhash::new_async(&large, 2000, 1500, 3).await
+            }
+        });
+
+        // NOTE: the runtime is single threaded, a hash computed inline would
+        // be over by the end of the poll that starts it, before any tick
+        while !started.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+
+        let mut ticks = 0;
+
+        while !hashing.is_finished() {
+            tokio::task::yield_now().await;
+            ticks += 1;
+        }
+
+        assert!(ticks > 0);
+        assert_eq!(hashing.await.unwrap(), expected);
+
+        assert!(Dhash::new_async(&bytes, 90, 81, 1).await.is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn new_async_owned() {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        let bytes = (0..2000 * 1500 * 3)
+            .map(|i| (i % 6000 / 3 * 7 % 251) as u8)
+            .collect::<Vec<_>>();
+
+        let expected = Dhash::try_new(&bytes, 2000, 1500, 3);
+        let started = Arc::new(AtomicBool::new(false));
+
+        let hashing = tokio::spawn({
+            let started = started.clone();
+
+            async move {
+                started.store(true, Ordering::SeqCst);
+                Dhash::new_async_owned(bytes, 2000, 1500, 3).await
+            }
+        });
+
+        // NOTE: the runtime is single threaded, a hash computed inline would
+        // be over by the end of the poll that starts it, before any tick
+        while !started.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+
+        let mut ticks = 0;
+
+        while !hashing.is_finished() {
+            tokio::task::yield_now().await;
+            ticks += 1;
+        }
+
+        assert!(ticks > 0);
+        assert_eq!(hashing.await.unwrap(), expected);
+
+        let bytes: Arc<[u8]> = vec![0; 90 * 80].into();
+        assert!(Dhash::new_async_owned(bytes, 90, 80, 1).await.is_ok());
+        assert!(Dhash::new_async_owned(vec![0; 10], 90, 80, 1)
+            .await
+            .is_err());
+    }
 
-#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
-pub struct Dhash {
-    pub hash: u64,
-}
+    #[test]
+    fn effective_dimensions() {
+        assert_eq!(Dhash::effective_dimensions(90, 80), (90, 80));
+        assert_eq!(Dhash::effective_dimensions(1920, 1080), (1917, 1080));
+        assert_eq!(Dhash::effective_dimensions(8, 7), (0, 0));
+    }
 
-impl Dhash {
-    pub fn new(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
-        let width = width as usize;
-        let height = height as usize;
-        let channel_count = channel_count as usize;
+    #[test]
+    fn median() {
+        let hash = |path| {
+            let image = ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image");
 
-        // NOTE: Very important, prevents possible segfault
-        if width * height * channel_count != bytes.len() {
-            panic!(
-                "Invalid image dimensions, expected {} got {}",
-                bytes.len(),
-                width * height * channel_count
-            );
-        }
+            Dhash::new_median(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().channel_count(),
+            )
+        };
+
+        assert_eq!(hash(".test/grad.ffff.jpg").hash, 0x0f0f0f0f0f0f0f0f);
+        assert_eq!(hash(".test/grad.0000.jpg").hash, 0xf0f0f0f0f0f0f0f0);
+    }
+
+    #[test]
+    fn assume_grayscale() {
+        let image = image::open(".test/radial.jpg").unwrap().to_luma8();
+        let rgb = image::DynamicImage::ImageLuma8(image.clone()).to_rgb8();
+        let (width, height) = image.dimensions();
+
+        let hash = Dhash::new(image.as_raw(), width, height, 1);
+
+        assert_eq!(
+            Dhash::new_assume_grayscale(rgb.as_raw(), width, height, 3),
+            hash
+        );
+        assert_eq!(Dhash::new(rgb.as_raw(), width, height, 3), hash);
+        assert_eq!(
+            Dhash::new_assume_grayscale(image.as_raw(), width, height, 1),
+            hash
+        );
+
+        // NOTE: no channels and an overflowing length must not be read
+        assert!(crate::grid::first_channel_grid(&[], 90, 80, 0).is_err());
+        assert!(crate::grid::first_channel_grid(&[0; 3], u32::MAX, u32::MAX, 255).is_err());
+    }
+
+    #[test]
+    fn subsampled() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
 
-        let cell_width = width / 9;
-        let cell_height = height / 8;
+        let hash = |step| {
+            Dhash::new_subsampled(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().channel_count(),
+                step,
+            )
+        };
 
-        let grid = if channel_count >= 3 {
-            grid_from_rgb(bytes, width, cell_width, cell_height, channel_count)
-        } else {
-            grid_from_grayscale(bytes, width, cell_width, cell_height, channel_count)
+        let exact = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
         };
 
-        let mut bits = [false; 64];
+        assert_eq!(hash(0), exact);
+        assert_eq!(hash(1), exact);
+        assert!(hash(2).hamming_distance(&exact) <= 4);
+        assert!(hash(usize::MAX).hamming_distance(&exact) <= 16);
 
-        for y in 0..8 {
-            for x in 0..8 {
-                bits[y * 8 + x] = grid[y][x] > grid[y][x + 1];
-            }
-        }
+        // NOTE: 2x2 cells getting darker to the right, but for their top
+        // left pixel, the only one read with larger steps
+        let bytes = (0..18 * 16)
+            .map(|i| {
+                let (x, y) = (i % 18, i / 18);
 
-        let mut hash: u64 = 0;
+                if x % 2 == 0 && y % 2 == 0 {
+                    100
+                } else {
+                    255 - x as u8 / 2 * 20
+                }
+            })
+            .collect::<Vec<u8>>();
 
-        for (i, &bit) in bits.iter().enumerate() {
-            if bit {
-                hash += 1 << i;
-            }
+        assert_eq!(Dhash::new_subsampled(&bytes, 18, 16, 1, 1).hash, u64::MAX);
+        assert_eq!(Dhash::new_subsampled(&bytes, 18, 16, 1, 2).hash, 0);
+        assert_eq!(Dhash::new_subsampled(&bytes, 18, 16, 1, 3).hash, 0);
+    }
+
+    #[test]
+    fn downsample_pow2() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+
+        let exact = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        for (bytes, channel_count) in [
+            (image.to_rgb8().into_raw(), 3),
+            (image.to_luma_alpha8().into_raw(), 2),
+            (image.to_luma8().into_raw(), 1),
+        ] {
+            let hash = |shift| {
+                Dhash::new_downsample_pow2(&bytes, width, height, channel_count, shift).unwrap()
+            };
+
+            assert_eq!(hash(0), exact);
+            assert!(hash(2).hamming_distance(&exact) <= 4);
         }
 
-        Self { hash }
+        // NOTE: 8x8 blocks, 2x2 blocks per cell, no two cells alike
+        let bytes = (0..144 * 128)
+            .map(|i| ((i % 144) * 3 + (i / 144) * 5 + i % 7) as u8)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            Dhash::new_downsample_pow2(&bytes, 144, 128, 1, 3),
+            Ok(Dhash::new(&bytes, 144, 128, 1))
+        );
+        assert_eq!(
+            Dhash::new_downsample_pow2(&bytes, 144, 128, 1, 5),
+            Err(DhashError::InvalidShape {
+                expected: (8, 9),
+                actual: (4, 4),
+            })
+        );
+        assert!(Dhash::new_downsample_pow2(&bytes, 144, 128, 1, 64).is_err());
+        assert!(Dhash::new_downsample_pow2(&bytes, 144, 129, 1, 0).is_err());
     }
 
-    pub fn hamming_distance(&self, other: &Self) -> u32 {
-        (self.hash ^ other.hash).count_ones()
+    #[test]
+    fn histogram() {
+        let (hash, histogram) = Dhash::new_with_histogram(&[0; 20 * 17 * 3], 20, 17, 3);
+
+        // NOTE: the remainder columns and rows are not hashed
+        assert_eq!(hash.hash, 0);
+        assert_eq!(histogram[0], 18 * 16);
+        assert_eq!(histogram.iter().sum::<u32>(), 18 * 16);
+
+        let bytes = (0..18 * 16)
+            .map(|i| if i % 18 < 9 { 255 } else { 0 })
+            .collect::<Vec<u8>>();
+
+        let (_, histogram) = Dhash::new_with_histogram(&bytes, 18, 16, 1);
+
+        assert_eq!((histogram[0], histogram[255]), (144, 144));
+        assert_eq!(histogram.iter().sum::<u32>(), 288);
+
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (hash, _) = Dhash::new_with_histogram(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().channel_count(),
+        );
+
+        assert_eq!(hash.hash, 0xf0f0e8cccce8f0f0);
     }
-}
 
-impl PartialEq for Dhash {
-    fn eq(&self, other: &Self) -> bool {
-        self.hamming_distance(other) < 11
+    #[test]
+    fn ascii_grid() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        let ascii = "\
+....####
+....####
+...#.###
+..##..##
+..##..##
+...#.###
+....####
+....####";
+
+        assert_eq!(hash.to_ascii_grid(), ascii);
+        assert_eq!(format!("{:#}", hash), ascii);
+        assert_eq!(Dhash::from_ascii_grid(ascii), Ok(hash));
+        assert_eq!(
+            Dhash::from_ascii_grid("....####"),
+            Err(ParseHashError::InvalidGridShape {
+                rows: 1,
+                columns: 8
+            })
+        );
+        assert_eq!(
+            Dhash::from_ascii_grid(&ascii.replacen("#.", "#", 1))
+                .unwrap_err()
+                .to_string(),
+            "invalid ASCII grid, expected 8 lines of 8 cells got 8 lines of 7"
+        );
     }
-}
 
-impl fmt::Display for Dhash {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:016x}", &self.hash)
+    #[test]
+    fn consensus() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+        let outlier = Dhash { hash: !hash.hash };
+
+        assert_eq!(Dhash::consensus(&[]), None);
+        assert_eq!(Dhash::consensus(&[hash; 7]), Some(hash));
+
+        let mut frames = [hash; 10];
+        frames[4] = outlier;
+
+        assert_eq!(Dhash::consensus(&frames), Some(hash));
+
+        // NOTE: every bit is a tie, the first hash wins
+        assert_eq!(Dhash::consensus(&[outlier, hash]), Some(outlier));
+        assert_eq!(Dhash::consensus(&[hash, outlier]), Some(hash));
+
+        let mut weighted = frames.map(|frame| (frame, 1.0));
+
+        assert_eq!(Dhash::consensus_weighted(&weighted), Some(hash));
+
+        weighted[4].1 = 10.0;
+
+        assert_eq!(Dhash::consensus_weighted(&weighted), Some(outlier));
+        assert_eq!(Dhash::consensus_weighted(&[]), None);
     }
-}
 
-impl str::FromStr for Dhash {
-    type Err = num::ParseIntError;
+    #[test]
+    fn from_thumbnail() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match u64::from_str_radix(s, 16) {
-            Ok(hash) => Ok(Self { hash }),
-            Err(error) => Err(error),
+        for (cols, comparison) in [(9, Comparison::RightNeighbor), (8, Comparison::Wrap)] {
+            let builder = DhashBuilder::new().comparison(comparison);
+
+            for thumbnail in [
+                image.thumbnail_exact(cols, 8).to_luma8().into_raw(),
+                image.thumbnail_exact(cols, 8).to_luma_alpha8().into_raw(),
+                image.thumbnail_exact(cols, 8).to_rgb8().into_raw(),
+                image.thumbnail_exact(cols, 8).to_rgba8().into_raw(),
+            ] {
+                let channel_count = (thumbnail.len() / (cols as usize * 8)) as u8;
+
+                assert_eq!(
+                    Dhash::from_thumbnail(&thumbnail, cols as usize, 8, channel_count),
+                    Ok(builder.build(&thumbnail, cols, 8, channel_count))
+                );
+            }
         }
+
+        assert_eq!(
+            Dhash::from_thumbnail(&[0; 90 * 80], 90, 80, 1),
+            Err(DhashError::InvalidShape {
+                expected: (8, 9),
+                actual: (80, 90)
+            })
+        );
+        assert_eq!(
+            Dhash::from_thumbnail(&[0; 9 * 8 * 3], 9, 8, 4),
+            Err(DhashError::InvalidDimensions {
+                expected: 9 * 8 * 4,
+                actual: 9 * 8 * 3
+            })
+        );
+        assert!(Dhash::from_thumbnail(&[], 9, 8, 0).is_err());
     }
-}
 
-fn grid_from_rgb(
-    bytes: &[u8],
-    width: usize,
-    cell_width: usize,
-    cell_height: usize,
-    channel_count: usize,
-) -> [[f64; 9]; 8] {
-    let mut grid = [[0f64; 9]; 8];
+    #[test]
+    fn sum() {
+        let hashes =
+            [0xf0f0e8cccce8f0f0, 0xf0f0e8cccce8f0f1, 0x0ff0e8cccce8f0f1].map(|hash| Dhash { hash });
 
-    thread::scope(|s| {
-        let mut handles = Vec::with_capacity(8);
+        assert_eq!(
+            Some(hashes.iter().copied().sum::<Dhash>()),
+            Dhash::consensus(&hashes)
+        );
+        assert_eq!(hashes.iter().sum::<Dhash>().hash, 0xf0f0e8cccce8f0f1);
+        assert_eq!([].iter().sum::<Dhash>(), Dhash::default());
+        assert_eq!(Dhash::default().hash, 0);
+    }
 
-        for y in 0..8 {
-            handles.push(s.spawn(move || {
-                let mut row = [0f64; 9];
+    #[test]
+    fn bit_density() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
 
-                for (x, cell) in row.iter_mut().enumerate() {
-                    let from = x * cell_width;
-                    let to = from + cell_width;
+        assert_eq!(hash.set_bit_count(), 32);
+        assert_eq!(hash.bit_density(), 0.5);
+        assert_eq!(Dhash { hash: u64::MAX }.bit_density(), 1.0);
+        assert_eq!(Dhash { hash: 0 }.set_bit_count(), 0);
+    }
 
-                    let mut rs = 0f64;
-                    let mut gs = 0f64;
-                    let mut bs = 0f64;
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack() {
+        for hash in [0, 1, 127, 0xf0f0e8cccce8f0f0, u64::MAX] {
+            let hash = Dhash { hash };
+            let bytes = hash.to_msgpack();
 
-                    for image_x in from..to {
-                        let from = y * cell_height;
-                        let to = from + cell_height;
+            assert_eq!(Dhash::from_msgpack(&bytes), Ok(hash));
+            assert_eq!(bytes, rmp_serde::to_vec(&hash).unwrap());
 
-                        for image_y in from..to {
-                            let i = (image_y * width + image_x) * channel_count;
+            let named = rmp_serde::to_vec_named(&hash).unwrap();
 
-                            unsafe {
-                                rs += *bytes.get_unchecked(i) as f64;
-                                gs += *bytes.get_unchecked(i + 1) as f64;
-                                bs += *bytes.get_unchecked(i + 2) as f64;
-                            }
-                        }
-                    }
+            assert_eq!(Dhash::from_msgpack(&named), Ok(hash));
+        }
 
-                    *cell += rs * 0.299 + gs * 0.587 + bs * 0.114;
-                }
+        // NOTE: fixarray of 1, uint 64 marker, then the big-endian integer
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+        let mut expected = vec![0x91, 0xcf];
+        expected.extend(hash.hash.to_be_bytes());
+
+        assert_eq!(hash.to_msgpack(), expected);
+        assert_eq!(Dhash { hash: 1 }.to_msgpack(), [0x91, 0x01]);
+
+        // NOTE: fixmap of 1, fixstr "hash", then the integer
+        let mut named = vec![0x81, 0xa4];
+        named.extend(b"hash");
+        named.extend([0xcf]);
+        named.extend(hash.hash.to_be_bytes());
+
+        assert_eq!(Dhash::from_msgpack(&named), Ok(hash));
+        assert!(matches!(
+            Dhash::from_msgpack(&[0x91]),
+            Err(DhashError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn hamming_to_raw() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
 
-                (y, row)
-            }));
+        for other in [0, u64::MAX, 0xf0f0e8cccce8f0f1, hash.hash] {
+            assert_eq!(
+                hash.hamming_to_raw(other),
+                hash.hamming_distance(&Dhash { hash: other })
+            );
         }
 
-        for handle in handles {
-            let (y, row) = handle.join().unwrap();
-            grid[y] = row;
+        assert_eq!(hash.hamming_to_raw(0), 32);
+    }
+
+    #[test]
+    fn weight() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            Dhash { hash: state }
+        };
+
+        for _ in 0..1000 {
+            let a = next();
+            // NOTE: keeps some pairs close, random ones are about 32 apart
+            let b = Dhash {
+                hash: a.hash ^ (next().hash & next().hash & next().hash),
+            };
+
+            assert_eq!(a.weight(), a.set_bit_count());
+            assert!(a.weight().abs_diff(b.weight()) <= a.hamming_distance(&b));
         }
-    });
+    }
 
-    grid
-}
+    #[test]
+    fn formatting() {
+        let hash = Dhash { hash: 0xf0e8cc };
 
-fn grid_from_grayscale(
-    bytes: &[u8],
-    width: usize,
-    cell_width: usize,
-    cell_height: usize,
-    channel_count: usize,
-) -> [[f64; 9]; 8] {
-    let mut grid = [[0f64; 9]; 8];
+        assert_eq!(format!("{:x}", hash), "0000000000f0e8cc");
+        assert_eq!(format!("{:X}", hash), "0000000000F0E8CC");
+        assert_eq!(format!("{:#x}", hash), "0x0000000000f0e8cc");
+        assert_eq!(format!("{:x}", hash), hash.to_string());
+        assert_eq!(
+            format!("{:b}", hash),
+            format!("{:0>64}", "111100001110100011001100")
+        );
+        assert_eq!(format!("{:#b}", hash).len(), 66);
+        assert_eq!(format!("{:064b}", hash), format!("{:064b}", hash.hash));
+    }
 
-    thread::scope(|s| {
-        let mut handles = Vec::with_capacity(8);
+    #[cfg(feature = "borsh")]
+    #[test]
+    fn borsh() {
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
 
-        for y in 0..8 {
-            handles.push(s.spawn(move || {
-                let mut row = [0f64; 9];
+        let bytes = borsh::to_vec(&hash).unwrap();
 
-                for (x, cell) in row.iter_mut().enumerate() {
-                    let from = x * cell_width;
-                    let to = from + cell_width;
+        assert_eq!(bytes, 0xf0f0e8cccce8f0f0u64.to_le_bytes());
+        assert_eq!(borsh::from_slice::<Dhash>(&bytes).unwrap(), hash);
+    }
 
-                    let mut luma = 0f64;
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn rkyv() {
+        let hashes = (0..1000)
+            .map(|i| Dhash {
+                hash: 0x9e3779b97f4a7c15u64.wrapping_mul(i),
+            })
+            .collect::<Vec<_>>();
 
-                    for image_x in from..to {
-                        let from = y * cell_height;
-                        let to = from + cell_height;
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&hashes).unwrap();
+        let archived =
+            rkyv::access::<rkyv::Archived<Vec<Dhash>>, rkyv::rancor::Error>(&bytes).unwrap();
 
-                        for image_y in from..to {
-                            let i = (image_y * width + image_x) * channel_count;
+        assert!(archived
+            .iter()
+            .zip(&hashes)
+            .all(|(archived, hash)| archived.hash == hash.hash));
 
-                            unsafe {
-                                luma += *bytes.get_unchecked(i) as f64;
-                            }
-                        }
-                    }
+        let deserialized = rkyv::deserialize::<Vec<Dhash>, rkyv::rancor::Error>(archived).unwrap();
 
-                    *cell += luma;
-                }
+        assert_eq!(deserialized, hashes);
+    }
 
-                (y, row)
-            }));
-        }
+    #[cfg(feature = "image")]
+    #[test]
+    fn from_image() {
+        use image::DynamicImage;
 
-        for handle in handles {
-            let (y, row) = handle.join().unwrap();
-            grid[y] = row;
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let expected = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        for image in [
+            DynamicImage::ImageRgb8(image.to_rgb8()),
+            DynamicImage::ImageRgba8(image.to_rgba8()),
+            DynamicImage::ImageRgb16(image.to_rgb16()),
+            DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        ] {
+            assert_eq!(Dhash::from_image(&image), expected, "{:?}", image.color());
         }
-    });
 
-    grid
-}
+        // NOTE: image converts to luma with different weights
+        let luma = Dhash::from_image(&DynamicImage::ImageLuma8(image.to_luma8()));
 
-#[cfg(test)]
-mod test {
-    use super::Dhash;
-    use image::ImageReader;
+        assert!(luma.hamming_distance(&expected) <= 2);
+
+        for image in [
+            DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+            DynamicImage::ImageLuma16(image.to_luma16()),
+            DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        ] {
+            assert_eq!(Dhash::from_image(&image), luma, "{:?}", image.color());
+        }
+    }
 
+    #[cfg(feature = "image")]
     #[test]
-    fn grad_ffff() {
-        let image = ImageReader::open(".test/grad.ffff.jpg")
+    fn from_view() {
+        use image::GenericImageView;
+
+        let image = ImageReader::open(".test/radial.jpg")
             .expect("cannot read image")
             .decode()
             .expect("cannot decode image");
 
-        let hash = Dhash::new(
-            image.as_bytes(),
-            image.width(),
-            image.height(),
-            image.color().channel_count(),
+        let expected = Dhash::from_image(&image);
+
+        assert_eq!(Dhash::from_view(&image), expected);
+        assert_eq!(Dhash::from_view(&image.to_rgb8()), expected);
+        assert_eq!(Dhash::from_view(&image.to_rgba16()), expected);
+        assert_eq!(Dhash::from_view(&image.to_rgb32f()), expected);
+        assert_eq!(
+            Dhash::from_view(&image.to_luma_alpha16()),
+            Dhash::from_image(&image.to_luma8().into()),
         );
 
-        assert_eq!(hash.hash, 0xffffffffffffffff);
+        let (width, height) = (image.width() / 2, image.height() / 3);
+        let cropped = image.crop_imm(width / 2, height, width, height).to_rgb8();
+
+        assert_eq!(
+            Dhash::from_view(&*image.view(width / 2, height, width, height)),
+            Dhash::new(cropped.as_raw(), width, height, 3),
+        );
     }
 
     #[test]
-    fn grad_0000() {
-        let image = ImageReader::open(".test/grad.0000.jpg")
-            .expect("cannot read image")
+    fn transposed() {
+        // NOTE: unlike radial.jpg, no symmetry across the diagonal, the
+        // luma peaks a third of the way across and grows downwards
+        let image = image::RgbImage::from_fn(150, 130, |x, y| {
+            let v = (x.abs_diff(50) * 2 + y / 2).min(255) as u8;
+            image::Rgb([v, v / 2, ((x + 3 * y) % 256) as u8])
+        });
+
+        let (width, height) = image.dimensions();
+        let transpose = image::imageops::flip_horizontal(&image::imageops::rotate90(&image));
+
+        assert_eq!(transpose.dimensions(), (height, width));
+        assert_eq!(
+            Dhash::transposed(image.as_raw(), width, height, 3),
+            Dhash::new(transpose.as_raw(), height, width, 3),
+        );
+        assert_eq!(
+            Dhash::transposed(transpose.as_raw(), height, width, 3),
+            Dhash::new(image.as_raw(), width, height, 3),
+        );
+        assert_ne!(
+            Dhash::transposed(image.as_raw(), width, height, 3),
+            Dhash::new(image.as_raw(), width, height, 3),
+        );
+    }
+
+    #[test]
+    fn canonical() {
+        for path in [
+            ".test/grad.ffff.jpg",
+            ".test/grad.0000.jpg",
+            ".test/radial.jpg",
+        ] {
+            let image = ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image")
+                .to_rgb8();
+
+            let rotations = [
+                image.clone(),
+                image::imageops::rotate90(&image),
+                image::imageops::rotate180(&image),
+                image::imageops::rotate270(&image),
+            ];
+
+            let hashes = rotations
+                .iter()
+                .map(|rotation| {
+                    Dhash::new(rotation.as_raw(), rotation.width(), rotation.height(), 3)
+                })
+                .collect::<Vec<_>>();
+
+            let canonical = rotations
+                .iter()
+                .map(|rotation| {
+                    Dhash::new_canonical(rotation.as_raw(), rotation.width(), rotation.height(), 3)
+                })
+                .collect::<Vec<_>>();
+
+            // NOTE: 1080 is a multiple of 72, the rotated grids are exact
+            let smallest = hashes.iter().min_by_key(|hash| hash.hash).unwrap();
+
+            assert!(canonical.iter().all(|hash| hash == smallest), "{}", path);
+        }
+
+        // NOTE: the gradients do not hash the same once rotated
+        let image = ImageReader::open(".test/grad.ffff.jpg")
+            .unwrap()
             .decode()
-            .expect("cannot decode image");
+            .unwrap()
+            .to_rgb8();
+        let rotated = image::imageops::rotate90(&image);
 
-        let hash = Dhash::new(
-            image.as_bytes(),
-            image.width(),
-            image.height(),
-            image.color().channel_count(),
+        assert_ne!(
+            Dhash::new(image.as_raw(), image.width(), image.height(), 3),
+            Dhash::new(rotated.as_raw(), rotated.width(), rotated.height(), 3),
         );
+    }
 
-        assert_eq!(hash.hash, 0x0000000000000000);
+    #[test]
+    fn flip_canonical() {
+        for path in [".test/grad.ffff.jpg", ".test/radial.jpg"] {
+            let image = ImageReader::open(path)
+                .expect("cannot read image")
+                .decode()
+                .expect("cannot decode image")
+                .to_rgb8();
+
+            let (width, height) = image.dimensions();
+            let hash = |image: &image::RgbImage| {
+                Dhash::new_flip_canonical(image.as_raw(), width, height, 3)
+            };
+
+            let mirrored = image::imageops::flip_horizontal(&image);
+            let upside_down = image::imageops::flip_vertical(&image);
+
+            assert_eq!(hash(&mirrored), hash(&image), "{}", path);
+            assert_eq!(hash(&upside_down), hash(&image), "{}", path);
+            assert_eq!(
+                hash(&image::imageops::rotate180(&image)),
+                hash(&image),
+                "{}",
+                path
+            );
+        }
+
+        // NOTE: the gradient does not hash the same once mirrored
+        let image = ImageReader::open(".test/grad.ffff.jpg")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgb8();
+        let mirrored = image::imageops::flip_horizontal(&image);
+
+        assert_ne!(
+            Dhash::new(image.as_raw(), 1080, 1080, 3),
+            Dhash::new(mirrored.as_raw(), 1080, 1080, 3),
+        );
     }
 
     #[test]
-    fn radial() {
+    fn feature_vector() {
         let image = ImageReader::open(".test/radial.jpg")
             .expect("cannot read image")
             .decode()
             .expect("cannot decode image");
 
-        let hash = Dhash::new(
+        let args = (
             image.as_bytes(),
             image.width(),
             image.height(),
             image.color().channel_count(),
         );
 
-        assert_eq!(hash.hash, 0xf0f0e8cccce8f0f0);
+        let features = Dhash::feature_vector(args.0, args.1, args.2, args.3);
+        let hash = Dhash::new(args.0, args.1, args.2, args.3);
+
+        for (i, feature) in features.iter().enumerate() {
+            assert_eq!(*feature > 0.0, hash.hash >> i & 1 == 1, "bit {}", i);
+        }
+
+        let norm = features.iter().map(|f| f * f).sum::<f32>().sqrt();
+
+        assert!((norm - 1.0).abs() < 1e-6);
+        assert_eq!(Dhash::feature_vector(&[128; 90 * 80], 90, 80, 1), [0.0; 64]);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn perturb() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        for n_bits in [0, 1, 10, 32, 64] {
+            assert_eq!(
+                hash.perturb(n_bits, &mut rng).hamming_distance(&hash),
+                n_bits as u32
+            );
+        }
+
+        assert_eq!(hash.perturb(64, &mut rng).hash, !hash.hash);
+        assert_ne!(hash.perturb(5, &mut rng), hash.perturb(5, &mut rng));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    #[should_panic(expected = "cannot flip 65 bits")]
+    fn perturb_too_many_bits() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        Dhash { hash: 0 }.perturb(65, &mut StdRng::seed_from_u64(42));
+    }
+
+    #[test]
+    fn with_brightness() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+            .to_luma8();
+
+        let (width, height) = image.dimensions();
+        let (hash, brightness) = Dhash::with_brightness(image.as_raw(), width, height, 1);
+
+        let (effective_width, effective_height) = Dhash::effective_dimensions(width, height);
+        let mean = image
+            .enumerate_pixels()
+            .filter(|(x, y, _)| *x < effective_width && *y < effective_height)
+            .map(|(_, _, pixel)| pixel.0[0] as f64)
+            .sum::<f64>()
+            / (effective_width * effective_height) as f64;
+
+        assert_eq!(hash, Dhash::new(image.as_raw(), width, height, 1));
+        assert_eq!(brightness, mean.round() as u8);
+
+        assert_eq!(Dhash::with_brightness(&[0; 90 * 80], 90, 80, 1).1, 0);
+        assert_eq!(
+            Dhash::with_brightness(&[255; 90 * 80 * 3], 90, 80, 3).1,
+            255
+        );
     }
 }
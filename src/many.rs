@@ -0,0 +1,196 @@
+//! Hashing many images at once, in parallel across the images.
+use crate::{
+    grid::{indexed_luma_grid_on, map_rows, subsampled_luma_grid, Reduction, ScanOrder, Workers},
+    Dhash, DhashError, Grid,
+};
+use std::{num::NonZero, thread};
+
+/// Borrowed pixels of an image, rows possibly padded, see
+/// [`ImageRef::with_stride`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageRef<'a> {
+    pub bytes: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub channel_count: u8,
+    /// Bytes from the start of a row to the start of the next one, at least
+    /// `width * channel_count`
+    pub stride: usize,
+}
+
+impl<'a> ImageRef<'a> {
+    /// Image with tightly packed rows, as [`Dhash::new`] expects them.
+    pub fn new(bytes: &'a [u8], width: u32, height: u32, channel_count: u8) -> Self {
+        Self {
+            bytes,
+            width,
+            height,
+            channel_count,
+            stride: width as usize * channel_count as usize,
+        }
+    }
+
+    /// Same image with rows `stride` bytes apart, e.g. a frame buffer with
+    /// aligned rows, the padding is never read.
+    pub fn with_stride(self, stride: usize) -> Self {
+        Self { stride, ..self }
+    }
+
+    /// Same as [`Dhash::try_new`], on the calling thread alone.
+    fn hash(&self) -> Result<Dhash, DhashError> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let channel_count = self.channel_count as usize;
+        let row_len = width * channel_count;
+
+        // NOTE: images without channels are rejected there
+        if self.stride == row_len || channel_count == 0 {
+            let reduction = Reduction {
+                workers: Workers::Threads(Some(1)),
+                ..Default::default()
+            };
+
+            return subsampled_luma_grid(
+                self.bytes,
+                self.width,
+                self.height,
+                self.channel_count,
+                reduction,
+            )
+            .map(|cells| Dhash::from_grid(&Grid::new(cells)));
+        }
+
+        // NOTE: Very important, prevents reading a row past the buffer
+        if self.stride < row_len {
+            return Err(DhashError::InvalidDimensions {
+                expected: row_len,
+                actual: self.stride,
+            });
+        }
+
+        let expected = match height {
+            0 => 0,
+            _ => self
+                .stride
+                .saturating_mul(height - 1)
+                .saturating_add(row_len),
+        };
+
+        // NOTE: the last row does not need its padding
+        if self.bytes.len() < expected {
+            return Err(DhashError::InvalidDimensions {
+                expected,
+                actual: self.bytes.len(),
+            });
+        }
+
+        let grid = indexed_luma_grid_on(
+            width,
+            height,
+            channel_count,
+            u8::MAX as f64,
+            ScanOrder::Rows,
+            Workers::Threads(Some(1)),
+            |i| {
+                let start = i / width * self.stride + i % width * channel_count;
+                let mut channels = [0.0; 3];
+
+                for (channel, &sample) in channels
+                    .iter_mut()
+                    .zip(&self.bytes[start..start + channel_count.min(3)])
+                {
+                    *channel = sample as f64;
+                }
+
+                channels
+            },
+        );
+
+        Ok(Dhash::from_grid(&Grid::new(grid)))
+    }
+}
+
+/// Hashes `images`, in parallel across the images, each one on a single
+/// thread, which scales better than [`Dhash::new`] splitting its rows when
+/// there are many small images.
+///
+/// The results are in the order of the images, an invalid image only fails
+/// its own result.
+pub fn hash_many(images: &[ImageRef<'_>]) -> Vec<Result<Dhash, DhashError>> {
+    let threads = thread::available_parallelism().map_or(1, NonZero::get);
+
+    map_rows(images.len(), Workers::Threads(Some(threads)), |i| {
+        images[i].hash()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{hash_many, ImageRef};
+    use crate::{Dhash, DhashError};
+    use image::ImageReader;
+
+    #[test]
+    fn hash_many_images() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let (width, height) = (image.width(), image.height());
+
+        let rgb = image.to_rgb8().into_raw();
+        let luma_alpha = image.to_luma_alpha8().into_raw();
+        let luma = image.to_luma8().into_raw();
+
+        // NOTE: rows padded with white pixels, without the padding of the
+        // last row
+        let stride = width as usize * 3 + 61;
+        let mut padded = vec![0xff; stride * height as usize];
+
+        for (row, pixels) in padded
+            .chunks_mut(stride)
+            .zip(rgb.chunks(width as usize * 3))
+        {
+            row[..pixels.len()].copy_from_slice(pixels);
+        }
+
+        padded.truncate(stride * (height as usize - 1) + width as usize * 3);
+
+        let images = [
+            ImageRef::new(&rgb, width, height, 3),
+            ImageRef::new(&luma_alpha, width, height, 2),
+            ImageRef::new(&rgb[1..], width, height, 3),
+            ImageRef::new(&luma, width, height, 1),
+            ImageRef::new(&padded, width, height, 3).with_stride(stride),
+            ImageRef::new(&padded, width, height, 3).with_stride(stride + 1),
+            ImageRef::new(&rgb, width, height, 3).with_stride(width as usize),
+        ];
+
+        let hash = Dhash::new(&rgb, width, height, 3);
+
+        assert_eq!(
+            hash_many(&images),
+            [
+                Ok(hash),
+                Ok(Dhash::new(&luma_alpha, width, height, 2)),
+                Err(DhashError::InvalidDimensions {
+                    expected: rgb.len(),
+                    actual: rgb.len() - 1,
+                }),
+                Ok(Dhash::new(&luma, width, height, 1)),
+                Ok(hash),
+                Err(DhashError::InvalidDimensions {
+                    expected: (stride + 1) * (height as usize - 1) + width as usize * 3,
+                    actual: padded.len(),
+                }),
+                Err(DhashError::InvalidDimensions {
+                    expected: width as usize * 3,
+                    actual: width as usize,
+                }),
+            ]
+        );
+
+        assert!(hash_many(&[]).is_empty());
+    }
+}
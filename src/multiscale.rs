@@ -0,0 +1,156 @@
+//! Coarse (64 bits) and fine (256 bits) hashes for two stage filtering.
+use crate::{grid::luma_grid, CellAggregation, Dhash, Grid, SimilarTo};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Dhash over a 16x16 comparison grid, 256 bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DhashN {
+    pub hash: [u64; 4],
+}
+
+impl DhashN {
+    pub fn hamming_distance(&self, other: &Self) -> u32 {
+        self.hash
+            .iter()
+            .zip(&other.hash)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
+impl SimilarTo for DhashN {
+    fn similar_to(&self, other: &Self, threshold: u32) -> bool {
+        self.hamming_distance(other) < threshold
+    }
+}
+
+impl fmt::Display for DhashN {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for word in &self.hash {
+            write!(f, "{:016x}", word)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct MultiScaleHash {
+    pub coarse: Dhash,
+    pub fine: DhashN,
+}
+
+impl MultiScaleHash {
+    /// Computes both hashes from a single 18x16 grid, the coarse 9x8 grid
+    /// is derived by summing 2x2 blocks of it.
+    ///
+    /// The fine hash ignores the last column of the grid, which is only
+    /// needed to derive the coarse one.
+    ///
+    /// The coarse hash is the one of [`Dhash::new`] only when the width is
+    /// a multiple of 18 and the height of 16. Otherwise the coarse cells are
+    /// `2 * (width / 18)` pixels wide rather than `width / 9`, and likewise
+    /// for the height, so they can cover one pixel less than those of
+    /// [`Dhash::new`] and the hashes can differ.
+    pub fn new(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        let fine_grid: [[f64; 18]; 16] =
+            match luma_grid(bytes, width, height, channel_count, CellAggregation::Sum) {
+                Ok(grid) => grid,
+                Err(error) => panic!("{}", error),
+            };
+
+        let mut coarse_grid = [[0f64; 9]; 8];
+
+        for (y, row) in coarse_grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                *cell = fine_grid[2 * y][2 * x]
+                    + fine_grid[2 * y][2 * x + 1]
+                    + fine_grid[2 * y + 1][2 * x]
+                    + fine_grid[2 * y + 1][2 * x + 1];
+            }
+        }
+
+        let mut fine = [0u64; 4];
+
+        for (y, row) in fine_grid.iter().enumerate() {
+            for x in 0..16 {
+                if row[x] > row[x + 1] {
+                    let i = y * 16 + x;
+                    fine[i / 64] |= 1 << (i % 64);
+                }
+            }
+        }
+
+        Self {
+            coarse: Dhash::from_grid(&Grid::new(coarse_grid)),
+            fine: DhashN { hash: fine },
+        }
+    }
+
+    /// Two stage check, the fine hashes are only compared when the coarse
+    /// ones are within `coarse_max`.
+    pub fn matches(&self, other: &Self, coarse_max: u32, fine_max: u32) -> bool {
+        self.coarse.hamming_distance(&other.coarse) <= coarse_max
+            && self.fine.hamming_distance(&other.fine) <= fine_max
+    }
+}
+
+impl fmt::Display for MultiScaleHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.coarse, self.fine)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::MultiScaleHash;
+    use crate::Dhash;
+    use image::{imageops::FilterType, DynamicImage, ImageReader};
+
+    fn open(path: &str) -> DynamicImage {
+        ImageReader::open(path)
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+    }
+
+    #[test]
+    fn coarse_matches_dhash() {
+        // NOTE: cells of the two grids only align exactly when the width
+        // is a multiple of 18 and the height a multiple of 16
+        let image = open(".test/radial.jpg").resize_exact(360, 320, FilterType::Triangle);
+
+        let bytes = image.as_bytes();
+        let channel_count = image.color().channel_count();
+
+        let hash = MultiScaleHash::new(bytes, 360, 320, channel_count);
+        let dhash = Dhash::new(bytes, 360, 320, channel_count);
+
+        assert_eq!(hash.coarse.hash, dhash.hash);
+    }
+
+    #[test]
+    fn coarse_rejects() {
+        let hash = |path| {
+            let image = open(path);
+
+            MultiScaleHash::new(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color().channel_count(),
+            )
+        };
+
+        let ffff = hash(".test/grad.ffff.jpg");
+        let zero = hash(".test/grad.0000.jpg");
+
+        assert!(ffff.matches(&ffff, 0, 0));
+        assert_eq!(ffff.coarse.hamming_distance(&zero.coarse), 64);
+        assert!(!ffff.matches(&zero, 10, 256));
+    }
+}
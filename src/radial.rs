@@ -0,0 +1,244 @@
+//! Radial variance hash, a rotation tolerant companion to dhash.
+//!
+//! The luminance of the image is sampled along lines crossing its center at
+//! evenly spaced angles in `[0, π)`, the variance of each line is a feature.
+//! Rotating the image cyclically shifts the features, which is what
+//! [`RadialHash::peak_cross_correlation`] compensates for.
+use crate::{
+    grid::{channels_luma, check_dimensions, pixel_channels},
+    ParseHashError,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{f64::consts::PI, fmt, str};
+
+/// Number of projections used by [`RadialHash::new`], a 90° rotation is an
+/// exact shift of `DEFAULT_PROJECTIONS / 2` features.
+pub const DEFAULT_PROJECTIONS: usize = 40;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct RadialHash {
+    /// One quantized variance per projection angle
+    pub features: Vec<u8>,
+}
+
+impl RadialHash {
+    pub fn new(bytes: &[u8], width: u32, height: u32, channel_count: u8) -> Self {
+        Self::with_projections(bytes, width, height, channel_count, DEFAULT_PROJECTIONS)
+    }
+
+    pub fn with_projections(
+        bytes: &[u8],
+        width: u32,
+        height: u32,
+        channel_count: u8,
+        projections: usize,
+    ) -> Self {
+        let width = width as usize;
+        let height = height as usize;
+        let channel_count = channel_count as usize;
+
+        // NOTE: Very important, prevents possible segfault
+        if let Err(error) = check_dimensions(bytes.len(), width, height, channel_count) {
+            panic!("{}", error);
+        }
+
+        let cx = (width as f64 - 1.0) / 2.0;
+        let cy = (height as f64 - 1.0) / 2.0;
+        let radius = (width.min(height) / 2) as isize;
+
+        // NOTE: LumaA is composited on white, as in Dhash::new
+        let luma = |x: usize, y: usize| {
+            channels_luma(
+                pixel_channels(bytes, channel_count, y * width + x),
+                channel_count,
+            )
+        };
+
+        let variances = (0..projections)
+            .map(|p| {
+                let (sin, cos) = (p as f64 * PI / projections as f64).sin_cos();
+
+                let mut sum = 0f64;
+                let mut sum_sq = 0f64;
+                let mut n = 0f64;
+
+                for d in -radius..=radius {
+                    let x = (cx + d as f64 * cos).round();
+                    let y = (cy + d as f64 * sin).round();
+
+                    if x < 0.0 || y < 0.0 || x >= width as f64 || y >= height as f64 {
+                        continue;
+                    }
+
+                    let value = luma(x as usize, y as usize);
+
+                    sum += value;
+                    sum_sq += value * value;
+                    n += 1.0;
+                }
+
+                if n == 0.0 {
+                    0.0
+                } else {
+                    sum_sq / n - (sum / n).powi(2)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let max = variances.iter().cloned().fold(0f64, f64::max);
+
+        let features = variances
+            .iter()
+            .map(|v| {
+                if max > 0.0 {
+                    (v / max * 255.0).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+
+        Self { features }
+    }
+
+    /// Peak of the normalized cross-correlation over every cyclic shift of
+    /// the features, `1.0` for identical or rotated copies, lower values for
+    /// different images.
+    ///
+    /// Hashes with a different number of projections are not comparable,
+    /// their correlation is `0.0`.
+    pub fn peak_cross_correlation(&self, other: &Self) -> f64 {
+        let n = self.features.len();
+
+        if n == 0 || n != other.features.len() {
+            return 0.0;
+        }
+
+        let mean = |f: &[u8]| f.iter().map(|&v| v as f64).sum::<f64>() / n as f64;
+
+        let a_mean = mean(&self.features);
+        let b_mean = mean(&other.features);
+
+        let a = self.features.iter().map(|&v| v as f64 - a_mean);
+        let b = other.features.iter().map(|&v| v as f64 - b_mean);
+
+        let a_norm = a.clone().map(|v| v * v).sum::<f64>().sqrt();
+        let b_norm = b.clone().map(|v| v * v).sum::<f64>().sqrt();
+
+        if a_norm == 0.0 || b_norm == 0.0 {
+            // NOTE: flat features only correlate with flat features
+            return if a_norm == b_norm { 1.0 } else { 0.0 };
+        }
+
+        let a = a.collect::<Vec<_>>();
+        let b = b.collect::<Vec<_>>();
+
+        (0..n)
+            .map(|shift| (0..n).map(|i| a[i] * b[(i + shift) % n]).sum::<f64>() / (a_norm * b_norm))
+            .fold(f64::MIN, f64::max)
+    }
+}
+
+impl fmt::Display for RadialHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for feature in &self.features {
+            write!(f, "{:02x}", feature)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl str::FromStr for RadialHash {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() % 2 == 1 {
+            return Err(ParseHashError::InvalidLength);
+        }
+
+        let features = (0..s.len())
+            .step_by(2)
+            .map(|i| {
+                s.get(i..i + 2)
+                    .ok_or(ParseHashError::InvalidLength)
+                    .and_then(|digits| {
+                        u8::from_str_radix(digits, 16).map_err(ParseHashError::InvalidDigit)
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { features })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RadialHash;
+    use image::ImageReader;
+
+    #[test]
+    fn rotated_90() {
+        let image = ImageReader::open(".test/grad.ffff.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let rotated = image.rotate90();
+
+        let hash = RadialHash::new(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            image.color().channel_count(),
+        );
+
+        let rotated_hash = RadialHash::new(
+            rotated.as_bytes(),
+            rotated.width(),
+            rotated.height(),
+            rotated.color().channel_count(),
+        );
+
+        assert!(hash.peak_cross_correlation(&rotated_hash) > 0.95);
+
+        let radial = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let radial_hash = RadialHash::new(
+            radial.as_bytes(),
+            radial.width(),
+            radial.height(),
+            radial.color().channel_count(),
+        );
+
+        assert!(hash.peak_cross_correlation(&radial_hash) < 0.9);
+    }
+
+    #[test]
+    fn display_from_str() {
+        let hash = RadialHash {
+            features: vec![0x00, 0x7f, 0xff],
+        };
+
+        assert_eq!(hash.to_string(), "007fff");
+        assert_eq!("007fff".parse::<RadialHash>(), Ok(hash));
+        assert!("07fff".parse::<RadialHash>().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid image dimensions, expected 7200 got 0")]
+    fn no_channels() {
+        RadialHash::new(&[], 90, 80, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid image dimensions, expected 18446744073709551615 got 3")]
+    fn overflowing_dimensions() {
+        RadialHash::new(&[0; 3], u32::MAX, u32::MAX, 255);
+    }
+}
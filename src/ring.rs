@@ -0,0 +1,100 @@
+//! Sliding window of the most recent hashes, e.g. of video frames.
+use crate::Dhash;
+use std::collections::VecDeque;
+
+/// Bounded buffer of recent hashes, comparisons are `O(capacity)` at worst.
+#[derive(Debug, Clone)]
+pub struct DhashRing {
+    capacity: usize,
+    hashes: VecDeque<Dhash>,
+}
+
+impl DhashRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            hashes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Appends `hash`, dropping the oldest one when the ring is full.
+    pub fn push(&mut self, hash: Dhash) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.hashes.len() == self.capacity {
+            self.hashes.pop_front();
+        }
+
+        self.hashes.push_back(hash);
+    }
+
+    /// Whether any hash in the ring is within `threshold` hamming distance.
+    pub fn is_duplicate(&self, hash: Dhash, threshold: u32) -> bool {
+        self.hashes
+            .iter()
+            .any(|other| other.hamming_distance(&hash) <= threshold)
+    }
+
+    /// Ring index (`0` is the oldest), hash and distance of the closest
+    /// match, ties go to the most recent hash.
+    pub fn find_nearest(&self, hash: Dhash) -> Option<(usize, Dhash, u32)> {
+        self.hashes
+            .iter()
+            .enumerate()
+            .map(|(i, other)| (i, *other, other.hamming_distance(&hash)))
+            .rev()
+            .min_by_key(|&(_, _, distance)| distance)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.hashes.clear();
+    }
+
+    /// Hashes from the oldest to the most recent.
+    pub fn iter(&self) -> impl Iterator<Item = &Dhash> {
+        self.hashes.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhashRing;
+    use crate::Dhash;
+
+    #[test]
+    fn push_and_find_nearest() {
+        let mut ring = DhashRing::new(3);
+
+        for hash in [0x0, 0xf, 0xff, 0xfff] {
+            ring.push(Dhash { hash });
+        }
+
+        assert_eq!(ring.len(), 3);
+        assert_eq!(ring.iter().next().unwrap().hash, 0xf);
+
+        assert!(ring.is_duplicate(Dhash { hash: 0x1f }, 1));
+        assert!(!ring.is_duplicate(Dhash { hash: 0x0 }, 3));
+
+        let (index, nearest, distance) = ring.find_nearest(Dhash { hash: 0x1ff }).unwrap();
+
+        assert_eq!(index, 1);
+        assert_eq!(nearest.hash, 0xff);
+        assert_eq!(distance, 1);
+
+        assert!(DhashRing::new(0).find_nearest(Dhash { hash: 0 }).is_none());
+    }
+}
@@ -0,0 +1,443 @@
+//! Comparison of videos fingerprinted as one hash per sampled frame,
+//! tolerant to offsets and to frames inserted or dropped.
+use crate::Dhash;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Cost of an inserted or deleted frame used by [`DhashSequence::distance`],
+/// half the distance of two unrelated hashes, so that a frame without a
+/// match on either side costs as much as a mismatched pair.
+pub const DEFAULT_GAP_COST: u32 = 16;
+
+/// Number of frames the alignment of [`DhashSequence::distance`] may drift
+/// from the diagonal, beyond the difference of the lengths.
+pub const DEFAULT_BAND: usize = 8;
+
+/// Hashes of consecutive frames, e.g. one per second of a video.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct DhashSequence(pub Vec<Dhash>);
+
+/// Costs and band of the alignment of [`DhashSequence::distance_with`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceAlignment {
+    /// Cost of a frame of the other sequence missing from this one
+    pub insertion_cost: u32,
+    /// Cost of a frame of this sequence missing from the other one
+    pub deletion_cost: u32,
+    /// Number of frames the alignment may drift from the diagonal, beyond
+    /// the difference of the lengths
+    pub band: usize,
+}
+
+impl Default for SequenceAlignment {
+    fn default() -> Self {
+        Self {
+            insertion_cost: DEFAULT_GAP_COST,
+            deletion_cost: DEFAULT_GAP_COST,
+            band: DEFAULT_BAND,
+        }
+    }
+}
+
+/// Cheapest alignment of two sequences, matched frames cost their hamming
+/// distance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SequenceDistance {
+    pub cost: u32,
+    /// Pairs of frames aligned with each other
+    pub matched: usize,
+    /// Frames of the other sequence missing from this one
+    pub insertions: usize,
+    /// Frames of this sequence missing from the other one
+    pub deletions: usize,
+}
+
+impl SequenceDistance {
+    /// `cost` per step of the alignment, in `[0, 64]` as long as the gap
+    /// costs are, 0 for two empty sequences.
+    pub fn mean_cost(&self) -> f64 {
+        let steps = self.matched + self.insertions + self.deletions;
+
+        match steps {
+            0 => 0.0,
+            _ => self.cost as f64 / steps as f64,
+        }
+    }
+}
+
+const UNREACHABLE: SequenceDistance = SequenceDistance {
+    cost: u32::MAX,
+    matched: 0,
+    insertions: 0,
+    deletions: 0,
+};
+
+impl DhashSequence {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Edit distance to `other` with the [`SequenceAlignment::default`]
+    /// costs and band.
+    pub fn distance(&self, other: &Self) -> SequenceDistance {
+        self.distance_with(other, &SequenceAlignment::default())
+    }
+
+    /// Edit distance to `other`, a substitution costing the hamming distance
+    /// of the two frames, computed by dynamic programming over a band around
+    /// the diagonal, in `O(len * band)`.
+    ///
+    /// Alignments drifting farther than the band are not considered, the
+    /// band is always wide enough to reach the end of both sequences.
+    pub fn distance_with(&self, other: &Self, alignment: &SequenceAlignment) -> SequenceDistance {
+        let (a, b) = (&self.0, &other.0);
+        let width = alignment.band + a.len().abs_diff(b.len());
+
+        let cell = |row: &[SequenceDistance], i: usize, j: usize| match i.abs_diff(j) <= width {
+            true => row[j],
+            false => UNREACHABLE,
+        };
+
+        let step = |from: SequenceDistance, cost: u32| SequenceDistance {
+            cost: from.cost.saturating_add(cost),
+            ..from
+        };
+
+        let mut previous = vec![UNREACHABLE; b.len() + 1];
+        let mut current = vec![UNREACHABLE; b.len() + 1];
+
+        for (j, cell) in previous.iter_mut().enumerate().take(width + 1) {
+            *cell = SequenceDistance {
+                cost: alignment.insertion_cost.saturating_mul(j as u32),
+                insertions: j,
+                ..Default::default()
+            };
+        }
+
+        for i in 1..=a.len() {
+            for j in i.saturating_sub(width)..=(i + width).min(b.len()) {
+                let mut best = cell(&previous, i - 1, j);
+                best = SequenceDistance {
+                    deletions: best.deletions + 1,
+                    ..step(best, alignment.deletion_cost)
+                };
+
+                if j > 0 {
+                    let diagonal = cell(&previous, i - 1, j - 1);
+                    let diagonal = SequenceDistance {
+                        matched: diagonal.matched + 1,
+                        ..step(diagonal, a[i - 1].hamming_distance(&b[j - 1]))
+                    };
+
+                    let left = cell(&current, i, j - 1);
+                    let left = SequenceDistance {
+                        insertions: left.insertions + 1,
+                        ..step(left, alignment.insertion_cost)
+                    };
+
+                    // NOTE: on ties matching frames is preferred to gaps
+                    for candidate in [left, diagonal] {
+                        if candidate.cost <= best.cost {
+                            best = candidate;
+                        }
+                    }
+                }
+
+                current[j] = best;
+            }
+
+            std::mem::swap(&mut previous, &mut current);
+        }
+
+        previous[b.len()]
+    }
+
+    /// Shift of at most `max_offset` frames best aligning the two
+    /// sequences, frame `i` of `other` matching frame `i + offset` of this
+    /// one, with the mean hamming distance of the overlapping frames.
+    ///
+    /// Only shifts overlapping at least half of the shorter sequence are
+    /// considered, ties go to the smallest shift, `None` if either sequence
+    /// is empty.
+    pub fn best_offset(&self, other: &Self, max_offset: usize) -> Option<(isize, f64)> {
+        let (a, b) = (&self.0, &other.0);
+        let min_overlap = (a.len().min(b.len()) / 2).max(1);
+
+        let offsets = (0..=max_offset as isize).flat_map(|offset| [offset, -offset]);
+
+        offsets
+            .filter_map(|offset| {
+                let (skip_a, skip_b) = match offset {
+                    0.. => (offset as usize, 0),
+                    _ => (0, offset.unsigned_abs()),
+                };
+
+                let pairs = a.iter().skip(skip_a).zip(b.iter().skip(skip_b));
+                let overlap = pairs.len();
+
+                if overlap < min_overlap {
+                    return None;
+                }
+
+                let total = pairs.map(|(a, b)| a.hamming_distance(b)).sum::<u32>();
+
+                Some((offset, total as f64 / overlap as f64))
+            })
+            .fold(None, |best: Option<(isize, f64)>, candidate| match best {
+                Some(best) if best.1 <= candidate.1 => Some(best),
+                _ => Some(candidate),
+            })
+    }
+}
+
+impl From<Vec<Dhash>> for DhashSequence {
+    fn from(hashes: Vec<Dhash>) -> Self {
+        Self(hashes)
+    }
+}
+
+/// Hashes of the keyframes of a video with their timestamps, sorted by
+/// timestamp.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct VideoFingerprint {
+    keyframes: Vec<(Duration, Dhash)>,
+}
+
+/// Window of a [`VideoFingerprint`] matching a clip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipMatch {
+    /// Timestamp of the keyframe the first keyframe of the clip matches
+    pub start: Duration,
+    /// `start` plus the duration of the clip
+    pub end: Duration,
+    /// Mean hamming distance of the keyframes of the clip to the keyframes
+    /// of the window, lower is closer
+    pub score: f64,
+}
+
+impl VideoFingerprint {
+    pub fn new(mut keyframes: Vec<(Duration, Dhash)>) -> Self {
+        keyframes.sort_by_key(|&(timestamp, _)| timestamp);
+
+        Self { keyframes }
+    }
+
+    pub fn keyframes(&self) -> &[(Duration, Dhash)] {
+        &self.keyframes
+    }
+
+    pub fn len(&self) -> usize {
+        self.keyframes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// Closest window to `clip` whose score is at most `max_avg_distance`.
+    ///
+    /// The first keyframe of the clip is aligned with each keyframe of the
+    /// video in turn, every other keyframe of the clip is compared with the
+    /// keyframe of the video closest to its timestamp relative to the start
+    /// of the clip, so the two need not be sampled at the same rate. A
+    /// window is abandoned as soon as its distance exceeds the best one so
+    /// far, windows running past the end of the video are not considered.
+    pub fn find_clip(&self, clip: &VideoFingerprint, max_avg_distance: f64) -> Option<ClipMatch> {
+        let (&(clip_start, _), &(clip_end, _)) = (clip.keyframes.first()?, clip.keyframes.last()?);
+        let &(video_end, _) = self.keyframes.last()?;
+
+        let duration = clip_end - clip_start;
+        // NOTE: the clip may end between two keyframes of the video
+        let slack = duration / clip.keyframes.len() as u32;
+
+        let mut limit = (max_avg_distance * clip.keyframes.len() as f64).floor() as u64;
+        let mut best = None;
+
+        for &(start, _) in &self.keyframes {
+            if start + duration > video_end + slack {
+                break;
+            }
+
+            let mut total = 0u64;
+
+            for &(timestamp, hash) in &clip.keyframes {
+                total += self
+                    .nearest(start + (timestamp - clip_start))
+                    .hamming_distance(&hash) as u64;
+
+                if total > limit {
+                    break;
+                }
+            }
+
+            if total <= limit && best.is_none_or(|(_, best)| total < best) {
+                limit = total;
+                best = Some((start, total));
+            }
+        }
+
+        best.map(|(start, total)| ClipMatch {
+            start,
+            end: start + duration,
+            score: total as f64 / clip.keyframes.len() as f64,
+        })
+    }
+
+    /// Hash of the keyframe closest to `timestamp`, the video must not be
+    /// empty.
+    fn nearest(&self, timestamp: Duration) -> Dhash {
+        let after = self.keyframes.partition_point(|&(t, _)| t < timestamp);
+
+        match (after.checked_sub(1), self.keyframes.get(after)) {
+            (Some(before), Some(&(t, hash))) => {
+                let (t_before, hash_before) = self.keyframes[before];
+
+                match timestamp - t_before <= t - timestamp {
+                    true => hash_before,
+                    false => hash,
+                }
+            }
+            (Some(before), None) => self.keyframes[before].1,
+            (None, Some(&(_, hash))) => hash,
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhashSequence, SequenceAlignment, VideoFingerprint, DEFAULT_GAP_COST};
+    use crate::Dhash;
+    use std::time::Duration;
+
+    fn random_hashes(n: usize, mut state: u64) -> Vec<Dhash> {
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+
+                Dhash { hash: state }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shifted() {
+        let frames = random_hashes(43, 0x9e3779b97f4a7c15);
+        let clip = DhashSequence(frames[..40].to_vec());
+        let shifted = DhashSequence(frames[3..].to_vec());
+
+        assert_eq!(clip.best_offset(&shifted, 5), Some((3, 0.0)));
+        assert_eq!(shifted.best_offset(&clip, 5), Some((-3, 0.0)));
+
+        let distance = clip.distance(&shifted);
+
+        // NOTE: the first 3 frames of the clip are deleted, the last 3 of
+        // the shifted clip inserted, the rest matches exactly
+        assert_eq!(distance.matched, 37);
+        assert_eq!((distance.insertions, distance.deletions), (3, 3));
+        assert_eq!(distance.cost, 6 * DEFAULT_GAP_COST);
+        assert!(distance.mean_cost() < 3.0);
+
+        assert_eq!(clip.distance(&clip).cost, 0);
+
+        // NOTE: a band narrower than the shift forces substitutions
+        let narrow = SequenceAlignment {
+            band: 1,
+            ..Default::default()
+        };
+
+        assert!(clip.distance_with(&shifted, &narrow).cost > distance.cost);
+    }
+
+    #[test]
+    fn insertions() {
+        let frames = random_hashes(30, 0x2545f4914f6cdd1d);
+        let mut edited = frames.clone();
+
+        edited.insert(10, Dhash { hash: 0 });
+        edited.insert(20, Dhash { hash: u64::MAX });
+        edited.remove(25);
+
+        let distance = DhashSequence(frames).distance(&DhashSequence(edited));
+
+        assert_eq!((distance.insertions, distance.deletions), (2, 1));
+        assert_eq!(distance.cost, 3 * DEFAULT_GAP_COST);
+    }
+
+    #[test]
+    fn unrelated() {
+        let a = DhashSequence(random_hashes(40, 1));
+        let b = DhashSequence(random_hashes(40, 2));
+
+        assert!(a.distance(&b).mean_cost() > 20.0);
+        assert!(a.best_offset(&b, 5).unwrap().1 > 20.0);
+
+        assert_eq!(a.best_offset(&DhashSequence::default(), 5), None);
+        assert_eq!(DhashSequence::default().distance(&a).insertions, 40);
+    }
+
+    #[test]
+    fn find_clip() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        // NOTE: a 2 hours video, one keyframe per second, a new shot every
+        // 5 seconds and a few noisy bits per keyframe
+        let shots = (0..1440).map(|_| next()).collect::<Vec<_>>();
+        let mut noisy = |hash: u64| hash ^ (1 << (next() % 64)) ^ (1 << (next() % 64));
+
+        let video = VideoFingerprint::new(
+            (0..7200)
+                .map(|s| {
+                    (
+                        Duration::from_secs(s),
+                        Dhash {
+                            hash: noisy(shots[s as usize / 5]),
+                        },
+                    )
+                })
+                .collect(),
+        );
+
+        // NOTE: 20 seconds from 1h 00m 03s, sampled every 1.25 seconds
+        let clip = VideoFingerprint::new(
+            (0..16)
+                .map(|i| {
+                    let offset = Duration::from_millis(1250 * i);
+                    let shot = shots[(3603 + offset.as_secs() as usize) / 5];
+
+                    (offset, Dhash { hash: noisy(shot) })
+                })
+                .collect(),
+        );
+
+        let found = video.find_clip(&clip, 8.0).unwrap();
+
+        assert!(found.start.abs_diff(Duration::from_secs(3603)) <= Duration::from_secs(1));
+        assert_eq!(found.end - found.start, Duration::from_millis(18750));
+        assert!(found.score < 8.0);
+
+        let unrelated = VideoFingerprint::new(
+            (0..16)
+                .map(|s| (Duration::from_secs(s), Dhash { hash: next() }))
+                .collect(),
+        );
+
+        assert_eq!(video.find_clip(&unrelated, 8.0), None);
+        assert_eq!(video.find_clip(&VideoFingerprint::default(), 8.0), None);
+    }
+}
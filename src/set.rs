@@ -0,0 +1,184 @@
+//! Grouping of similar hashes into equivalence classes.
+use crate::Dhash;
+
+/// Identifier of a class of a [`DhashSet`], classes merged into another
+/// keep resolving to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClassId(pub usize);
+
+/// Hashes grouped transitively, two hashes within `threshold` hamming
+/// distance always end up in the same class, insertions are `O(len)`.
+#[derive(Debug, Clone)]
+pub struct DhashSet {
+    threshold: u32,
+    // NOTE: union-find parents, roots hold the members of their class
+    parents: Vec<usize>,
+    members: Vec<Vec<Dhash>>,
+}
+
+impl DhashSet {
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            parents: Vec::new(),
+            members: Vec::new(),
+        }
+    }
+
+    /// Adds `hash` to the class of the hashes within the threshold, merging
+    /// them if they belong to different classes, or to a new class.
+    pub fn insert(&mut self, hash: Dhash) -> ClassId {
+        let matches = (0..self.members.len())
+            .filter(|&class| {
+                self.members[class]
+                    .iter()
+                    .any(|other| other.hamming_distance(&hash) <= self.threshold)
+            })
+            .collect::<Vec<_>>();
+
+        let root = match matches.split_first() {
+            Some((&first, rest)) => rest
+                .iter()
+                .fold(first, |root, &class| self.union(root, class)),
+            None => {
+                self.parents.push(self.members.len());
+                self.members.push(Vec::new());
+                self.members.len() - 1
+            }
+        };
+
+        self.members[root].push(hash);
+
+        ClassId(root)
+    }
+
+    /// Merges the classes whose representatives, their first hash, are
+    /// within `threshold` hamming distance.
+    pub fn merge_classes(&mut self, threshold: u32) {
+        let roots = self.roots().collect::<Vec<_>>();
+
+        for (i, &a) in roots.iter().enumerate() {
+            for &b in &roots[i + 1..] {
+                let (a, b) = (self.root(a.0), self.root(b.0));
+
+                if a != b && self.members[a][0].hamming_distance(&self.members[b][0]) <= threshold {
+                    self.union(a, b);
+                }
+            }
+        }
+    }
+
+    /// The current class of `class`, which may have been merged since it
+    /// was returned by [`DhashSet::insert`].
+    pub fn find(&self, class: ClassId) -> ClassId {
+        ClassId(self.root(class.0))
+    }
+
+    /// Hashes of `class`, in insertion order within each merged class.
+    pub fn get(&self, class: ClassId) -> Option<&[Dhash]> {
+        (class.0 < self.parents.len()).then(|| self.members[self.root(class.0)].as_slice())
+    }
+
+    /// All the classes, in creation order.
+    pub fn classes(&self) -> impl Iterator<Item = (ClassId, &[Dhash])> {
+        self.roots()
+            .map(|class| (class, self.members[class.0].as_slice()))
+    }
+
+    /// Number of hashes.
+    pub fn len(&self) -> usize {
+        self.members.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    fn roots(&self) -> impl Iterator<Item = ClassId> + '_ {
+        (0..self.parents.len())
+            .filter(|&class| self.parents[class] == class)
+            .map(ClassId)
+    }
+
+    fn root(&self, mut class: usize) -> usize {
+        while self.parents[class] != class {
+            class = self.parents[class];
+        }
+
+        class
+    }
+
+    /// Merges the class `b` into `a`, or the other way around to keep the
+    /// oldest class as the root, returns the root.
+    fn union(&mut self, a: usize, b: usize) -> usize {
+        let (a, b) = (self.root(a), self.root(b));
+
+        if a == b {
+            return a;
+        }
+
+        let (root, child) = (a.min(b), a.max(b));
+        let members = std::mem::take(&mut self.members[child]);
+
+        self.parents[child] = root;
+        self.members[root].extend(members);
+
+        root
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClassId, DhashSet};
+    use crate::Dhash;
+
+    #[test]
+    fn transitivity() {
+        let mut set = DhashSet::new(4);
+
+        let a = Dhash { hash: 0 };
+        let b = Dhash { hash: 0b1111 };
+        let c = Dhash { hash: 0b1111_1111 };
+        let far = Dhash { hash: u64::MAX };
+
+        // NOTE: a and c are 8 bits apart, they are only linked through b
+        assert_eq!(set.insert(a), ClassId(0));
+        assert_eq!(set.insert(c), ClassId(1));
+        assert_eq!(set.insert(far), ClassId(2));
+        assert_eq!(set.insert(b), ClassId(0));
+
+        assert_eq!(set.find(ClassId(1)), ClassId(0));
+        assert_eq!(set.get(ClassId(1)), Some(&[a, c, b][..]));
+        assert_eq!(set.get(ClassId(3)), None);
+
+        let classes = set.classes().collect::<Vec<_>>();
+
+        assert_eq!(
+            classes,
+            vec![(ClassId(0), &[a, c, b][..]), (ClassId(2), &[far][..])]
+        );
+        assert_eq!(set.len(), 4);
+    }
+
+    #[test]
+    fn merge_classes() {
+        let mut set = DhashSet::new(2);
+
+        set.insert(Dhash { hash: 0 });
+        set.insert(Dhash { hash: 0b11110 });
+        set.insert(Dhash {
+            hash: 0b111111 << 58,
+        });
+
+        assert_eq!(set.classes().count(), 3);
+
+        set.merge_classes(4);
+
+        assert_eq!(set.classes().count(), 2);
+        assert_eq!(set.find(ClassId(1)), ClassId(0));
+
+        set.merge_classes(64);
+
+        assert_eq!(set.classes().count(), 1);
+    }
+}
@@ -0,0 +1,105 @@
+//! Signatures of hashes, so that stored hashes cannot be altered without
+//! the key.
+use crate::Dhash;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use zeroize::Zeroize;
+
+/// HMAC-SHA256 of the little-endian bytes of hashes, the key is zeroed
+/// when dropped.
+#[derive(Clone)]
+pub struct DhashSigner {
+    key: [u8; 32],
+}
+
+impl DhashSigner {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { key }
+    }
+
+    pub fn sign(&self, hash: Dhash) -> [u8; 32] {
+        self.mac(hash).finalize().into_bytes().into()
+    }
+
+    /// Whether `signature` is the signature of `hash`, compared in constant
+    /// time.
+    pub fn verify(&self, hash: Dhash, signature: &[u8; 32]) -> bool {
+        self.mac(hash).verify_slice(signature).is_ok()
+    }
+
+    fn mac(&self, hash: Dhash) -> Hmac<Sha256> {
+        // NOTE: HMAC accepts keys of any length
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(&hash.hash.to_le_bytes());
+        mac
+    }
+}
+
+impl Drop for DhashSigner {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhashSigner;
+    use crate::Dhash;
+
+    #[test]
+    fn sign() {
+        let signer = DhashSigner::new([7; 32]);
+        let hash = Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        };
+
+        let signature = signer.sign(hash);
+
+        assert!(signer.verify(hash, &signature));
+        assert!(signer.clone().verify(hash, &signature));
+
+        for bit in 0..64 {
+            let tampered = Dhash {
+                hash: hash.hash ^ 1 << bit,
+            };
+
+            assert!(!signer.verify(tampered, &signature));
+        }
+
+        assert!(!DhashSigner::new([8; 32]).verify(hash, &signature));
+    }
+
+    #[test]
+    fn rfc_4231() {
+        // NOTE: test case 2 of RFC 4231 has a 4 bytes key, zero padded to
+        // the block size as HMAC does, so is a 32 bytes zero padded key
+        let mut key = [0; 32];
+        key[..4].copy_from_slice(b"Jefe");
+
+        let mut expected = [0; 32];
+        let hex = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+
+        for (byte, digits) in expected.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+
+        let mac = |message: &[u8]| {
+            use hmac::{Hmac, Mac};
+
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(&key).unwrap();
+            mac.update(message);
+            <[u8; 32]>::from(mac.finalize().into_bytes())
+        };
+
+        assert_eq!(mac(b"what do ya want for nothing?"), expected);
+
+        let hash = Dhash {
+            hash: 0x0123456789abcdef,
+        };
+
+        assert_eq!(
+            DhashSigner::new(key).sign(hash),
+            mac(&[0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01])
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! Images the hash can be computed from, see
+//! [`Dhash::from_source`](crate::Dhash::from_source).
+use crate::{Dhash, DhashError};
+#[cfg(feature = "image")]
+use image::{ImageBuffer, Luma, LumaA, Rgb, Rgba};
+#[cfg(feature = "image")]
+use std::ops::Deref;
+
+/// Interleaved 8 bits pixels and their layout, as taken by
+/// [`Dhash::new`](crate::Dhash::new).
+pub trait PixelSource {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn channel_count(&self) -> u8;
+    /// `width * height * channel_count` bytes, row by row.
+    fn bytes(&self) -> &[u8];
+}
+
+/// Anything [`Dhash::from_source`] can hash, every [`PixelSource`] and,
+/// with the `image` feature, the 8 and 16 bits luma, luma alpha, RGB and
+/// RGBA [`ImageBuffer`](image::ImageBuffer)s.
+pub trait HashableImage {
+    fn try_dhash(&self) -> Result<Dhash, DhashError>;
+}
+
+impl<S: PixelSource + ?Sized> HashableImage for S {
+    fn try_dhash(&self) -> Result<Dhash, DhashError> {
+        Dhash::try_new(
+            self.bytes(),
+            self.width(),
+            self.height(),
+            self.channel_count(),
+        )
+    }
+}
+
+#[cfg(feature = "image")]
+macro_rules! impl_pixel_source {
+    ($($pixel:ident => $channel_count:literal),*) => {$(
+        impl<C: Deref<Target = [u8]>> PixelSource for ImageBuffer<$pixel<u8>, C> {
+            fn width(&self) -> u32 {
+                ImageBuffer::width(self)
+            }
+
+            fn height(&self) -> u32 {
+                ImageBuffer::height(self)
+            }
+
+            fn channel_count(&self) -> u8 {
+                $channel_count
+            }
+
+            fn bytes(&self) -> &[u8] {
+                self.as_raw()
+            }
+        }
+
+        // NOTE: not a PixelSource, the samples are not bytes
+        impl<C: Deref<Target = [u16]>> HashableImage for ImageBuffer<$pixel<u16>, C> {
+            fn try_dhash(&self) -> Result<Dhash, DhashError> {
+                Dhash::try_from_samples(
+                    self.as_raw(),
+                    self.width(),
+                    self.height(),
+                    $channel_count,
+                )
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "image")]
+impl_pixel_source!(Luma => 1, LumaA => 2, Rgb => 3, Rgba => 4);
+
+/// Grayscale horizontal gradient generated in memory, for tests that should
+/// not depend on image files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticGradient {
+    width: u32,
+    height: u32,
+    bytes: Vec<u8>,
+}
+
+impl SyntheticGradient {
+    /// From white on the left to black on the right, all the bits of its
+    /// hash are set.
+    pub fn left_bright(width: u32, height: u32) -> Self {
+        let mut gradient = Self::left_dark(width, height);
+
+        for byte in gradient.bytes.iter_mut() {
+            *byte = u8::MAX - *byte;
+        }
+
+        gradient
+    }
+
+    /// From black on the left to white on the right, none of the bits of its
+    /// hash are set.
+    pub fn left_dark(width: u32, height: u32) -> Self {
+        let last = width.saturating_sub(1).max(1) as usize;
+
+        let row = (0..width as usize)
+            .map(|x| (x * u8::MAX as usize / last) as u8)
+            .collect::<Vec<_>>();
+
+        Self {
+            width,
+            height,
+            bytes: row.repeat(height as usize),
+        }
+    }
+}
+
+impl PixelSource for SyntheticGradient {
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn channel_count(&self) -> u8 {
+        1
+    }
+
+    fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PixelSource, SyntheticGradient};
+    use crate::Dhash;
+    #[cfg(feature = "image")]
+    use image::ImageReader;
+
+    #[test]
+    fn gradients() {
+        let bright = SyntheticGradient::left_bright(90, 80);
+        let dark = SyntheticGradient::left_dark(90, 80);
+
+        assert_eq!(bright.bytes().len(), 90 * 80);
+        assert_eq!((bright.bytes()[0], bright.bytes()[89]), (255, 0));
+
+        assert_eq!(Dhash::from_source(&bright).hash, 0xffffffffffffffff);
+        assert_eq!(Dhash::from_source(&dark).hash, 0x0000000000000000);
+
+        // NOTE: also through a trait object
+        let source: &dyn PixelSource = &SyntheticGradient::left_bright(1920, 1080);
+
+        assert_eq!(Dhash::from_source(source).hash, 0xffffffffffffffff);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_buffers() {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image");
+
+        let rgb = Dhash::from_source(&image.to_rgb8());
+
+        assert_eq!(rgb.hash, 0xf0f0e8cccce8f0f0);
+        assert_eq!(Dhash::from_source(&image.to_rgba8()), rgb);
+        assert_eq!(Dhash::from_source(&image.to_rgb16()), rgb);
+        assert_eq!(Dhash::from_source(&image.to_rgba16()), rgb);
+
+        let luma = Dhash::from_source(&image.to_luma8());
+
+        assert_eq!(Dhash::from_source(&image.to_luma_alpha8()), luma);
+        assert_eq!(Dhash::from_source(&image.to_luma16()), luma);
+        assert_eq!(Dhash::from_source(&image.to_luma_alpha16()), luma);
+
+        assert_eq!(image.to_rgb8().channel_count(), 3);
+        assert_eq!(image.to_luma_alpha8().channel_count(), 2);
+    }
+}
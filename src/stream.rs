@@ -0,0 +1,114 @@
+//! Hashing a [`Stream`] of frames, several at a time on tokio's blocking
+//! thread pool.
+use crate::{Dhash, DhashError};
+use futures_util::{Stream, StreamExt};
+
+/// A decoded frame, its pixels and whatever identifies it, e.g. a timestamp
+/// or a frame number, returned with its hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame<M, B> {
+    pub meta: M,
+    pub bytes: B,
+    pub width: u32,
+    pub height: u32,
+    pub channel_count: u8,
+}
+
+/// Adds [`DhashStreamExt::dhash_frames`] to the streams of [`Frame`]s.
+pub trait DhashStreamExt<M, B>: Stream<Item = Frame<M, B>> + Sized
+where
+    M: Send + 'static,
+    B: AsRef<[u8]> + Send + 'static,
+{
+    /// Hashes up to `concurrency` frames at a time, at least one, with
+    /// [`Dhash::new_async_owned`], the results being yielded in the order
+    /// of the frames.
+    ///
+    /// At most `concurrency` frames are pulled ahead of the consumer, a slow
+    /// consumer slows down the reading of the frames.
+    fn dhash_frames(
+        self,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<(M, Dhash), DhashError>> {
+        self.map(|frame| async move {
+            let hash =
+                Dhash::new_async_owned(frame.bytes, frame.width, frame.height, frame.channel_count)
+                    .await?;
+
+            Ok((frame.meta, hash))
+        })
+        .buffered(concurrency.max(1))
+    }
+}
+
+impl<S, M, B> DhashStreamExt<M, B> for S
+where
+    S: Stream<Item = Frame<M, B>>,
+    M: Send + 'static,
+    B: AsRef<[u8]> + Send + 'static,
+{
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DhashStreamExt, Frame};
+    use crate::Dhash;
+    use futures_util::{stream, StreamExt};
+    use std::cell::Cell;
+
+    fn frame(i: usize) -> Frame<usize, Vec<u8>> {
+        // NOTE: every 10th frame has an invalid length
+        let len = if i % 10 == 9 { 10 } else { 90 * 80 };
+
+        Frame {
+            meta: i,
+            bytes: (0..len).map(|j| ((j % 90) * (i + 1) % 251) as u8).collect(),
+            width: 90,
+            height: 80,
+            channel_count: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn dhash_frames() {
+        let results = stream::iter((0..100).map(frame))
+            .dhash_frames(4)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(results.len(), 100);
+
+        for (i, result) in results.into_iter().enumerate() {
+            let frame = frame(i);
+
+            assert_eq!(
+                result,
+                Dhash::try_new(&frame.bytes, 90, 80, 1).map(|hash| (i, hash))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn backpressure() {
+        let pulled = Cell::new(0);
+        let consumed = Cell::new(0);
+
+        let frames = stream::iter(0..100).map(|i| {
+            pulled.set(pulled.get() + 1);
+
+            // NOTE: at most 4 frames pulled but not yet consumed
+            assert!(pulled.get() <= consumed.get() + 4);
+
+            frame(i)
+        });
+
+        let mut hashes = Box::pin(frames.dhash_frames(4));
+
+        while hashes.next().await.is_some() {
+            consumed.set(consumed.get() + 1);
+        }
+
+        assert_eq!(pulled.get(), 100);
+        assert_eq!(consumed.get(), 100);
+    }
+}
@@ -0,0 +1,186 @@
+//! Hashing `ndarray` image tensors in place, whatever their strides.
+//!
+//! The luma grid is also exposed as an array, see
+//! [`Dhash::luma_grid_as_array`](crate::Dhash::luma_grid_as_array).
+use crate::{grid::indexed_luma_grid, grid::Sample, grid::ScanOrder, DhashError};
+use ndarray::ArrayView3;
+
+/// Order of the axes of an image tensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TensorLayout {
+    /// Height, width and channels, interleaved pixels as taken by
+    /// [`Dhash::new`](crate::Dhash::new).
+    Hwc,
+    /// Channels, height and width, one plane per channel as taken by
+    /// [`Dhash::from_rgb_planar`](crate::Dhash::from_rgb_planar).
+    Chw,
+}
+
+/// Luma grid of `view`, its channels interpreted as by
+/// [`Dhash::new`](crate::Dhash::new), read through the view strides.
+pub(crate) fn tensor_luma_grid<T: Sample>(
+    view: ArrayView3<T>,
+    layout: TensorLayout,
+) -> Result<[[f64; 9]; 8], DhashError> {
+    // NOTE: HWC is read as is, CHW is read as its HWC permutation, so that
+    // the channels are always the last axis
+    let view = match layout {
+        TensorLayout::Hwc => view,
+        TensorLayout::Chw => view.permuted_axes([1, 2, 0]),
+    };
+
+    let (height, width, channel_count) = view.dim();
+
+    if channel_count == 0 {
+        return Err(DhashError::InvalidChannelCount(0));
+    }
+
+    let view = &view;
+
+    Ok(indexed_luma_grid(
+        width,
+        height,
+        channel_count,
+        T::MAX,
+        ScanOrder::for_values(T::INTEGER),
+        |i| {
+            let (y, x) = (i / width, i % width);
+            let mut channels = [0.0; 3];
+
+            for (c, channel) in channels.iter_mut().enumerate().take(channel_count) {
+                // SAFETY: the pixel index is below `width * height`, so `y`
+                // and `x` are within the first two axes of the view, and `c`
+                // is below its `channel_count`
+                *channel = unsafe { view.uget([y, x, c]) }.to_f64();
+            }
+
+            channels
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::TensorLayout;
+    use crate::{compute_grid, Dhash, DhashError};
+    use image::ImageReader;
+    use ndarray::{s, Array2, Array3};
+
+    fn radial() -> (Array3<u8>, Dhash) {
+        let image = ImageReader::open(".test/radial.jpg")
+            .expect("cannot read image")
+            .decode()
+            .expect("cannot decode image")
+            .to_rgb8();
+
+        let (width, height) = image.dimensions();
+        let hash = Dhash::new(image.as_raw(), width, height, 3);
+
+        let array =
+            Array3::from_shape_vec((height as usize, width as usize, 3), image.into_raw()).unwrap();
+
+        (array, hash)
+    }
+
+    #[test]
+    fn hwc_and_chw() {
+        let (array, expected) = radial();
+
+        assert_eq!(
+            Dhash::from_ndarray(array.view(), TensorLayout::Hwc),
+            Ok(expected)
+        );
+
+        let planar = array
+            .view()
+            .permuted_axes([2, 0, 1])
+            .as_standard_layout()
+            .to_owned();
+        let plane = |c: usize| {
+            planar
+                .slice(s![c, .., ..])
+                .iter()
+                .copied()
+                .collect::<Vec<_>>()
+        };
+        let (height, width) = (array.dim().0 as u32, array.dim().1 as u32);
+
+        assert_eq!(
+            Dhash::from_ndarray(planar.view(), TensorLayout::Chw),
+            Dhash::from_rgb_planar(&plane(0), &plane(1), &plane(2), width, height),
+        );
+
+        let samples = array.mapv(|sample| sample as f32 / 255.0);
+
+        assert_eq!(
+            Dhash::from_ndarray_f32(samples.view(), TensorLayout::Hwc),
+            Ok(Dhash::new_f32(
+                samples.as_slice().unwrap(),
+                width,
+                height,
+                3
+            )),
+        );
+
+        assert_eq!(
+            Dhash::from_ndarray(array.slice(s![.., .., ..0]), TensorLayout::Hwc),
+            Err(DhashError::InvalidChannelCount(0))
+        );
+    }
+
+    #[test]
+    fn strided() {
+        let (array, _) = radial();
+
+        // NOTE: every other column, reversed rows and channels, and the
+        // image transposed, views of the same buffer, none contiguous
+        let sliced = array.slice(s![..;-1, ..;2, ..;-1]);
+        let transposed = array.view().permuted_axes([1, 0, 2]);
+
+        for view in [sliced, transposed] {
+            assert!(!view.is_standard_layout());
+            assert!(array
+                .as_slice()
+                .unwrap()
+                .as_ptr_range()
+                .contains(&view.as_ptr()));
+
+            let copy = view.as_standard_layout().to_owned();
+            let (height, width, channel_count) = copy.dim();
+
+            assert_eq!(
+                Dhash::from_ndarray(view, TensorLayout::Hwc),
+                Dhash::try_new(
+                    copy.as_slice().unwrap(),
+                    width as u32,
+                    height as u32,
+                    channel_count as u8,
+                ),
+            );
+        }
+    }
+
+    #[test]
+    fn luma_array() {
+        let (array, expected) = radial();
+        let bytes = array.as_slice().unwrap();
+        let (height, width) = (array.dim().0 as u32, array.dim().1 as u32);
+
+        let grid = Dhash::luma_grid_as_array(bytes, width, height, 3).unwrap();
+        let cells = compute_grid(bytes, width, height, 3).unwrap();
+
+        assert_eq!(grid.dim(), (8, 9));
+        assert_eq!(grid[[2, 7]], cells.get(7, 2).unwrap());
+        assert_eq!(Dhash::from_luma_array(&grid), Ok(expected));
+
+        assert_eq!(
+            Dhash::from_luma_array(&grid.t().to_owned()),
+            Err(DhashError::InvalidShape {
+                expected: (8, 9),
+                actual: (9, 8),
+            })
+        );
+        assert!(Dhash::from_luma_array(&Array2::zeros((0, 0))).is_err());
+        assert!(Dhash::luma_grid_as_array(bytes, width, height, 4).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Arbitrary size luma reduction shared by the companion hashes.
+use crate::grid::{channels_luma, pixel_channels};
+
+/// Mean luma of `thumb_width` x `thumb_height` cells, row major, LumaA
+/// composited on white as in [`Dhash::new`](crate::Dhash::new).
+///
+/// Cells are never empty, images smaller than the thumbnail repeat pixels.
+pub(crate) fn luma_thumbnail(
+    bytes: &[u8],
+    width: usize,
+    height: usize,
+    channel_count: usize,
+    thumb_width: usize,
+    thumb_height: usize,
+) -> Vec<f64> {
+    let bounds = |i: usize, size: usize, thumb_size: usize| {
+        let from = i * size / thumb_size;
+        let to = ((i + 1) * size / thumb_size).max(from + 1);
+        from..to
+    };
+
+    let mut thumbnail = vec![0f64; thumb_width * thumb_height];
+
+    for (y, row) in thumbnail.chunks_exact_mut(thumb_width).enumerate() {
+        let rows = bounds(y, height, thumb_height);
+
+        for (x, cell) in row.iter_mut().enumerate() {
+            let cols = bounds(x, width, thumb_width);
+
+            let mut luma = 0f64;
+
+            for image_y in rows.clone() {
+                for image_x in cols.clone() {
+                    let channels = pixel_channels(bytes, channel_count, image_y * width + image_x);
+
+                    luma += channels_luma(channels, channel_count);
+                }
+            }
+
+            *cell = luma / (rows.len() * cols.len()) as f64;
+        }
+    }
+
+    thumbnail
+}
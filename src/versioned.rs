@@ -0,0 +1,145 @@
+//! Hashes tagged with the version of the algorithm that computed them, so
+//! that stored hashes stay comparable, or are known not to be, when the
+//! algorithm changes.
+use crate::{Dhash, DhashError, ParseHashError};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{fmt, str};
+
+/// Version of the algorithm behind [`Dhash`] since 2.0, the 9x8 luma grid
+/// with the 0.299, 0.587 and 0.114 weights and 2 channel images composited
+/// on white.
+///
+/// Versioned hashes were added in 2.0, hashes computed by 1.x are not
+/// version 1 for LumaA images with an alpha below 255, which 1.x hashed
+/// from the luma alone, see the changelog.
+pub const CURRENT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct VersionedDhash {
+    pub version: u8,
+    pub hash: u64,
+}
+
+impl VersionedDhash {
+    /// Tags a [`Dhash`], computed by version 1 of the algorithm.
+    pub fn v1(hash: Dhash) -> Self {
+        Self {
+            version: 1,
+            hash: hash.hash,
+        }
+    }
+
+    /// The [`Dhash`] of a version 1 hash, `None` for any other version.
+    pub fn as_dhash(&self) -> Option<Dhash> {
+        match self.version {
+            1 => Some(Dhash { hash: self.hash }),
+            _ => None,
+        }
+    }
+
+    /// Hamming distance to `other`, only meaningful between hashes of the
+    /// same version.
+    pub fn hamming_distance(&self, other: &Self) -> Result<u32, DhashError> {
+        if self.version != other.version {
+            return Err(DhashError::VersionMismatch {
+                expected: self.version,
+                actual: other.version,
+            });
+        }
+
+        Ok((self.hash ^ other.hash).count_ones())
+    }
+}
+
+impl From<Dhash> for VersionedDhash {
+    fn from(hash: Dhash) -> Self {
+        Self::v1(hash)
+    }
+}
+
+/// The version and the 16 hex digits of the hash, e.g.
+/// `v1:f0f0e8cccce8f0f0`.
+impl fmt::Display for VersionedDhash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}:{:016x}", self.version, self.hash)
+    }
+}
+
+impl str::FromStr for VersionedDhash {
+    type Err = ParseHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(s) = s.strip_prefix('v') else {
+            return Err(match s.chars().next() {
+                Some(c) => ParseHashError::InvalidCharacter(c),
+                None => ParseHashError::InvalidLength,
+            });
+        };
+
+        let (version, hash) = s.split_once(':').ok_or(ParseHashError::InvalidLength)?;
+
+        if hash.len() != 16 {
+            return Err(ParseHashError::InvalidLength);
+        }
+
+        Ok(Self {
+            version: version.parse().map_err(ParseHashError::InvalidDigit)?,
+            hash: u64::from_str_radix(hash, 16).map_err(ParseHashError::InvalidDigit)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{VersionedDhash, CURRENT_VERSION};
+    use crate::{Dhash, DhashError, ParseHashError};
+
+    #[test]
+    fn round_trip() {
+        let hash = VersionedDhash::v1(Dhash {
+            hash: 0xf0f0e8cccce8f0f0,
+        });
+
+        assert_eq!(hash.version, CURRENT_VERSION);
+        assert_eq!(hash.to_string(), "v1:f0f0e8cccce8f0f0");
+        assert_eq!("v1:f0f0e8cccce8f0f0".parse(), Ok(hash));
+        assert_eq!(hash.as_dhash().unwrap().hash, 0xf0f0e8cccce8f0f0);
+
+        assert_eq!(
+            "f0f0e8cccce8f0f0".parse::<VersionedDhash>(),
+            Err(ParseHashError::InvalidCharacter('f'))
+        );
+        assert_eq!(
+            "v1:f0f0e8cc".parse::<VersionedDhash>(),
+            Err(ParseHashError::InvalidLength)
+        );
+        assert!(matches!(
+            "v256:f0f0e8cccce8f0f0".parse::<VersionedDhash>(),
+            Err(ParseHashError::InvalidDigit(_))
+        ));
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let v1 = VersionedDhash::v1(Dhash { hash: 0xff });
+        let v2 = VersionedDhash {
+            version: 2,
+            hash: 0xff,
+        };
+
+        assert_eq!(
+            v1.hamming_distance(&VersionedDhash::v1(Dhash { hash: 0xf0 })),
+            Ok(4)
+        );
+        assert_eq!(
+            v1.hamming_distance(&v2),
+            Err(DhashError::VersionMismatch {
+                expected: 1,
+                actual: 2
+            })
+        );
+        assert_eq!(v2.as_dhash(), None);
+    }
+}
@@ -0,0 +1,232 @@
+//! Frame to frame comparison for video streams.
+use crate::Dhash;
+
+/// Distance above which [`frame_diff`] reports a scene change.
+pub const DEFAULT_SCENE_CHANGE_THRESHOLD: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameDiff {
+    pub hamming: u32,
+    pub is_scene_change: bool,
+    /// `hamming` over the 64 bits of the hash, in `[0, 1]`
+    pub change_intensity: f64,
+}
+
+impl FrameDiff {
+    pub fn new(prev: Dhash, curr: Dhash, scene_change_threshold: u32) -> Self {
+        let hamming = prev.hamming_distance(&curr);
+
+        Self {
+            hamming,
+            is_scene_change: hamming > scene_change_threshold,
+            change_intensity: hamming as f64 / 64.0,
+        }
+    }
+}
+
+/// Compares two consecutive frames with [`DEFAULT_SCENE_CHANGE_THRESHOLD`].
+pub fn frame_diff(prev: Dhash, curr: Dhash) -> FrameDiff {
+    FrameDiff::new(prev, curr, DEFAULT_SCENE_CHANGE_THRESHOLD)
+}
+
+/// Compares each frame of a stream with the previous one.
+#[derive(Debug, Clone)]
+pub struct VideoHasher {
+    scene_change_threshold: u32,
+    prev: Option<Dhash>,
+}
+
+impl VideoHasher {
+    pub fn new() -> Self {
+        Self::with_threshold(DEFAULT_SCENE_CHANGE_THRESHOLD)
+    }
+
+    pub fn with_threshold(scene_change_threshold: u32) -> Self {
+        Self {
+            scene_change_threshold,
+            prev: None,
+        }
+    }
+
+    /// Diff against the previous frame, the first frame is compared with
+    /// itself and never reports a change.
+    pub fn push_frame(&mut self, hash: Dhash) -> FrameDiff {
+        let prev = self.prev.replace(hash).unwrap_or(hash);
+
+        FrameDiff::new(prev, hash, self.scene_change_threshold)
+    }
+
+    /// Forgets the previous frame, e.g. after seeking.
+    pub fn reset(&mut self) {
+        self.prev = None;
+    }
+}
+
+impl Default for VideoHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Detects cuts in a stream of frames, with hysteresis.
+///
+/// A cut is reported when a frame is more than `threshold` bits away from
+/// the previous one, at least `min_scene_len` frames after the previous
+/// cut. The detector then disarms until two consecutive frames are within
+/// `threshold / 2` bits, so that a fade or a flash, changing a lot from
+/// every frame to the next, reports a single cut rather than one per frame.
+#[derive(Debug, Clone)]
+pub struct SceneDetector {
+    threshold: u32,
+    min_scene_len: usize,
+    prev: Option<Dhash>,
+    /// Index of the next frame
+    index: usize,
+    scene_start: usize,
+    armed: bool,
+}
+
+impl SceneDetector {
+    pub fn new(threshold: u32, min_scene_len: usize) -> Self {
+        Self {
+            threshold,
+            min_scene_len,
+            prev: None,
+            index: 0,
+            scene_start: 0,
+            armed: true,
+        }
+    }
+
+    /// Index of the frame, counted from the first one pushed, if it starts
+    /// a new scene.
+    pub fn push(&mut self, hash: Dhash) -> Option<usize> {
+        let index = self.index;
+        self.index += 1;
+
+        let distance = self.prev.replace(hash)?.hamming_distance(&hash);
+
+        if distance <= self.threshold / 2 {
+            self.armed = true;
+        }
+
+        if distance > self.threshold && self.armed && index - self.scene_start >= self.min_scene_len
+        {
+            self.scene_start = index;
+            self.armed = false;
+
+            return Some(index);
+        }
+
+        None
+    }
+}
+
+/// Indices of the frames starting a new scene, as reported by a
+/// [`SceneDetector`].
+pub fn scene_changes(
+    hashes: impl IntoIterator<Item = Dhash>,
+    threshold: u32,
+    min_scene_len: usize,
+) -> Vec<usize> {
+    let mut detector = SceneDetector::new(threshold, min_scene_len);
+
+    hashes
+        .into_iter()
+        .filter_map(|hash| detector.push(hash))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        frame_diff, scene_changes, SceneDetector, VideoHasher, DEFAULT_SCENE_CHANGE_THRESHOLD,
+    };
+    use crate::Dhash;
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Flips `n` distinct random bits of `hash`
+        fn flip(&mut self, hash: u64, n: u32) -> u64 {
+            let mut mask = 0u64;
+
+            while mask.count_ones() < n {
+                mask |= 1 << (self.next() % 64);
+            }
+
+            hash ^ mask
+        }
+    }
+
+    #[test]
+    fn push_frame() {
+        let diff = frame_diff(Dhash { hash: 0 }, Dhash { hash: u64::MAX });
+
+        assert_eq!(diff.hamming, 64);
+        assert!(diff.is_scene_change);
+        assert_eq!(diff.change_intensity, 1.0);
+
+        let mut video = VideoHasher::with_threshold(4);
+
+        let diffs = [0x0, 0xf, 0x3ff]
+            .map(|hash| video.push_frame(Dhash { hash }))
+            .map(|diff| (diff.hamming, diff.is_scene_change));
+
+        assert_eq!(diffs, [(0, false), (4, false), (6, true)]);
+    }
+
+    #[test]
+    fn three_scenes() {
+        let mut rng = XorShift(0x9e3779b97f4a7c15);
+        let scenes = [rng.next(), rng.next(), rng.next()];
+
+        // NOTE: 20 frames per scene, each a few bits away from the scene
+        let hashes = scenes
+            .iter()
+            .flat_map(|&scene| vec![scene; 20])
+            .map(|scene| Dhash {
+                hash: rng.flip(scene, 3),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            scene_changes(hashes, DEFAULT_SCENE_CHANGE_THRESHOLD, 5),
+            [20, 40]
+        );
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut rng = XorShift(0x2545f4914f6cdd1d);
+        let mut hash = rng.next();
+        let mut hashes = vec![hash; 10];
+
+        // NOTE: a fade, every frame far from the previous one
+        for _ in 0..6 {
+            hash = rng.flip(hash, 25);
+            hashes.push(hash);
+        }
+
+        hashes.extend([hash; 10]);
+
+        let hashes = hashes.into_iter().map(|hash| Dhash { hash });
+
+        assert_eq!(scene_changes(hashes.clone(), 20, 0), [10]);
+
+        // NOTE: the flash at frame 2 is closer than the minimum scene length
+        // to the start of the stream, and ignored
+        let mut detector = SceneDetector::new(20, 5);
+        let cuts =
+            [0, 0, u64::MAX, 0, 0, 0, 0, 0, u64::MAX].map(|hash| detector.push(Dhash { hash }));
+
+        assert_eq!(cuts.into_iter().flatten().collect::<Vec<_>>(), [8]);
+    }
+}
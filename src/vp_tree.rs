@@ -0,0 +1,202 @@
+//! Vantage-point tree for range queries in hamming space.
+use crate::Dhash;
+
+/// Hashes tried as the vantage point of each node
+const CANDIDATES: usize = 5;
+
+/// Hashes the spread of the distances to a candidate is measured on
+const SAMPLES: usize = 32;
+
+/// Hashes partitioned around vantage points, each node splitting the
+/// hashes below it at the median of their distances to its vantage point.
+///
+/// Queries skip the subtrees the triangle inequality rules out, the
+/// smaller the radius the more of the tree is skipped. On uniformly random
+/// hashes most of the tree is visited from a radius of about 8, where a
+/// linear scan such as [`DhashBank::query`](crate::DhashBank::query) is
+/// faster.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhashVPTree {
+    /// Preorder layout, the node at `i` is followed by the nodes within its
+    /// radius up to `end_inside`, then by the others up to the end of its
+    /// parent range.
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Node {
+    hash: Dhash,
+    /// Position of the hash in the slice the tree was built from
+    index: usize,
+    /// Median distance from the vantage point to the hashes below it
+    radius: u32,
+    end_inside: usize,
+}
+
+impl DhashVPTree {
+    pub fn new(hashes: &[Dhash]) -> Self {
+        let mut nodes = hashes
+            .iter()
+            .enumerate()
+            .map(|(index, &hash)| Node {
+                hash,
+                index,
+                radius: 0,
+                end_inside: 0,
+            })
+            .collect::<Vec<_>>();
+
+        let mut ranges = vec![(0, nodes.len())];
+
+        while let Some((from, to)) = ranges.pop() {
+            if from >= to {
+                continue;
+            }
+
+            let vantage = from + vantage_point(&nodes[from..to]);
+            nodes.swap(from, vantage);
+
+            let hash = nodes[from].hash;
+            let rest = &mut nodes[from + 1..to];
+
+            if rest.is_empty() {
+                nodes[from].end_inside = to;
+                continue;
+            }
+
+            let median = rest.len() / 2;
+            rest.select_nth_unstable_by_key(median, |node| node.hash.hamming_distance(&hash));
+
+            // NOTE: the hashes up to the median are within the radius, the
+            // ones after it are at least as far, ties can be on both sides
+            let radius = rest[median].hash.hamming_distance(&hash);
+            let end_inside = from + 1 + median + 1;
+
+            nodes[from].radius = radius;
+            nodes[from].end_inside = end_inside;
+
+            ranges.push((from + 1, end_inside));
+            ranges.push((end_inside, to));
+        }
+
+        Self { nodes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Indexes, in the slice the tree was built from, of the hashes within
+    /// `radius` hamming distance of `query`, in no particular order.
+    pub fn query_range(&self, query: Dhash, radius: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut ranges = vec![(0, self.nodes.len())];
+
+        while let Some((from, to)) = ranges.pop() {
+            if from >= to {
+                continue;
+            }
+
+            let node = &self.nodes[from];
+            let distance = node.hash.hamming_distance(&query);
+
+            if distance <= radius {
+                found.push(node.index);
+            }
+
+            if distance <= node.radius.saturating_add(radius) {
+                ranges.push((from + 1, node.end_inside));
+            }
+
+            if distance.saturating_add(radius) >= node.radius {
+                ranges.push((node.end_inside, to));
+            }
+        }
+
+        found
+    }
+}
+
+/// Position of the candidate whose distances to a sample of `nodes` have
+/// the largest variance, spread out distances split more evenly.
+fn vantage_point(nodes: &[Node]) -> usize {
+    if nodes.len() <= 2 {
+        return 0;
+    }
+
+    let samples = (0..SAMPLES.min(nodes.len()))
+        .map(|i| nodes[i * nodes.len() / SAMPLES.min(nodes.len())].hash)
+        .collect::<Vec<_>>();
+
+    (0..CANDIDATES.min(nodes.len()))
+        .map(|i| i * nodes.len() / CANDIDATES.min(nodes.len()))
+        .max_by_key(|&candidate| {
+            let (sum, squares) = samples.iter().fold((0u64, 0u64), |(sum, squares), sample| {
+                let distance = nodes[candidate].hash.hamming_distance(sample) as u64;
+                (sum + distance, squares + distance * distance)
+            });
+
+            // NOTE: variance times the squared sample count, in integers
+            samples.len() as u64 * squares - sum * sum
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::DhashVPTree;
+    use crate::Dhash;
+
+    #[test]
+    fn query_range() {
+        let mut state = 0x9e3779b97f4a7c15u64;
+        let mut hashes = Vec::new();
+
+        for i in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+
+            // NOTE: some near duplicates and exact duplicates, so that
+            // distances tie
+            let hash = match i % 4 {
+                1 => hashes[i - 1] ^ (state & 0x0101_0101),
+                2 => hashes[i - 2],
+                _ => state,
+            };
+
+            hashes.push(hash);
+        }
+
+        let hashes = hashes
+            .into_iter()
+            .map(|hash| Dhash { hash })
+            .collect::<Vec<_>>();
+        let tree = DhashVPTree::new(&hashes);
+
+        assert_eq!(tree.len(), 2000);
+
+        for query in [hashes[0], hashes[1001], Dhash { hash: 0 }] {
+            for radius in [0, 2, 4, 10, 20, 32, 64] {
+                let mut found = tree.query_range(query, radius);
+                found.sort_unstable();
+
+                let expected = (0..hashes.len())
+                    .filter(|&i| hashes[i].hamming_distance(&query) <= radius)
+                    .collect::<Vec<_>>();
+
+                assert_eq!(found, expected, "radius {}", radius);
+            }
+        }
+
+        assert!(DhashVPTree::new(&[]).query_range(hashes[0], 64).is_empty());
+        assert_eq!(
+            DhashVPTree::new(&hashes[..1]).query_range(hashes[0], 0),
+            vec![0]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Hashing every image of a directory tree.
+use crate::Dhash;
+use image::{ImageError, ImageFormat};
+use rayon::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Hashes, in parallel, every image found in `path` and its
+/// subdirectories, sorted by path.
+///
+/// Files are picked by extension, other files are skipped, decoding errors
+/// are reported per file rather than aborting the walk.
+pub fn hash_directory(path: &Path) -> Vec<(PathBuf, Result<Dhash, ImageError>)> {
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+
+    walk(path, &mut files, &mut errors);
+
+    let mut results = files
+        .into_par_iter()
+        .map(|path| {
+            let hash = hash_file(&path);
+            (path, hash)
+        })
+        .chain(errors.into_par_iter())
+        .collect::<Vec<_>>();
+
+    results.sort_by(|a, b| a.0.cmp(&b.0));
+
+    results
+}
+
+fn walk(
+    path: &Path,
+    files: &mut Vec<PathBuf>,
+    errors: &mut Vec<(PathBuf, Result<Dhash, ImageError>)>,
+) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(error) => return errors.push((path.to_path_buf(), Err(ImageError::IoError(error)))),
+    };
+
+    for entry in entries {
+        let path = match entry.and_then(|entry| entry.file_type().map(|kind| (entry, kind))) {
+            Ok((entry, kind)) if kind.is_dir() => {
+                walk(&entry.path(), files, errors);
+                continue;
+            }
+            Ok((entry, _)) => entry.path(),
+            Err(error) => {
+                errors.push((path.to_path_buf(), Err(ImageError::IoError(error))));
+                continue;
+            }
+        };
+
+        if ImageFormat::from_path(&path).is_ok() {
+            files.push(path);
+        }
+    }
+}
+
+fn hash_file(path: &Path) -> Result<Dhash, ImageError> {
+    Ok(Dhash::from_image(&image::open(path)?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::hash_directory;
+    use std::{env, fs};
+
+    #[test]
+    fn directory() {
+        let dir = env::temp_dir().join(format!("fast-dhash-walk-{}", std::process::id()));
+        let nested = dir.join("nested");
+
+        fs::create_dir_all(&nested).unwrap();
+        fs::copy(".test/radial.jpg", dir.join("radial.jpg")).unwrap();
+        fs::copy(".test/grad.ffff.jpg", nested.join("grad.ffff.jpg")).unwrap();
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        fs::write(dir.join("broken.png"), "not a png").unwrap();
+
+        let results = hash_directory(&dir);
+
+        fs::remove_dir_all(&dir).unwrap();
+
+        let names = results
+            .iter()
+            .map(|(path, _)| path.strip_prefix(&dir).unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["broken.png", "nested/grad.ffff.jpg", "radial.jpg"]);
+
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].1.as_ref().unwrap().hash, 0xffffffffffffffff);
+        assert_eq!(results[2].1.as_ref().unwrap().hash, 0xf0f0e8cccce8f0f0);
+    }
+}